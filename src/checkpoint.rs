@@ -0,0 +1,227 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Newest `created_at` seen per relay and per sync filter, so subscriptions resume
+//! where the previous session stopped
+//!
+//! Relays don't send stored events in order, so a checkpoint only moves once the
+//! subscription reached its EOSE: a session stopped before would skip the older events
+//! not received yet.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::nips::raw::now;
+use crate::util::dir;
+use crate::util::json_store::{self, Debounce};
+
+/// Subtracted from the checkpoints, to catch events relays received late
+const SAFETY_WINDOW: u64 = 10 * 60;
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// relay -> filter name -> newest `created_at`
+    checkpoints: HashMap<String, HashMap<String, u64>>,
+    /// relay -> filter name -> hash of the authors the checkpoint was reached with
+    authors: HashMap<String, HashMap<String, String>>,
+    /// subscription id -> (relay, filter name)
+    subscriptions: HashMap<String, (String, String)>,
+    /// subscription id -> newest `created_at` received before its EOSE
+    pending: HashMap<String, u64>,
+    /// Subscriptions past their EOSE
+    stored: HashSet<String>,
+    save: Debounce,
+}
+
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    public_key: XOnlyPublicKey,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Persisted {
+    checkpoints: HashMap<String, HashMap<String, u64>>,
+    #[serde(default)]
+    authors: HashMap<String, HashMap<String, String>>,
+}
+
+impl Checkpoints {
+    pub fn load(public_key: XOnlyPublicKey) -> Self {
+        let (checkpoints, authors) = json_store::load::<Persisted>(path(&public_key))
+            .map(|p| (p.checkpoints, p.authors))
+            .unwrap_or_default();
+        Self {
+            public_key,
+            inner: Arc::new(Mutex::new(Inner {
+                checkpoints,
+                authors,
+                ..Inner::default()
+            })),
+        }
+    }
+
+    /// `since` for `filter` on `relay`: the checkpoint minus the safety window
+    pub fn since(&self, relay: &Url, filter: &str) -> Option<u64> {
+        let inner = self.inner.lock().ok()?;
+        inner
+            .checkpoints
+            .get(relay.as_str())
+            .and_then(|filters| filters.get(filter))
+            .map(|created_at| created_at.saturating_sub(SAFETY_WINDOW))
+    }
+
+    /// Drop the checkpoint of `filter` on `relay` if it was reached with other authors:
+    /// the new ones have older events to read
+    pub fn set_authors(&self, relay: &Url, filter: &str, authors: &[XOnlyPublicKey]) {
+        let mut authors: Vec<String> = authors.iter().map(|a| a.to_string()).collect();
+        authors.sort();
+        let hash = format!("{:x}", Sha256::digest(authors.join(",").as_bytes()));
+
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        let known = inner
+            .authors
+            .entry(relay.to_string())
+            .or_default()
+            .insert(filter.to_string(), hash.clone());
+        if known.as_ref() != Some(&hash) {
+            if let Some(filters) = inner.checkpoints.get_mut(relay.as_str()) {
+                if filters.remove(filter).is_some() {
+                    log::debug!("Authors of {} changed, resetting its checkpoint", filter);
+                }
+            }
+            inner.save.touch();
+        }
+    }
+
+    /// Id of the subscription of `filter` on `relay`, remembered to attribute the events.
+    /// The same pair always gets the same id, so a new REQ replaces the old one.
+    pub fn subscription_id(&self, relay: &Url, filter: &str) -> String {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return format!("sync-{}", filter),
+        };
+        let target = (relay.to_string(), filter.to_string());
+        let id = match inner.subscriptions.iter().find(|(_, t)| **t == target) {
+            Some((id, _)) => id.clone(),
+            None => format!("sync-{}-{}", filter, inner.subscriptions.len()),
+        };
+        // A new REQ sends the stored events again
+        inner.pending.remove(&id);
+        inner.stored.remove(&id);
+        inner.subscriptions.insert(id.clone(), target);
+        id
+    }
+
+    /// Relay a subscription was opened on
+    pub fn relay_of(&self, subscription_id: &str) -> Option<Url> {
+        let inner = self.inner.lock().ok()?;
+        let (relay, _) = inner.subscriptions.get(subscription_id)?;
+        Url::parse(relay).ok()
+    }
+
+    /// Ids of the subscriptions opened on `relay`
+    pub fn subscriptions_on(&self, relay: &Url) -> Vec<String> {
+        match self.inner.lock() {
+            Ok(inner) => inner
+                .subscriptions
+                .iter()
+                .filter(|(_, (r, _))| r == relay.as_str())
+                .map(|(id, _)| id.clone())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Move the checkpoint forward, or keep `created_at` until the EOSE of the
+    /// subscription. Timestamps in the future are clamped to now, so a wrong clock can't
+    /// make us skip events.
+    pub fn update(&self, subscription_id: &str, created_at: u64) {
+        let created_at = created_at.min(now());
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        if !inner.subscriptions.contains_key(subscription_id) {
+            return;
+        }
+        if !inner.stored.contains(subscription_id) {
+            let pending = inner
+                .pending
+                .entry(subscription_id.to_string())
+                .or_default();
+            *pending = created_at.max(*pending);
+            return;
+        }
+        Self::advance(&mut inner, subscription_id, created_at);
+        if inner.save.is_due() {
+            self.save_inner(&mut inner);
+        }
+    }
+
+    /// EOSE of a subscription: every stored event was received, so the checkpoint can
+    /// move to the newest one
+    pub fn end_of_stored(&self, subscription_id: &str) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        if !inner.subscriptions.contains_key(subscription_id) {
+            return;
+        }
+        inner.stored.insert(subscription_id.to_string());
+        if let Some(created_at) = inner.pending.remove(subscription_id) {
+            Self::advance(&mut inner, subscription_id, created_at);
+        }
+        self.save_inner(&mut inner);
+    }
+
+    fn advance(inner: &mut Inner, subscription_id: &str, created_at: u64) {
+        let (relay, filter) = match inner.subscriptions.get(subscription_id) {
+            Some(target) => target.clone(),
+            None => return,
+        };
+        let checkpoint = inner
+            .checkpoints
+            .entry(relay)
+            .or_default()
+            .entry(filter)
+            .or_default();
+        if created_at > *checkpoint {
+            *checkpoint = created_at;
+            inner.save.touch();
+        }
+    }
+
+    /// Write the checkpoints if they changed
+    pub fn save(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            self.save_inner(&mut inner);
+        }
+    }
+
+    fn save_inner(&self, inner: &mut Inner) {
+        if inner.save.take() {
+            let persisted = Persisted {
+                checkpoints: inner.checkpoints.clone(),
+                authors: inner.authors.clone(),
+            };
+            json_store::save(path(&self.public_key), &persisted, "sync checkpoints");
+        }
+    }
+}
+
+fn path(public_key: &XOnlyPublicKey) -> Option<PathBuf> {
+    dir::default_dir()
+        .ok()
+        .map(|dir| dir.join(format!("checkpoints-{}.json", public_key)))
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Command-line arguments
+
+use std::fmt;
+use std::path::PathBuf;
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+
+use crate::nips::nip19::{self, Entity};
+
+pub const USAGE: &str = "\
+Usage: nostr-desktop [OPTIONS] [URI]
+
+Arguments:
+  [URI]  nostr: URI (npub, nprofile, note, nevent or naddr) to open
+
+Options:
+      --data-dir <PATH>     Keep every file in PATH
+      --log-level <FILTER>  Log filter, e.g. info or nostr_desktop=debug
+      --account <NPUB>      Account to log in with
+  -h, --help                Print help
+  -V, --version             Print version";
+
+#[derive(Debug)]
+pub enum Error {
+    MissingValue(String),
+    UnknownOption(String),
+    InvalidAccount(String),
+    InvalidUri(String),
+    UnexpectedArgument(String),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingValue(option) => write!(f, "{} needs a value", option),
+            Self::UnknownOption(option) => write!(f, "unknown option {}", option),
+            Self::InvalidAccount(account) => write!(f, "{} is not a valid npub", account),
+            Self::InvalidUri(uri) => write!(f, "{} is not a valid nostr: URI", uri),
+            Self::UnexpectedArgument(arg) => write!(f, "unexpected argument {}", arg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    pub data_dir: Option<PathBuf>,
+    pub log_level: Option<String>,
+    pub account: Option<XOnlyPublicKey>,
+    pub uri: Option<Entity>,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Args {
+    pub fn from_env() -> Result<Self, Error> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parse the arguments, without the program name
+    pub fn parse<I>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (option, inline) = match arg.split_once('=') {
+                Some((option, value)) if arg.starts_with("--") => {
+                    (option.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = |option: &str| {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| Error::MissingValue(option.to_string()))
+            };
+            match option.as_str() {
+                "--data-dir" => parsed.data_dir = Some(PathBuf::from(value(&option)?)),
+                "--log-level" => parsed.log_level = Some(value(&option)?),
+                "--account" => {
+                    let account = value(&option)?;
+                    match nip19::parse(&account) {
+                        Some(Entity::Profile { public_key, .. }) => {
+                            parsed.account = Some(public_key)
+                        }
+                        _ => return Err(Error::InvalidAccount(account)),
+                    }
+                }
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                _ if option.starts_with('-') => return Err(Error::UnknownOption(option.clone())),
+                _ if parsed.uri.is_some() => return Err(Error::UnexpectedArgument(arg)),
+                _ => match nip19::parse(&arg) {
+                    Some(entity) => parsed.uri = Some(entity),
+                    None => return Err(Error::InvalidUri(arg)),
+                },
+            }
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,433 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Application settings, stored in `settings.toml`
+//!
+//! The keys this version doesn't know about (or no longer uses) are kept in the
+//! document the file was loaded from, and written back when saved.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::net::ProxySettings;
+use crate::notifier::NotifierSettings;
+use crate::shortcut::KeyBindings;
+use crate::stage::dashboard::relay_auth::AuthPolicy;
+use crate::stage::dashboard::relay_policy::PolicyState;
+use crate::theme::Theme;
+use crate::util::{dir, json_store};
+
+pub const MIN_FONT_SCALE: f32 = 0.75;
+pub const MAX_FONT_SCALE: f32 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Appearance {
+    pub theme: Theme,
+    pub font_scale: f32,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            font_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Media {
+    /// Download profile pictures without being asked
+    pub autoload_images: bool,
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        Self {
+            autoload_images: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feed {
+    /// Notes loaded at a time in the home feed
+    pub page_size: usize,
+    pub hide_replies: bool,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            page_size: 40,
+            hide_replies: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Relays {
+    /// Relay url -> whether to answer its NIP-42 challenges
+    pub auth: BTreeMap<String, AuthPolicy>,
+}
+
+impl Relays {
+    pub fn auth_policy(&self, url: &Url) -> AuthPolicy {
+        self.auth.get(url.as_str()).copied().unwrap_or_default()
+    }
+
+    pub fn set_auth_policy(&mut self, url: &Url, policy: AuthPolicy) {
+        self.auth.insert(url.to_string(), policy);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Explore {
+    pub pinned: Vec<String>,
+    /// Relays the global feed is read from. All the connected ones if empty.
+    pub relays: BTreeSet<String>,
+}
+
+/// Settings of a single account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Account {
+    pub relays: PolicyState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub appearance: Appearance,
+    pub media: Media,
+    pub feed: Feed,
+    pub notifications: NotifierSettings,
+    pub keys: KeyBindings,
+    pub network: ProxySettings,
+    pub relays: Relays,
+    pub explore: Explore,
+    /// Public key -> settings of the account
+    pub accounts: BTreeMap<String, Account>,
+    /// Keys of the loaded document that aren't fields of the settings
+    #[serde(skip)]
+    document: Table,
+    /// Set when the file couldn't be read nor backed up, not to overwrite it
+    #[serde(skip)]
+    read_only: bool,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = match path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => {
+                let mut config = Self::default();
+                config.import_legacy(&Table::new());
+                return config;
+            }
+        };
+        let document: Table = match content.parse() {
+            Ok(document) => document,
+            Err(e) => {
+                log::error!("Impossible to parse {}: {}", path.display(), e);
+                return Self {
+                    read_only: !backup(&path),
+                    ..Self::default()
+                };
+            }
+        };
+        let mut config = match Value::Table(document.clone()).try_into::<Self>() {
+            Ok(config) => config.with_document(document.clone()),
+            Err(e) => {
+                log::error!("Impossible to read settings: {}", e);
+                let read_only = !backup(&path);
+                let mut config = Self::from_document(document.clone());
+                config.read_only = read_only;
+                config
+            }
+        };
+        config.import_legacy(&document);
+        config
+    }
+
+    /// Settings of `document`. The keys with an invalid value keep their default, the
+    /// others are read as usual.
+    fn from_document(document: Table) -> Self {
+        read_lenient::<Self>(&document).with_document(document)
+    }
+
+    /// Clamp the values read and remember the keys of `document` that aren't settings
+    fn with_document(mut self, document: Table) -> Self {
+        self.appearance.font_scale = self
+            .appearance
+            .font_scale
+            .clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+        self.document = match Table::try_from(&self) {
+            Ok(known) => unknown(document, &known),
+            Err(_) => document,
+        };
+        self
+    }
+
+    /// Relay policies of the account `public_key`
+    pub fn relay_policies(&self, public_key: &XOnlyPublicKey) -> PolicyState {
+        match self.accounts.get(&public_key.to_string()) {
+            Some(account) => account.relays.clone(),
+            None => legacy_data(&format!("relays-{}.json", public_key)).unwrap_or_default(),
+        }
+    }
+
+    pub fn set_relay_policies(&mut self, public_key: &XOnlyPublicKey, policies: PolicyState) {
+        self.accounts
+            .entry(public_key.to_string())
+            .or_default()
+            .relays = policies;
+    }
+
+    /// Read the sections missing from `document` from the files they were kept in
+    /// before they moved into the settings file
+    fn import_legacy(&mut self, document: &Table) {
+        if !document.contains_key("notifications") {
+            if let Some(settings) = legacy_data("desktop-notifications.json") {
+                self.notifications = settings;
+            }
+        }
+        if !document.contains_key("network") {
+            let path = dir::config_dir().ok().map(|dir| dir.join("proxy.json"));
+            if let Some(settings) = json_store::load(path) {
+                self.network = settings;
+            }
+        }
+        if !document.contains_key("relays") {
+            if let Some(auth) = legacy_data("relay-auth.json") {
+                self.relays.auth = auth;
+            }
+        }
+        if !document.contains_key("explore") {
+            if let Some(explore) = legacy_data("explore.json") {
+                self.explore = explore;
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = match path() {
+            Some(path) => path,
+            None => return,
+        };
+        if self.read_only {
+            log::warn!(
+                "Not saving settings: {} couldn't be read nor backed up",
+                path.display()
+            );
+            return;
+        }
+        match self.to_toml() {
+            Ok(content) => {
+                if let Err(e) = fs::write(path, content) {
+                    log::error!("Impossible to save settings: {}", e);
+                }
+            }
+            Err(e) => log::error!("Impossible to serialize settings: {}", e),
+        }
+    }
+
+    /// Settings file content: the loaded document with the settings written over it
+    fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut document = self.document.clone();
+        merge(&mut document, Table::try_from(self)?);
+        toml::to_string_pretty(&document)
+    }
+}
+
+/// Copy the settings file at `path` aside, before it's overwritten with what could be
+/// read of it. Returns whether it worked.
+fn backup(path: &Path) -> bool {
+    let backup = path.with_extension("toml.bak");
+    match fs::copy(path, &backup) {
+        Ok(_) => {
+            log::warn!("Settings file backed up to {}", backup.display());
+            true
+        }
+        Err(e) => {
+            log::error!("Impossible to back up {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// `document` read into a `T` key by key, recursing into tables: the keys with an
+/// invalid value are skipped, so they keep their default
+fn read_lenient<T>(document: &Table) -> T
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    let mut valid = Table::try_from(T::default()).unwrap_or_default();
+    merge_valid::<T>(&mut valid, &[], document);
+    Value::Table(valid).try_into().unwrap_or_default()
+}
+
+/// Add the keys of `document`, found at `path` in `valid`, that keep `valid` a `T`
+fn merge_valid<T>(valid: &mut Table, path: &[&str], document: &Table)
+where
+    T: DeserializeOwned,
+{
+    for (key, value) in document.iter() {
+        let mut candidate = valid.clone();
+        let mut table = &mut candidate;
+        for name in path.iter() {
+            table = match table
+                .entry(name.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(table) => table,
+                _ => return,
+            };
+        }
+        table.insert(key.clone(), value.clone());
+        let mut path = path.to_vec();
+        path.push(key.as_str());
+        if Value::Table(candidate.clone()).try_into::<T>().is_ok() {
+            *valid = candidate;
+        } else if let Value::Table(value) = value {
+            merge_valid::<T>(valid, &path, value);
+        } else {
+            log::warn!("Invalid setting {}, using its default", path.join("."));
+        }
+    }
+}
+
+/// Overwrite the keys of `document` that are in `table`, recursing into tables
+fn merge(document: &mut Table, table: Table) {
+    for (key, value) in table.into_iter() {
+        match (document.get_mut(&key), value) {
+            (Some(Value::Table(current)), Value::Table(value)) => merge(current, value),
+            (_, value) => {
+                document.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Keys of `document` that aren't in `known`, recursing into tables
+fn unknown(document: Table, known: &Table) -> Table {
+    document
+        .into_iter()
+        .filter_map(|(key, value)| match (value, known.get(&key)) {
+            (Value::Table(table), Some(Value::Table(known))) => {
+                let table = unknown(table, known);
+                if table.is_empty() {
+                    None
+                } else {
+                    Some((key, Value::Table(table)))
+                }
+            }
+            (_, Some(_)) => None,
+            (value, None) => Some((key, value)),
+        })
+        .collect()
+}
+
+/// JSON file some settings were kept in: in the config directory since the XDG
+/// migration, in the data directory if it was migrated before it knew about them
+fn legacy_data<T>(name: &str) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    [dir::config_dir(), dir::default_dir()]
+        .into_iter()
+        .filter_map(|dir| dir.ok())
+        .map(|dir| dir.join(name))
+        .find(|path| path.exists())
+        .and_then(|path| json_store::load(Some(path)))
+}
+
+fn path() -> Option<PathBuf> {
+    dir::config_dir().ok().map(|dir| dir.join("settings.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether every key of `expected` is in `table` with the same value
+    fn contains(table: &Table, expected: &Table) -> bool {
+        expected
+            .iter()
+            .all(|(key, value)| match (value, table.get(key)) {
+                (Value::Table(expected), Some(Value::Table(table))) => contains(table, expected),
+                (value, found) => found == Some(value),
+            })
+    }
+
+    #[test]
+    fn unknown_keys_round_trip() {
+        let content = r#"
+            from_the_future = "kept"
+
+            [appearance]
+            font_scale = 1.5
+            new_knob = 3
+
+            [feed]
+            page_size = 20
+
+            [feed.experimental]
+            flag = true
+
+            [plugin]
+            name = "x"
+        "#;
+        let original: Table = content.parse().unwrap();
+        let config = Config::from_document(original.clone());
+        assert_eq!(config.appearance.font_scale, 1.5);
+        assert_eq!(config.feed.page_size, 20);
+
+        let saved = config.to_toml().unwrap();
+        let document: Table = saved.parse().unwrap();
+        assert!(contains(&document, &original), "{}", saved);
+
+        // Saving what was saved changes nothing
+        let config = Config::from_document(document);
+        assert_eq!(config.to_toml().unwrap(), saved);
+    }
+
+    #[test]
+    fn invalid_values_keep_their_default() {
+        let content = r#"
+            [appearance]
+            font_scale = "big"
+
+            [feed]
+            page_size = -1
+            hide_replies = true
+
+            [media]
+            autoload_images = false
+        "#;
+        let document: Table = content.parse().unwrap();
+        assert!(Value::Table(document.clone()).try_into::<Config>().is_err());
+
+        let config = Config::from_document(document);
+        assert_eq!(config.appearance.font_scale, 1.0);
+        assert_eq!(config.feed.page_size, Feed::default().page_size);
+        assert!(config.feed.hide_replies);
+        assert!(!config.media.autoload_images);
+    }
+}
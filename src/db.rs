@@ -0,0 +1,465 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! SQLite index of the events received
+//!
+//! The SDK store only answers the feed queries. This one keeps every event the sync
+//! receives, with the note it refers to, so trending notes and searches are plain queries
+//! instead of round trips to the relays.
+//!
+//! Events are dropped once older than [`MAX_AGE`], except the latest metadata of each
+//! profile, and the oldest go first beyond [`MAX_EVENTS`].
+//!
+//! It also holds the publish queue: our signed events and their delivery to each relay.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::Event;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::nips::raw::{now, RawEvent};
+use crate::util::dir;
+
+const METADATA_KIND: u64 = 0;
+const TEXT_NOTE_KIND: u64 = 1;
+const REPOST_KIND: u64 = 6;
+const REACTION_KIND: u64 = 7;
+
+/// Age of the oldest events kept
+const MAX_AGE: u64 = 30 * 24 * 60 * 60;
+/// Events kept at most
+const MAX_EVENTS: usize = 200_000;
+/// New events indexed between two prunes
+const PRUNE_INTERVAL: u64 = 1_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- Note a reaction or repost is about: the last `e` tag
+    target TEXT,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_kind_created_at ON events (kind, created_at);
+CREATE TABLE IF NOT EXISTS outbox (
+    id TEXT PRIMARY KEY,
+    json TEXT NOT NULL,
+    queued_at INTEGER NOT NULL,
+    -- Relay url -> delivery, as JSON
+    deliveries TEXT NOT NULL DEFAULT '{}'
+);
+";
+
+/// Event of the publish queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedEvent {
+    pub id: String,
+    pub json: String,
+    pub queued_at: u64,
+    pub deliveries: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+    inserted: Arc<AtomicU64>,
+}
+
+impl Database {
+    /// Open the index of the account. If the file can't be opened, events are indexed in
+    /// memory for this session.
+    pub fn load(public_key: &XOnlyPublicKey) -> Self {
+        let conn = path(public_key)
+            .ok_or(rusqlite::Error::InvalidPath(PathBuf::new()))
+            .and_then(Connection::open)
+            .and_then(|conn| {
+                conn.pragma_update(None, "journal_mode", "WAL")?;
+                Ok(conn)
+            });
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::error!("Impossible to open the event index: {}", e);
+                Connection::open_in_memory().expect("Can't open in-memory database")
+            }
+        };
+        let db = Self::with_connection(conn);
+        db.prune_expired();
+        db
+    }
+
+    fn with_connection(conn: Connection) -> Self {
+        if let Err(e) = conn.execute_batch(SCHEMA) {
+            log::error!("Impossible to create the event index: {}", e);
+        }
+        Self {
+            conn: Arc::new(Mutex::new(conn)),
+            inserted: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic during a query leaves the connection usable
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Index `event`. Returns whether it's new.
+    pub fn insert(&self, event: &Event) -> rusqlite::Result<bool> {
+        match RawEvent::from_event(event) {
+            Ok(raw) => self.insert_raw(&raw),
+            Err(_) => Ok(false),
+        }
+    }
+
+    pub fn insert_raw(&self, raw: &RawEvent) -> rusqlite::Result<bool> {
+        let target = match raw.kind {
+            REPOST_KIND | REACTION_KIND => raw.tag_values("e").last(),
+            _ => None,
+        };
+        let inserted = self.conn().execute(
+            "INSERT OR IGNORE INTO events (id, pubkey, created_at, kind, content, target, json)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                raw.id,
+                raw.pubkey,
+                raw.created_at as i64,
+                raw.kind as i64,
+                raw.content,
+                target,
+                raw.as_json()
+            ],
+        )?;
+        if inserted > 0 && self.inserted.fetch_add(1, Ordering::Relaxed) + 1 >= PRUNE_INTERVAL {
+            self.inserted.store(0, Ordering::Relaxed);
+            self.prune_expired();
+        }
+        Ok(inserted > 0)
+    }
+
+    /// Drop the events created before `before`, except the latest metadata of each
+    /// profile, then the oldest ones beyond `keep`. Returns the number of events dropped.
+    pub fn prune(&self, before: u64, keep: usize) -> rusqlite::Result<usize> {
+        let conn = self.conn();
+        let expired = conn.execute(
+            "DELETE FROM events AS e
+            WHERE created_at < ?1
+                AND NOT (kind = ?2 AND created_at = (
+                    SELECT MAX(created_at) FROM events WHERE kind = ?2 AND pubkey = e.pubkey
+                ))",
+            params![before as i64, METADATA_KIND as i64],
+        )?;
+        let excess = conn.execute(
+            "DELETE FROM events WHERE rowid IN (
+                SELECT rowid FROM events ORDER BY created_at DESC, rowid DESC
+                LIMIT -1 OFFSET ?1
+            )",
+            [keep as i64],
+        )?;
+        Ok(expired + excess)
+    }
+
+    fn prune_expired(&self) {
+        match self.prune(now().saturating_sub(MAX_AGE), MAX_EVENTS) {
+            Ok(0) => (),
+            Ok(dropped) => log::debug!("{} events dropped from the index", dropped),
+            Err(e) => log::error!("Impossible to prune the event index: {}", e),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> rusqlite::Result<Option<RawEvent>> {
+        let json: Option<String> = self
+            .conn()
+            .query_row("SELECT json FROM events WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(json.and_then(|json| RawEvent::from_json(&json).ok()))
+    }
+
+    /// Ids of the notes with the most reactions and reposts since `since`, best first. A
+    /// repost weighs twice a reaction, and downvotes don't count.
+    pub fn trending(&self, since: u64, limit: usize) -> rusqlite::Result<Vec<(String, u64)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT target, SUM(CASE kind WHEN ?1 THEN 2 ELSE 1 END) AS score
+            FROM events
+            WHERE kind IN (?1, ?2) AND created_at >= ?3 AND target IS NOT NULL
+                AND NOT (kind = ?2 AND content = '-')
+            GROUP BY target
+            ORDER BY score DESC, MAX(created_at) DESC
+            LIMIT ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                REPOST_KIND as i64,
+                REACTION_KIND as i64,
+                since as i64,
+                limit as i64
+            ],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)),
+        )?;
+        rows.collect()
+    }
+
+    /// Notes containing `query`, newest first
+    pub fn search_notes(&self, query: &str, limit: usize) -> rusqlite::Result<Vec<RawEvent>> {
+        self.query_events(
+            "SELECT json FROM events
+            WHERE kind = ?1 AND content LIKE ?2 ESCAPE '\\'
+            ORDER BY created_at DESC
+            LIMIT ?3",
+            params![TEXT_NOTE_KIND as i64, like_pattern(query), limit as i64],
+        )
+    }
+
+    /// Latest metadata of the profiles containing `query`. The match is on the whole
+    /// metadata JSON, callers check the fields they show.
+    pub fn search_profiles(&self, query: &str, limit: usize) -> rusqlite::Result<Vec<RawEvent>> {
+        self.query_events(
+            "SELECT json FROM events AS e
+            WHERE kind = ?1 AND content LIKE ?2 ESCAPE '\\'
+                AND created_at = (
+                    SELECT MAX(created_at) FROM events WHERE kind = ?1 AND pubkey = e.pubkey
+                )
+            LIMIT ?3",
+            params![METADATA_KIND as i64, like_pattern(query), limit as i64],
+        )
+    }
+
+    /// Publish queue, oldest first
+    pub fn queued(&self) -> rusqlite::Result<Vec<QueuedEvent>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, json, queued_at, deliveries FROM outbox ORDER BY queued_at, rowid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(QueuedEvent {
+                id: row.get(0)?,
+                json: row.get(1)?,
+                queued_at: row.get::<_, i64>(2)? as u64,
+                deliveries: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Add `event` to the publish queue, keeping the `keep` newest events. Returns whether
+    /// it's new.
+    pub fn enqueue(&self, event: &QueuedEvent, keep: usize) -> rusqlite::Result<bool> {
+        let conn = self.conn();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO outbox (id, json, queued_at, deliveries)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![
+                event.id,
+                event.json,
+                event.queued_at as i64,
+                event.deliveries
+            ],
+        )?;
+        conn.execute(
+            "DELETE FROM outbox WHERE rowid NOT IN (
+                SELECT rowid FROM outbox ORDER BY queued_at DESC, rowid DESC LIMIT ?1
+            )",
+            [keep as i64],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn set_deliveries(&self, id: &str, deliveries: &str) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE outbox SET deliveries = ?2 WHERE id = ?1",
+            params![id, deliveries],
+        )?;
+        Ok(())
+    }
+
+    fn query_events<P>(&self, sql: &str, params: P) -> rusqlite::Result<Vec<RawEvent>>
+    where
+        P: rusqlite::Params,
+    {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+        let mut events = Vec::new();
+        for json in rows {
+            if let Ok(raw) = RawEvent::from_json(&json?) {
+                events.push(raw);
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// `LIKE` pattern matching `query` anywhere. SQLite ignores the case of ASCII letters
+/// only.
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+fn path(public_key: &XOnlyPublicKey) -> Option<PathBuf> {
+    dir::default_dir()
+        .ok()
+        .map(|dir| dir.join(format!("index-{}.db", public_key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        id: &str,
+        kind: u64,
+        created_at: u64,
+        target: Option<&str>,
+        content: &str,
+    ) -> RawEvent {
+        let tags = target
+            .map(|target| vec![vec![String::from("e"), target.to_string()]])
+            .unwrap_or_default();
+        RawEvent {
+            id: id.to_string(),
+            pubkey: String::from("pk"),
+            created_at,
+            kind,
+            tags,
+            content: content.to_string(),
+            sig: None,
+        }
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let db = Database::with_connection(Connection::open_in_memory().unwrap());
+        let note = event("a", 1, 10, None, "gm");
+        assert!(db.insert_raw(&note).unwrap());
+        assert!(!db.insert_raw(&note).unwrap());
+        assert_eq!(db.get("a").unwrap(), Some(note));
+        assert_eq!(db.get("b").unwrap(), None);
+    }
+
+    #[test]
+    fn trending_scores() {
+        let db = Database::with_connection(Connection::open_in_memory().unwrap());
+        let events = [
+            event("1", REACTION_KIND, 100, Some("a"), "+"),
+            event("2", REACTION_KIND, 100, Some("a"), "🤙"),
+            event("3", REPOST_KIND, 100, Some("b"), ""),
+            event("4", REPOST_KIND, 100, Some("b"), ""),
+            event("5", REACTION_KIND, 100, Some("c"), "-"),
+            event("6", REACTION_KIND, 10, Some("c"), "+"),
+            event("7", REACTION_KIND, 10, Some("c"), "+"),
+            event("8", 1, 100, Some("c"), "reply"),
+        ];
+        for e in events.iter() {
+            db.insert_raw(e).unwrap();
+        }
+        assert_eq!(
+            db.trending(50, 10).unwrap(),
+            vec![(String::from("b"), 4), (String::from("a"), 2)]
+        );
+        assert_eq!(db.trending(50, 1).unwrap(), vec![(String::from("b"), 4)]);
+        assert_eq!(db.trending(0, 10).unwrap()[2], (String::from("c"), 2));
+    }
+
+    #[test]
+    fn search() {
+        let db = Database::with_connection(Connection::open_in_memory().unwrap());
+        let mut old_profile = event("1", METADATA_KIND, 10, None, r#"{"name":"Satoshi"}"#);
+        old_profile.pubkey = String::from("a");
+        let mut profile = event("2", METADATA_KIND, 20, None, r#"{"name":"Hal"}"#);
+        profile.pubkey = String::from("a");
+        let notes = [
+            event("3", TEXT_NOTE_KIND, 30, None, "Running bitcoin"),
+            event("4", TEXT_NOTE_KIND, 40, None, "100% BITCOIN"),
+            event("5", TEXT_NOTE_KIND, 50, None, "gm"),
+        ];
+        for e in [&old_profile, &profile].into_iter().chain(notes.iter()) {
+            db.insert_raw(e).unwrap();
+        }
+
+        let ids =
+            |events: Vec<RawEvent>| -> Vec<String> { events.into_iter().map(|e| e.id).collect() };
+        assert_eq!(ids(db.search_notes("bitcoin", 10).unwrap()), ["4", "3"]);
+        assert_eq!(ids(db.search_notes("bitcoin", 1).unwrap()), ["4"]);
+        assert_eq!(ids(db.search_notes("0%", 10).unwrap()), ["4"]);
+        assert!(db.search_notes("_", 10).unwrap().is_empty());
+        assert_eq!(ids(db.search_profiles("hal", 10).unwrap()), ["2"]);
+        assert!(db.search_profiles("satoshi", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prune() {
+        let db = Database::with_connection(Connection::open_in_memory().unwrap());
+        let mut old_profile = event("1", METADATA_KIND, 10, None, "{}");
+        old_profile.pubkey = String::from("a");
+        let mut profile = event("2", METADATA_KIND, 20, None, "{}");
+        profile.pubkey = String::from("a");
+        let events = [
+            old_profile,
+            profile,
+            event("3", TEXT_NOTE_KIND, 30, None, "old"),
+            event("4", TEXT_NOTE_KIND, 100, None, "a"),
+            event("5", TEXT_NOTE_KIND, 110, None, "b"),
+            event("6", TEXT_NOTE_KIND, 120, None, "c"),
+        ];
+        for e in events.iter() {
+            db.insert_raw(e).unwrap();
+        }
+
+        // The latest metadata of a profile is kept whatever its age
+        assert_eq!(db.prune(50, 10).unwrap(), 2);
+        assert!(db.get("1").unwrap().is_none());
+        assert!(db.get("2").unwrap().is_some());
+        assert!(db.get("3").unwrap().is_none());
+
+        assert_eq!(db.prune(50, 2).unwrap(), 2);
+        for (id, kept) in [("2", false), ("4", false), ("5", true), ("6", true)] {
+            assert_eq!(db.get(id).unwrap().is_some(), kept, "event {}", id);
+        }
+    }
+
+    #[test]
+    fn publish_queue() {
+        let db = Database::with_connection(Connection::open_in_memory().unwrap());
+        let queued = |id: &str, queued_at: u64| QueuedEvent {
+            id: id.to_string(),
+            json: format!("{{\"id\":\"{}\"}}", id),
+            queued_at,
+            deliveries: String::from("{}"),
+        };
+        assert!(db.enqueue(&queued("a", 10), 2).unwrap());
+        assert!(!db.enqueue(&queued("a", 10), 2).unwrap());
+        assert!(db.enqueue(&queued("b", 20), 2).unwrap());
+        db.set_deliveries("a", r#"{"wss://relay.example":{"status":"accepted"}}"#)
+            .unwrap();
+
+        let events = db.queued().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, "a");
+        assert_eq!(
+            events[0].deliveries,
+            r#"{"wss://relay.example":{"status":"accepted"}}"#
+        );
+        assert_eq!(events[1], queued("b", 20));
+
+        // The oldest events are dropped
+        assert!(db.enqueue(&queued("c", 30), 2).unwrap());
+        let ids: Vec<String> = db.queued().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, ["b", "c"]);
+    }
+}
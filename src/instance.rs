@@ -0,0 +1,150 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Single instance
+//!
+//! The first instance listens on a local socket in the runtime directory, one per
+//! data directory. The next ones send it their arguments and exit, so they don't
+//! open the same store.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher as _};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use iced::advanced::subscription::{EventStream, Recipe};
+use iced::advanced::Hasher;
+use iced::Subscription;
+use iced_futures::BoxStream;
+use once_cell::sync::Lazy;
+
+#[cfg(unix)]
+type Listener = std::os::unix::net::UnixListener;
+#[cfg(not(unix))]
+type Listener = ();
+
+/// Taken by the subscription, the first time it starts
+static LISTENER: Lazy<Mutex<Option<Listener>>> = Lazy::new(|| Mutex::new(None));
+
+/// Arguments are separated by NUL, which can't appear in them
+const SEPARATOR: char = '\0';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instance {
+    /// No other instance: we keep running and listen for the next ones
+    Primary,
+    /// The arguments were handed to the running instance
+    Forwarded,
+}
+
+/// Forward `args` to the running instance of `data_dir`, or become it
+#[cfg(unix)]
+pub fn acquire(data_dir: &Path, args: &[String]) -> io::Result<Instance> {
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = socket_path(data_dir);
+    match UnixStream::connect(&path) {
+        Ok(mut stream) => {
+            let payload: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+            stream.write_all(payload.join(&SEPARATOR.to_string()).as_bytes())?;
+            stream.shutdown(Shutdown::Write)?;
+            return Ok(Instance::Forwarded);
+        }
+        // Left behind by an instance that didn't exit cleanly
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(&path)?;
+        }
+        Err(_) => (),
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    if let Ok(mut l) = LISTENER.lock() {
+        *l = Some(listener);
+    }
+    Ok(Instance::Primary)
+}
+
+#[cfg(not(unix))]
+pub fn acquire(_data_dir: &Path, _args: &[String]) -> io::Result<Instance> {
+    Ok(Instance::Primary)
+}
+
+/// Remove the socket, so the next instance doesn't have to find it stale
+pub fn release(data_dir: &Path) {
+    if cfg!(unix) {
+        let _ = std::fs::remove_file(socket_path(data_dir));
+    }
+}
+
+fn socket_path(data_dir: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    data_dir.hash(&mut hasher);
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("nostr-desktop-{:016x}.sock", hasher.finish()))
+}
+
+/// Arguments forwarded by the next instances
+pub struct Forwarded;
+
+impl Forwarded {
+    pub fn subscription() -> Subscription<Vec<String>> {
+        Subscription::from_recipe(Self)
+    }
+}
+
+impl Recipe for Forwarded {
+    type Output = Vec<String>;
+
+    fn hash(&self, state: &mut Hasher) {
+        std::any::TypeId::of::<Self>().hash(state);
+    }
+
+    #[cfg(unix)]
+    fn stream(self: Box<Self>, _input: EventStream) -> BoxStream<Self::Output> {
+        use async_stream::stream;
+        use tokio::io::AsyncReadExt;
+        use tokio::net::UnixListener;
+
+        let listener = LISTENER
+            .lock()
+            .ok()
+            .and_then(|mut l| l.take())
+            .and_then(|l| UnixListener::from_std(l).ok());
+        let stream = stream! {
+            let listener = match listener {
+                Some(listener) => listener,
+                None => return,
+            };
+            loop {
+                let mut stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::error!("Impossible to accept forwarded arguments: {}", e);
+                        continue;
+                    }
+                };
+                let mut payload = String::new();
+                if let Err(e) = stream.read_to_string(&mut payload).await {
+                    log::error!("Impossible to read forwarded arguments: {}", e);
+                    continue;
+                }
+                yield payload
+                    .split(SEPARATOR)
+                    .filter(|a| !a.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        };
+        Box::pin(stream)
+    }
+
+    #[cfg(not(unix))]
+    fn stream(self: Box<Self>, _input: EventStream) -> BoxStream<Self::Output> {
+        Box::pin(iced::futures::stream::empty())
+    }
+}
@@ -1,13 +1,25 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::{executor, Application, Command, Element, Settings, Size, Subscription, Theme};
+use iced::{executor, window, Application, Command, Element, Settings, Size, Subscription, Theme};
 use once_cell::sync::Lazy;
 use tokio::runtime::Runtime;
 
+mod checkpoint;
+mod cli;
 mod component;
+mod config;
+mod db;
 mod error;
+mod instance;
 mod message;
+mod net;
+mod nips;
+mod notifier;
+mod outbox;
+mod relay;
+mod seen_on;
+mod shortcut;
 mod stage;
 mod sync;
 mod theme;
@@ -15,13 +27,63 @@ mod util;
 
 use theme::font::BOOTSTRAP_ICONS_BYTES;
 
+use self::cli::Args;
+use self::config::Config;
+use self::instance::{Forwarded, Instance};
 use self::message::Message;
+use self::util::dir;
 
 static RUNTIME: Lazy<Runtime> = Lazy::new(|| Runtime::new().expect("Can't start Tokio runtime"));
 
 pub fn main() -> iced::Result {
-    env_logger::init();
+    let args = match Args::from_env() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = &args.log_level {
+        logger.parse_filters(level);
+    }
+    logger.init();
+
+    let data_dir = match dir::init(args.data_dir.clone()).and_then(|_| dir::default_dir()) {
+        Ok(path) => path,
+        Err(e) => {
+            log::error!("Impossible to set up the data directory: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    let primary = match instance::acquire(&data_dir, &raw_args) {
+        Ok(Instance::Forwarded) => {
+            log::info!("Arguments forwarded to the running instance");
+            return Ok(());
+        }
+        Ok(Instance::Primary) => true,
+        Err(e) => {
+            log::warn!("Impossible to check for a running instance: {}", e);
+            false
+        }
+    };
+
+    let config = Config::load();
+    net::set_settings(config.network.clone());
+
     let mut settings = Settings {
+        flags: (config, args),
         fonts: vec![BOOTSTRAP_ICONS_BYTES.into()],
         ..Settings::default()
     };
@@ -29,7 +91,11 @@ pub fn main() -> iced::Result {
         width: 600.0,
         height: 600.0,
     });
-    NostrDesktop::run(settings)
+    let result = NostrDesktop::run(settings);
+    if primary {
+        instance::release(&data_dir);
+    }
+    result
 }
 
 pub enum NostrDesktop {
@@ -37,17 +103,48 @@ pub enum NostrDesktop {
     Dashboard(stage::App),
 }
 
+impl NostrDesktop {
+    fn config(&self) -> &Config {
+        match self {
+            Self::Auth(auth) => &auth.config,
+            Self::Dashboard(app) => &app.context.config,
+        }
+    }
+
+    /// Open the URI another instance was started with, and bring the window up
+    fn handle_forwarded(&mut self, args: Vec<String>) -> Command<Message> {
+        let focus = window::gain_focus(window::Id::MAIN);
+        let uri = match Args::parse(args) {
+            Ok(args) => args.uri,
+            Err(e) => {
+                log::warn!("Invalid forwarded arguments: {}", e);
+                None
+            }
+        };
+        match (self, uri) {
+            (Self::Dashboard(app), Some(entity)) => {
+                Command::batch(vec![app.update(Message::Open(entity)), focus])
+            }
+            (Self::Auth(auth), Some(entity)) => {
+                auth.set_uri(entity);
+                focus
+            }
+            (_, None) => focus,
+        }
+    }
+}
+
 impl Application for NostrDesktop {
     type Executor = executor::Default;
-    type Flags = ();
+    type Flags = (Config, Args);
     type Message = Message;
     type Theme = Theme;
 
-    fn new(_flags: ()) -> (Self, Command<Self::Message>) {
+    fn new((config, args): (Config, Args)) -> (Self, Command<Self::Message>) {
         // read local db
         // if key exists, load main app
         // else load login/register view
-        let stage = stage::Auth::new();
+        let stage = stage::Auth::new(config, args.account, args.uri);
         (Self::Auth(stage.0), stage.1)
     }
 
@@ -59,17 +156,28 @@ impl Application for NostrDesktop {
     }
 
     fn theme(&self) -> Theme {
-        Theme::Dark
+        self.config().appearance.theme.resolve()
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.config().appearance.font_scale as f64
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        match self {
+        let stage = match self {
             Self::Auth(auth) => auth.subscription(),
             Self::Dashboard(app) => app.subscription(),
-        }
+        };
+        Subscription::batch(vec![
+            stage,
+            Forwarded::subscription().map(Message::Forwarded),
+        ])
     }
 
     fn update(&mut self, message: Message) -> Command<Self::Message> {
+        if let Message::Forwarded(args) = message {
+            return self.handle_forwarded(args);
+        }
         match self {
             Self::Auth(auth) => {
                 let (command, stage_to_move) = auth.update(message);
@@ -81,7 +189,8 @@ impl Application for NostrDesktop {
             Self::Dashboard(app) => match message {
                 Message::Lock => {
                     let client = app.context.client.clone();
-                    *self = Self::Auth(stage::Auth::new().0);
+                    let config = app.context.config.clone();
+                    *self = Self::Auth(stage::Auth::new(config, None, None).0);
                     Command::perform(
                         async move {
                             if let Err(e) = client.shutdown().await {
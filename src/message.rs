@@ -2,16 +2,29 @@
 // Distributed under the MIT software license
 
 use iced::widget::scrollable;
+use nostr_sdk::nostr::url::Url;
 use nostr_sdk::nostr::Event;
 use nostr_sdk::Client;
 
+use crate::nips::nip19::Entity;
 use crate::stage::auth::screen::LoginMessage;
+use crate::stage::dashboard::publisher::Delivery;
 use crate::stage::dashboard::screen::{
     ChatMessage, ContactsMessage, ExploreMessage, HomeMessage, NotificationsMessage,
-    ProfileMessage, SettingMessage,
+    ProfileMessage, SettingMessage, ThreadMessage,
 };
 use crate::stage::{auth, dashboard};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    Started,
+    /// Sync couldn't start, next attempt at `retry_at` (unix timestamp)
+    Failed {
+        error: String,
+        retry_at: u64,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub enum DashboardMessage {
     Home(HomeMessage),
@@ -20,6 +33,7 @@ pub enum DashboardMessage {
     Contacts(ContactsMessage),
     Notifications(NotificationsMessage),
     Profile(ProfileMessage),
+    Thread(ThreadMessage),
     Setting(SettingMessage),
 }
 
@@ -27,9 +41,34 @@ pub enum DashboardMessage {
 pub enum Message {
     Tick,
     Sync(Event),
+    SyncStatus(SyncStatus),
+    /// `OK` of a relay for a published event
+    RelayOk {
+        event_id: String,
+        accepted: bool,
+        message: String,
+    },
+    /// Queue a signed event for the write relays
+    Publish(Event),
+    RetryPublish,
+    /// Events of a delivery round, and their delivery to each relay
+    Delivered(Vec<String>, Vec<(String, Url, Delivery)>),
+    /// Open or close the details panel of a post
+    ToggleEventDetails(String),
+    WindowFocused(bool),
     Scrolled(scrollable::Viewport),
     SetAuthStage(auth::Stage),
     SetDashboardStage(dashboard::Stage),
+    /// Open the profile or thread a `nostr:` URI points to
+    Open(Entity),
+    /// Previous or next screen of the navigation history
+    NavigateBack,
+    NavigateForward,
+    /// Key pressed outside of a text input, by its name in the bindings
+    Shortcut(String),
+    ToggleShortcuts,
+    /// Arguments of an instance started while we were running
+    Forwarded(Vec<String>),
     LoginResult(Client),
     Lock,
     Clipboard(String),
@@ -0,0 +1,160 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Proxy settings shared by the relay connections and the HTTP requests
+//!
+//! The settings are kept in the `[network]` section of the settings file. This is the
+//! copy the connections read, set when the settings are loaded or changed.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::Client;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+static SETTINGS: Lazy<RwLock<ProxySettings>> = Lazy::new(|| RwLock::new(ProxySettings::default()));
+
+#[derive(Debug)]
+pub enum Error {
+    /// Tor-only mode and no proxy to go through
+    ClearnetRefused,
+    Reqwest(reqwest::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ClearnetRefused => write!(f, "clearnet connections are refused in Tor-only mode"),
+            Self::Reqwest(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Reqwest(e)
+    }
+}
+
+/// How a relay connects, when it doesn't follow the global proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyOverride {
+    Direct,
+    Socks5(SocketAddr),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    /// SOCKS5 proxy of every connection without an override, e.g. Tor on 127.0.0.1:9050
+    pub proxy: Option<SocketAddr>,
+    /// Relay url -> override
+    pub overrides: HashMap<String, ProxyOverride>,
+    /// Refuse the connections that wouldn't go through a proxy
+    pub tor_only: bool,
+}
+
+impl ProxySettings {
+    fn proxy_of(&self, url: &Url) -> Option<SocketAddr> {
+        match self.overrides.get(url.as_str()) {
+            Some(ProxyOverride::Direct) => None,
+            Some(ProxyOverride::Socks5(proxy)) => Some(*proxy),
+            None => self.proxy,
+        }
+    }
+}
+
+pub fn settings() -> ProxySettings {
+    SETTINGS.read().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Use `settings` for the next connections. The caller saves them in the settings file.
+pub fn set_settings(settings: ProxySettings) {
+    if let Ok(mut current) = SETTINGS.write() {
+        *current = settings;
+    }
+}
+
+/// Fail in Tor-only mode if there's no proxy to go through
+pub fn check(proxy: Option<SocketAddr>) -> Result<(), Error> {
+    if proxy.is_none() && settings().tor_only {
+        Err(Error::ClearnetRefused)
+    } else {
+        Ok(())
+    }
+}
+
+/// Proxy `url` should connect through: its override, or the global proxy
+pub fn relay_proxy(url: &Url) -> Result<Option<SocketAddr>, Error> {
+    let proxy = settings().proxy_of(url);
+    check(proxy)?;
+    Ok(proxy)
+}
+
+/// HTTP client going through `proxy`, or direct if `None`: the proxy of a relay with a
+/// direct override is `None` too
+pub fn http_client(proxy: Option<SocketAddr>, timeout: Duration) -> Result<reqwest::Client, Error> {
+    check(proxy)?;
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(format!("socks5h://{}", proxy))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Keep the proxy relays were added with before the global proxy existed, as overrides.
+/// Returns whether the settings changed.
+pub async fn adopt_relay_proxies(client: &Client) -> bool {
+    let mut settings = settings();
+    let mut changed = false;
+    for (url, relay) in client.relays().await.into_iter() {
+        if let Some(proxy) = relay.proxy() {
+            if settings.proxy != Some(proxy) && !settings.overrides.contains_key(url.as_str()) {
+                settings
+                    .overrides
+                    .insert(url.to_string(), ProxyOverride::Socks5(proxy));
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        set_settings(settings);
+    }
+    changed
+}
+
+/// Reconnect the relays whose proxy doesn't match the settings anymore, and return
+/// them: they come back without subscriptions. The relays refused by Tor-only mode are
+/// removed, and stay in the saved relays.
+pub async fn apply(client: &Client) -> Vec<Url> {
+    let settings = settings();
+    let mut reconnected = Vec::new();
+    for (url, relay) in client.relays().await.into_iter() {
+        let proxy = settings.proxy_of(&url);
+        if relay.proxy() == proxy && (proxy.is_some() || !settings.tor_only) {
+            continue;
+        }
+        if let Err(e) = client.remove_relay(url.as_str()).await {
+            log::error!("Impossible to remove {}: {}", url, e);
+            continue;
+        }
+        if proxy.is_none() && settings.tor_only {
+            log::info!("{} removed: no proxy in Tor-only mode", url);
+            continue;
+        }
+        match client.add_relay(url.clone(), proxy).await {
+            Ok(_) => reconnected.push(url),
+            Err(e) => log::error!("Impossible to add {}: {}", url, e),
+        }
+    }
+    client.connect().await;
+    reconnected
+}
@@ -0,0 +1,15 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+pub mod nip10;
+pub mod nip11;
+pub mod nip17;
+pub mod nip19;
+pub mod nip28;
+pub mod nip42;
+pub mod nip44;
+pub mod nip57;
+pub mod nip59;
+pub mod nip65;
+pub mod nip77;
+pub mod raw;
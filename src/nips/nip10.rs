@@ -0,0 +1,76 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-10 replies
+
+use super::raw::RawEvent;
+
+/// Event a note replies to: its `reply` marked `e` tag, its `root` one for a direct
+/// reply to the root, or the last `e` tag for notes that don't use markers. `None` if
+/// the note isn't a reply, e.g. when it only has `mention` markers.
+pub fn replied_to(raw: &RawEvent) -> Option<String> {
+    let e_tags: Vec<&Vec<String>> = raw
+        .tags
+        .iter()
+        .filter(|t| t.first().map(|n| n == "e").unwrap_or(false) && t.len() > 1)
+        .collect();
+    let marked = |marker: &str| {
+        e_tags
+            .iter()
+            .find(|t| t.get(3).map(|m| m == marker).unwrap_or(false))
+            .map(|t| t[1].clone())
+    };
+    if e_tags
+        .iter()
+        .any(|t| t.get(3).map(|m| !m.is_empty()).unwrap_or(false))
+    {
+        marked("reply").or_else(|| marked("root"))
+    } else {
+        e_tags.last().map(|t| t[1].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(tags: Vec<Vec<&str>>) -> RawEvent {
+        let mut raw: RawEvent = serde_json::from_value(serde_json::json!({
+            "id": "",
+            "pubkey": "",
+            "created_at": 0,
+            "kind": 1,
+            "tags": [],
+            "content": "",
+        }))
+        .unwrap();
+        raw.tags = tags
+            .into_iter()
+            .map(|t| t.into_iter().map(String::from).collect())
+            .collect();
+        raw
+    }
+
+    #[test]
+    fn marked_tags() {
+        let reply = note(vec![
+            vec!["e", "root", "", "root"],
+            vec!["e", "parent", "", "reply"],
+            vec!["e", "quoted", "", "mention"],
+        ]);
+        assert_eq!(replied_to(&reply).as_deref(), Some("parent"));
+
+        let direct = note(vec![vec!["e", "root", "", "root"]]);
+        assert_eq!(replied_to(&direct).as_deref(), Some("root"));
+
+        let mention = note(vec![vec!["e", "quoted", "", "mention"]]);
+        assert_eq!(replied_to(&mention), None);
+    }
+
+    #[test]
+    fn positional_tags() {
+        let reply = note(vec![vec!["e", "root"], vec!["e", "parent"]]);
+        assert_eq!(replied_to(&reply).as_deref(), Some("parent"));
+        assert_eq!(replied_to(&note(vec![vec!["p", "someone"]])), None);
+    }
+}
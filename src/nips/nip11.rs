@@ -0,0 +1,121 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-11: relay information document
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use nostr_sdk::nostr::url::Url;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::net;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limitation {
+    pub max_message_length: Option<u64>,
+    pub max_subscriptions: Option<u64>,
+    pub max_filters: Option<u64>,
+    pub max_limit: Option<u64>,
+    pub max_event_tags: Option<u64>,
+    pub max_content_length: Option<u64>,
+    pub min_pow_difficulty: Option<u64>,
+    pub auth_required: bool,
+    pub payment_required: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayInformationDocument {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub pubkey: Option<String>,
+    pub contact: Option<String>,
+    #[serde(deserialize_with = "lenient_nips")]
+    pub supported_nips: Vec<u16>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+    pub limitation: Limitation,
+}
+
+impl RelayInformationDocument {
+    pub fn supports(&self, nip: u16) -> bool {
+        self.supported_nips.contains(&nip)
+    }
+}
+
+/// Some relays list NIPs as strings, or with entries that aren't NIP numbers. The valid
+/// ones are kept rather than rejecting the whole document.
+fn lenient_nips<'de, D>(deserializer: D) -> Result<Vec<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = match Value::deserialize(deserializer)? {
+        Value::Array(values) => values,
+        _ => return Ok(Vec::new()),
+    };
+    Ok(values
+        .iter()
+        .filter_map(|value| match value {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        })
+        .filter_map(|nip| u16::try_from(nip).ok())
+        .collect())
+}
+
+/// `wss://relay` -> `https://relay`
+fn http_url(relay_url: &Url) -> String {
+    let url = relay_url.to_string();
+    if let Some(rest) = url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        url
+    }
+}
+
+/// Fetch the document, through the SOCKS5 `proxy` the relay connects through, if any
+pub async fn fetch(
+    relay_url: &Url,
+    proxy: Option<SocketAddr>,
+) -> Result<RelayInformationDocument, net::Error> {
+    Ok(net::http_client(proxy, TIMEOUT)?
+        .get(http_url(relay_url))
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await?
+        .json()
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient_supported_nips() {
+        let document: RelayInformationDocument = serde_json::from_str(
+            r#"{"name":"relay","supported_nips":[1,"11"," 50 ","nip-42",70000,-1,2.5,null]}"#,
+        )
+        .unwrap();
+        assert_eq!(document.name.as_deref(), Some("relay"));
+        assert_eq!(document.supported_nips, vec![1, 11, 50]);
+        assert!(document.supports(50));
+
+        for nips in ["null", "\"1, 11\"", "{}"] {
+            let json = format!(r#"{{"name":"relay","supported_nips":{}}}"#, nips);
+            let document: RelayInformationDocument = serde_json::from_str(&json).unwrap();
+            assert!(document.supported_nips.is_empty());
+        }
+
+        let document: RelayInformationDocument = serde_json::from_str("{}").unwrap();
+        assert!(document.supported_nips.is_empty());
+    }
+}
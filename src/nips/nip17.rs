@@ -0,0 +1,164 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-17 private direct messages
+
+use std::fmt;
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, Event, Kind, SubscriptionFilter};
+use nostr_sdk::{Client, Result};
+
+use super::nip59;
+use super::raw::{now, RawEvent};
+use crate::net;
+
+pub const PRIVATE_DIRECT_MESSAGE_KIND: u64 = 14;
+pub const DM_RELAYS_KIND: u64 = 10050;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The recipient published no kind 10050 relay list
+    NoDmRelays,
+    /// None of the DM relays took the event
+    NotSent,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoDmRelays => write!(
+                f,
+                "the recipient can't receive NIP-17 messages: they published no DM relay list"
+            ),
+            Self::NotSent => write!(f, "no DM relay of the recipient could be reached"),
+        }
+    }
+}
+
+/// Build the unsigned kind 14 rumor sent from `sender` to `receiver`
+pub fn rumor(sender: XOnlyPublicKey, receiver: XOnlyPublicKey, content: String) -> RawEvent {
+    RawEvent::new(
+        sender,
+        now(),
+        PRIVATE_DIRECT_MESSAGE_KIND,
+        vec![vec![String::from("p"), receiver.to_string()]],
+        content,
+    )
+}
+
+/// Relays listed in a kind 10050 event
+pub fn dm_relays(event: &Event) -> Vec<Url> {
+    match RawEvent::from_event(event) {
+        Ok(raw) if raw.kind == DM_RELAYS_KIND => raw
+            .tag_values("relay")
+            .filter_map(|url| Url::parse(url).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Fetch the kind 10050 relay list of `public_key`
+pub async fn get_dm_relays(client: &Client, public_key: XOnlyPublicKey) -> Vec<Url> {
+    let filter = SubscriptionFilter::new()
+        .author(public_key)
+        .kind(Kind::from(DM_RELAYS_KIND))
+        .limit(1);
+    match client.get_events_of(vec![filter]).await {
+        Ok(events) => events
+            .iter()
+            .max_by_key(|e| e.created_at)
+            .map(dm_relays)
+            .unwrap_or_default(),
+        Err(e) => {
+            log::error!("Impossible to get DM relays of {}: {}", public_key, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Gift wrap `content` for `receiver` and for ourselves, and publish each copy to the DM
+/// relays of its recipient. Nothing is sent if `receiver` has no DM relay list; our
+/// own copy goes to our relays if we don't have one.
+pub async fn send_private_message(
+    client: &Client,
+    receiver: XOnlyPublicKey,
+    content: String,
+) -> Result<()> {
+    let receiver_relays = get_dm_relays(client, receiver).await;
+    if receiver_relays.is_empty() {
+        return Err(Error::NoDmRelays.into());
+    }
+
+    let keys = client.keys();
+    let secret_key = keys.secret_key()?;
+    let sender = keys.public_key();
+    let rumor = rumor(sender, receiver, content);
+
+    let event = nip59::gift_wrap(&secret_key, &receiver, &rumor)?;
+    if !send_event_to(client, &receiver_relays, event).await {
+        return Err(Error::NotSent.into());
+    }
+
+    let event = nip59::gift_wrap(&secret_key, &sender, &rumor)?;
+    let relays = get_dm_relays(client, sender).await;
+    if relays.is_empty() {
+        if let Err(e) = client.send_event(event).await {
+            log::error!("Impossible to send our copy of the message: {}", e);
+        }
+    } else if !send_event_to(client, &relays, event).await {
+        log::error!("Impossible to send our copy of the message to our DM relays");
+    }
+
+    Ok(())
+}
+
+/// Send `event` to `urls`: through our connection to the relays we're connected to, and
+/// through a short-lived client sharing our keys to the others, so the recipient's relays
+/// don't end up in our relay list. Returns whether at least one relay got it.
+async fn send_event_to(client: &Client, urls: &[Url], event: Event) -> bool {
+    let connected = client.relays().await;
+    let mut sent = false;
+    let mut others = Vec::new();
+    for url in urls.iter() {
+        match connected.get(url) {
+            Some(relay) => match relay
+                .send_msg(ClientMessage::new_event(event.clone()))
+                .await
+            {
+                Ok(_) => sent = true,
+                Err(e) => log::error!("Impossible to send event to {}: {}", url, e),
+            },
+            None => others.push(url.clone()),
+        }
+    }
+    if others.is_empty() {
+        return sent;
+    }
+
+    let temp = Client::new(&client.keys());
+    for url in others.into_iter() {
+        let result = match net::relay_proxy(&url) {
+            Ok(proxy) => temp
+                .add_relay(url.clone(), proxy)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            log::error!("Impossible to add {}: {}", url, e);
+        }
+    }
+    temp.connect().await;
+    match temp.send_event(event).await {
+        Ok(_) => sent = true,
+        Err(e) => log::error!("Impossible to send event to DM relays: {}", e),
+    }
+    if let Err(e) = temp.shutdown().await {
+        log::error!("Impossible to shutdown client: {}", e);
+    }
+    sent
+}
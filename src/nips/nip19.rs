@@ -0,0 +1,232 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-19: bech32-encoded entities
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+
+use super::raw::{from_hex, to_hex};
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+const TLV_SPECIAL: u8 = 0;
+const TLV_RELAY: u8 = 1;
+const TLV_AUTHOR: u8 = 2;
+const TLV_KIND: u8 = 3;
+
+/// NIP-21 URI scheme
+const URI_PREFIX: &str = "nostr:";
+
+/// Entity a `npub`, `nprofile`, `note`, `nevent` or `naddr` points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entity {
+    Profile {
+        public_key: XOnlyPublicKey,
+        relays: Vec<String>,
+    },
+    Event {
+        /// Hex id
+        id: String,
+        relays: Vec<String>,
+        author: Option<XOnlyPublicKey>,
+    },
+    /// Parameterized replaceable event
+    Address {
+        identifier: String,
+        author: XOnlyPublicKey,
+        kind: u32,
+        relays: Vec<String>,
+    },
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut chk: u32 = 1;
+    for v in values.iter() {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ *v as u32;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|b| b & 31));
+    expanded
+}
+
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max = (1 << to) - 1;
+    let mut out = Vec::new();
+    for value in data.iter() {
+        let value = *value as u32;
+        if value >> from != 0 {
+            return None;
+        }
+        acc = (acc << from) | value;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+pub fn encode(hrp: &str, data: &[u8]) -> String {
+    let mut values = convert_bits(data, 8, 5, true).unwrap_or_default();
+    let mut checksum_input = hrp_expand(hrp);
+    checksum_input.extend_from_slice(&values);
+    checksum_input.extend_from_slice(&[0; 6]);
+    let checksum = polymod(&checksum_input) ^ 1;
+    values.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8));
+
+    let mut encoded = format!("{}1", hrp);
+    encoded.extend(values.iter().map(|v| CHARSET[*v as usize] as char));
+    encoded
+}
+
+/// Human readable part and data of a bech32 string
+pub fn decode(s: &str) -> Option<(String, Vec<u8>)> {
+    if s.to_lowercase() != s && s.to_uppercase() != s {
+        return None;
+    }
+    let s = s.to_lowercase();
+    let (hrp, data) = s.rsplit_once('1')?;
+    if hrp.is_empty() || data.len() < 6 {
+        return None;
+    }
+    let values = data
+        .bytes()
+        .map(|b| CHARSET.iter().position(|c| *c == b).map(|p| p as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    let mut checksum_input = hrp_expand(hrp);
+    checksum_input.extend_from_slice(&values);
+    if polymod(&checksum_input) != 1 {
+        return None;
+    }
+    let data = convert_bits(&values[..values.len() - 6], 5, 8, false)?;
+    Some((hrp.to_string(), data))
+}
+
+/// Parse a bech32 entity, with or without the `nostr:` prefix
+pub fn parse(s: &str) -> Option<Entity> {
+    let s = s.trim();
+    let s = s.strip_prefix(URI_PREFIX).unwrap_or(s);
+    let (hrp, data) = decode(s)?;
+    match hrp.as_str() {
+        "npub" => Some(Entity::Profile {
+            public_key: XOnlyPublicKey::from_slice(&data).ok()?,
+            relays: Vec::new(),
+        }),
+        "note" if data.len() == 32 => Some(Entity::Event {
+            id: to_hex(&data),
+            relays: Vec::new(),
+            author: None,
+        }),
+        "nprofile" => {
+            let tlv = Tlv::parse(&data)?;
+            Some(Entity::Profile {
+                public_key: XOnlyPublicKey::from_slice(tlv.special?).ok()?,
+                relays: tlv.relays,
+            })
+        }
+        "nevent" => {
+            let tlv = Tlv::parse(&data)?;
+            Some(Entity::Event {
+                id: tlv.special.filter(|id| id.len() == 32).map(to_hex)?,
+                relays: tlv.relays,
+                author: tlv.author,
+            })
+        }
+        "naddr" => {
+            let tlv = Tlv::parse(&data)?;
+            Some(Entity::Address {
+                identifier: String::from_utf8(tlv.special?.to_vec()).ok()?,
+                author: tlv.author?,
+                kind: tlv.kind?,
+                relays: tlv.relays,
+            })
+        }
+        _ => None,
+    }
+}
+
+pub fn npub(public_key: &XOnlyPublicKey) -> String {
+    encode("npub", &public_key.serialize())
+}
+
+#[derive(Default)]
+struct Tlv<'a> {
+    special: Option<&'a [u8]>,
+    relays: Vec<String>,
+    author: Option<XOnlyPublicKey>,
+    kind: Option<u32>,
+}
+
+impl<'a> Tlv<'a> {
+    /// Unknown types are ignored, as NIP-19 requires
+    fn parse(mut data: &'a [u8]) -> Option<Self> {
+        let mut tlv = Self::default();
+        while !data.is_empty() {
+            let (t, len) = (*data.first()?, *data.get(1)? as usize);
+            let value = data.get(2..2 + len)?;
+            match t {
+                TLV_SPECIAL if tlv.special.is_none() => tlv.special = Some(value),
+                TLV_RELAY => {
+                    if let Ok(relay) = String::from_utf8(value.to_vec()) {
+                        tlv.relays.push(relay);
+                    }
+                }
+                TLV_AUTHOR => tlv.author = XOnlyPublicKey::from_slice(value).ok(),
+                TLV_KIND => tlv.kind = value.try_into().ok().map(u32::from_be_bytes),
+                _ => (),
+            }
+            data = &data[2 + len..];
+        }
+        Some(tlv)
+    }
+}
+
+fn tlv(out: &mut Vec<u8>, t: u8, value: &[u8]) {
+    out.push(t);
+    out.push(value.len() as u8);
+    out.extend_from_slice(value);
+}
+
+/// `nevent` of an event id, with relay hints
+pub fn nevent(
+    id: &str,
+    relays: &[String],
+    author: Option<&XOnlyPublicKey>,
+    kind: Option<u32>,
+) -> Option<String> {
+    let id = from_hex(id).filter(|id| id.len() == 32)?;
+    let mut data = Vec::new();
+    tlv(&mut data, TLV_SPECIAL, &id);
+    for relay in relays.iter().filter(|r| r.len() <= u8::MAX as usize) {
+        tlv(&mut data, TLV_RELAY, relay.as_bytes());
+    }
+    if let Some(author) = author {
+        tlv(&mut data, TLV_AUTHOR, &author.serialize());
+    }
+    if let Some(kind) = kind {
+        tlv(&mut data, TLV_KIND, &kind.to_be_bytes());
+    }
+    Some(encode("nevent", &data))
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-28 public chat channels
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+use super::raw::{now, RawEvent};
+
+pub const CHANNEL_CREATION_KIND: u64 = 40;
+pub const CHANNEL_METADATA_KIND: u64 = 41;
+pub const CHANNEL_MESSAGE_KIND: u64 = 42;
+pub const CHANNEL_HIDE_MESSAGE_KIND: u64 = 43;
+pub const CHANNEL_MUTE_USER_KIND: u64 = 44;
+/// NIP-51 list of the public chats a user joined
+pub const PUBLIC_CHATS_KIND: u64 = 10005;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMetadata {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub about: Option<String>,
+    #[serde(default)]
+    pub picture: Option<String>,
+}
+
+impl ChannelMetadata {
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+}
+
+/// `["e", <id>, <relay>, <marker>]` tag with the given NIP-10 marker, if any
+pub fn marked_tag(raw: &RawEvent, marker: &str) -> Option<String> {
+    raw.tags
+        .iter()
+        .find(|t| {
+            t.first().map(|n| n == "e").unwrap_or(false)
+                && t.get(3).map(|m| m == marker).unwrap_or(false)
+        })
+        .and_then(|t| t.get(1).cloned())
+}
+
+/// Channel an event belongs to: the `root` marked tag, or the first `e` tag for
+/// clients that don't use markers
+pub fn channel_id(raw: &RawEvent) -> Option<String> {
+    marked_tag(raw, "root").or_else(|| raw.tag_values("e").next().map(String::from))
+}
+
+/// Kind 42 message in `channel_id`, optionally replying to `reply_to` from `reply_author`
+pub fn message(
+    author: XOnlyPublicKey,
+    channel_id: &str,
+    reply_to: Option<(&str, XOnlyPublicKey)>,
+    content: String,
+) -> RawEvent {
+    let mut tags = vec![e_tag(channel_id, "root")];
+    if let Some((id, public_key)) = reply_to {
+        tags.push(e_tag(id, "reply"));
+        tags.push(vec![String::from("p"), public_key.to_string()]);
+    }
+    RawEvent::new(author, now(), CHANNEL_MESSAGE_KIND, tags, content)
+}
+
+pub fn hide_message(author: XOnlyPublicKey, message_id: &str) -> RawEvent {
+    RawEvent::new(
+        author,
+        now(),
+        CHANNEL_HIDE_MESSAGE_KIND,
+        vec![vec![String::from("e"), message_id.to_string()]],
+        String::new(),
+    )
+}
+
+pub fn mute_user(author: XOnlyPublicKey, public_key: XOnlyPublicKey) -> RawEvent {
+    RawEvent::new(
+        author,
+        now(),
+        CHANNEL_MUTE_USER_KIND,
+        vec![vec![String::from("p"), public_key.to_string()]],
+        String::new(),
+    )
+}
+
+/// Replaceable list of joined channels
+pub fn public_chats<'a, I>(author: XOnlyPublicKey, channel_ids: I) -> RawEvent
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let tags = channel_ids
+        .into_iter()
+        .map(|id| vec![String::from("e"), id.clone()])
+        .collect();
+    RawEvent::new(author, now(), PUBLIC_CHATS_KIND, tags, String::new())
+}
+
+fn e_tag(id: &str, marker: &str) -> Vec<String> {
+    vec![
+        String::from("e"),
+        id.to_string(),
+        String::new(),
+        marker.to_string(),
+    ]
+}
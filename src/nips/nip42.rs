@@ -0,0 +1,20 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-42: authentication of clients to relays
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+
+use super::raw::{now, RawEvent};
+
+pub const AUTH_KIND: u64 = 22242;
+
+/// Build the kind 22242 answering `challenge` from `relay`
+pub fn auth_event(author: XOnlyPublicKey, relay: &Url, challenge: &str) -> RawEvent {
+    let tags = vec![
+        vec![String::from("relay"), relay.to_string()],
+        vec![String::from("challenge"), challenge.to_string()],
+    ];
+    RawEvent::new(author, now(), AUTH_KIND, tags, String::new())
+}
@@ -0,0 +1,337 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-44 (v2) payload encryption
+
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use nostr_sdk::nostr::secp256k1::{ecdh, Parity, PublicKey, SecretKey, XOnlyPublicKey};
+use sha2::Sha256;
+
+const VERSION: u8 = 0x02;
+const SALT: &[u8] = b"nip44-v2";
+const MIN_PLAINTEXT_SIZE: usize = 1;
+const MAX_PLAINTEXT_SIZE: usize = 65535;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidPlaintextLength,
+    InvalidPayload,
+    UnknownVersion(u8),
+    InvalidMac,
+    InvalidPadding,
+    Utf8,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidPlaintextLength => write!(f, "invalid plaintext length"),
+            Self::InvalidPayload => write!(f, "invalid payload"),
+            Self::UnknownVersion(v) => write!(f, "unknown version: {}", v),
+            Self::InvalidMac => write!(f, "invalid MAC"),
+            Self::InvalidPadding => write!(f, "invalid padding"),
+            Self::Utf8 => write!(f, "plaintext is not valid UTF-8"),
+        }
+    }
+}
+
+/// Conversation key shared by `secret_key` and `public_key`
+pub fn conversation_key(secret_key: &SecretKey, public_key: &XOnlyPublicKey) -> [u8; 32] {
+    let public_key = PublicKey::from_x_only_public_key(*public_key, Parity::Even);
+    let point = ecdh::shared_secret_point(&public_key, secret_key);
+    let (prk, _) = Hkdf::<Sha256>::extract(Some(SALT), &point[..32]);
+    prk.into()
+}
+
+pub fn encrypt(
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    plaintext: &str,
+) -> Result<String, Error> {
+    let nonce: [u8; 32] = rand::random();
+    encrypt_with_nonce(&conversation_key(secret_key, public_key), &nonce, plaintext)
+}
+
+pub fn decrypt(
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    payload: &str,
+) -> Result<String, Error> {
+    decrypt_with_conversation_key(&conversation_key(secret_key, public_key), payload)
+}
+
+fn encrypt_with_nonce(
+    conversation_key: &[u8; 32],
+    nonce: &[u8; 32],
+    plaintext: &str,
+) -> Result<String, Error> {
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, nonce);
+
+    let mut buffer = pad(plaintext.as_bytes())?;
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut buffer);
+    let mac = hmac_aad(&hmac_key, &buffer, nonce);
+
+    let mut payload = Vec::with_capacity(1 + nonce.len() + buffer.len() + mac.len());
+    payload.push(VERSION);
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(&buffer);
+    payload.extend_from_slice(&mac);
+    Ok(BASE64.encode(payload))
+}
+
+fn decrypt_with_conversation_key(
+    conversation_key: &[u8; 32],
+    payload: &str,
+) -> Result<String, Error> {
+    if payload.is_empty() || payload.starts_with('#') {
+        return Err(Error::InvalidPayload);
+    }
+
+    let data = BASE64.decode(payload).map_err(|_| Error::InvalidPayload)?;
+    // version (1) + nonce (32) + min padded plaintext (2 + 32) + mac (32)
+    if data.len() < 99 || data.len() > 65603 {
+        return Err(Error::InvalidPayload);
+    }
+    if data[0] != VERSION {
+        return Err(Error::UnknownVersion(data[0]));
+    }
+
+    let nonce: [u8; 32] = data[1..33].try_into().map_err(|_| Error::InvalidPayload)?;
+    let ciphertext = &data[33..data.len() - 32];
+    let mac = &data[data.len() - 32..];
+
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, &nonce);
+
+    let mut verifier =
+        <Hmac<Sha256> as Mac>::new_from_slice(&hmac_key).map_err(|_| Error::InvalidMac)?;
+    verifier.update(&nonce);
+    verifier.update(ciphertext);
+    verifier.verify_slice(mac).map_err(|_| Error::InvalidMac)?;
+
+    let mut buffer = ciphertext.to_vec();
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut buffer);
+    let plaintext = unpad(&buffer)?;
+    String::from_utf8(plaintext.to_vec()).map_err(|_| Error::Utf8)
+}
+
+fn message_keys(conversation_key: &[u8; 32], nonce: &[u8; 32]) -> ([u8; 32], [u8; 12], [u8; 32]) {
+    let mut okm = [0u8; 76];
+    Hkdf::<Sha256>::from_prk(conversation_key)
+        .expect("32 bytes is a valid PRK length")
+        .expand(nonce, &mut okm)
+        .expect("76 bytes is a valid output length");
+
+    let mut chacha_key = [0u8; 32];
+    let mut chacha_nonce = [0u8; 12];
+    let mut hmac_key = [0u8; 32];
+    chacha_key.copy_from_slice(&okm[0..32]);
+    chacha_nonce.copy_from_slice(&okm[32..44]);
+    hmac_key.copy_from_slice(&okm[44..76]);
+    (chacha_key, chacha_nonce, hmac_key)
+}
+
+fn hmac_aad(key: &[u8; 32], message: &[u8], aad: &[u8; 32]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key size");
+    mac.update(aad);
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+fn calc_padded_len(len: usize) -> usize {
+    if len <= 32 {
+        return 32;
+    }
+    let next_power = 1 << (usize::BITS - (len - 1).leading_zeros());
+    let chunk = if next_power <= 256 {
+        32
+    } else {
+        next_power / 8
+    };
+    chunk * ((len - 1) / chunk + 1)
+}
+
+fn pad(plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let len = plaintext.len();
+    if !(MIN_PLAINTEXT_SIZE..=MAX_PLAINTEXT_SIZE).contains(&len) {
+        return Err(Error::InvalidPlaintextLength);
+    }
+    let mut padded = Vec::with_capacity(2 + calc_padded_len(len));
+    padded.extend_from_slice(&(len as u16).to_be_bytes());
+    padded.extend_from_slice(plaintext);
+    padded.resize(2 + calc_padded_len(len), 0);
+    Ok(padded)
+}
+
+fn unpad(padded: &[u8]) -> Result<&[u8], Error> {
+    if padded.len() < 2 {
+        return Err(Error::InvalidPadding);
+    }
+    let len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+    if len < MIN_PLAINTEXT_SIZE || padded.len() != 2 + calc_padded_len(len) {
+        return Err(Error::InvalidPadding);
+    }
+    Ok(&padded[2..2 + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::nips::raw::from_hex;
+
+    // Vectors from https://github.com/paulmillr/nip44 (nip44.vectors.json)
+
+    fn hex32(s: &str) -> [u8; 32] {
+        from_hex(s).unwrap().try_into().unwrap()
+    }
+
+    fn public_key(secret_key: &SecretKey) -> XOnlyPublicKey {
+        let secp = nostr_sdk::nostr::secp256k1::Secp256k1::new();
+        secret_key.x_only_public_key(&secp).0
+    }
+
+    #[test]
+    fn conversation_key_vectors() {
+        let vectors = [
+            (
+                "315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+                "3dfef0ce2a4d80a25e7a328accf73448ef67096f65f79588e358d9a0eb9013f1",
+            ),
+            (
+                "a1e37752c9fdc1273be53f68c5f74be7c8905728e8de75800b94262f9497c86e",
+                "03bb7947065dde12ba991ea045132581d0954f042c84e06d8c00066e23c1a800",
+                "4d14f36e81b8452128da64fe6f1eae873baae2f444b02c950b90e43553f2178b",
+            ),
+        ];
+        for (sec1, pub2, expected) in vectors.into_iter() {
+            let secret_key = SecretKey::from_str(sec1).unwrap();
+            let public_key = XOnlyPublicKey::from_str(pub2).unwrap();
+            assert_eq!(conversation_key(&secret_key, &public_key), hex32(expected));
+        }
+    }
+
+    #[test]
+    fn message_keys_vector() {
+        let (chacha_key, chacha_nonce, hmac_key) = message_keys(
+            &hex32("a1a3d60f3470a8612633924e91febf96dc5366ce130f658b1f0fc652c20b3b54"),
+            &hex32("e1e6f880560d6d149ed83dcc7e5861ee62a5ee051f7fde9975fe5d25d2a02d72"),
+        );
+        assert_eq!(
+            chacha_key,
+            hex32("f145f3bed47cb70dbeaac07f3a3fe683e822b3715edb7c4fe310829014ce7d76")
+        );
+        assert_eq!(
+            chacha_nonce,
+            [0xc4, 0xad, 0x12, 0x9b, 0xb0, 0x11, 0x80, 0xc0, 0x93, 0x3a, 0x16, 0x0c]
+        );
+        assert_eq!(
+            hmac_key,
+            hex32("027c1db445f05e2eee864a0975b0ddef5b7110583c8c192de3732571ca5838c4")
+        );
+    }
+
+    #[test]
+    fn padded_length_vectors() {
+        let vectors = [
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+            (65536, 65536),
+        ];
+        for (len, padded) in vectors.into_iter() {
+            assert_eq!(calc_padded_len(len), padded, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn encrypt_decrypt_vectors() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "a",
+                "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "f00000000000000000000000000000f00000000000000000000000000000000f",
+                "🍕🫃",
+                "AvAAAAAAAAAAAAAAAAAAAPAAAAAAAAAAAAAAAAAAAAAPSKSK6is9ngkX2+cSq85Th16oRTISAOfhStnixqZziKMDvB0QQzgFZdjLTPicCJaV8nDITO+QfaQ61+KbWQIOO2Yj",
+            ),
+        ];
+        for (sec1, sec2, key, nonce, plaintext, payload) in vectors.into_iter() {
+            let sec1 = SecretKey::from_str(sec1).unwrap();
+            let sec2 = SecretKey::from_str(sec2).unwrap();
+            let key = hex32(key);
+            assert_eq!(conversation_key(&sec1, &public_key(&sec2)), key);
+            assert_eq!(conversation_key(&sec2, &public_key(&sec1)), key);
+            assert_eq!(
+                encrypt_with_nonce(&key, &hex32(nonce), plaintext).unwrap(),
+                payload
+            );
+            assert_eq!(
+                decrypt(&sec2, &public_key(&sec1), payload).unwrap(),
+                plaintext
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_and_tampering() {
+        let sec1 = SecretKey::from_str(&"01".repeat(32)).unwrap();
+        let sec2 = SecretKey::from_str(&"02".repeat(32)).unwrap();
+        let plaintext = "x".repeat(1000);
+        let payload = encrypt(&sec1, &public_key(&sec2), &plaintext).unwrap();
+        assert_eq!(
+            decrypt(&sec2, &public_key(&sec1), &payload).unwrap(),
+            plaintext
+        );
+
+        let mut data = BASE64.decode(&payload).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert_eq!(
+            decrypt(&sec2, &public_key(&sec1), &BASE64.encode(data)),
+            Err(Error::InvalidMac)
+        );
+        assert_eq!(
+            encrypt(&sec1, &public_key(&sec2), ""),
+            Err(Error::InvalidPlaintextLength)
+        );
+    }
+}
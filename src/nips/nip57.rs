@@ -0,0 +1,71 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-57 zap receipts
+
+use super::raw::RawEvent;
+
+/// Millisatoshis in a bitcoin
+const MSATS_PER_BTC: u64 = 100_000_000_000;
+
+/// Amount paid, in millisatoshis, read from the `bolt11` invoice of a zap receipt. The
+/// `amount` tag of the zap request is what the zapper asked for, not what was paid.
+pub fn receipt_amount(receipt: &RawEvent) -> Option<u64> {
+    receipt.tag_values("bolt11").next().and_then(invoice_amount)
+}
+
+/// Amount of a BOLT-11 invoice in millisatoshis, `None` if it has no amount
+pub fn invoice_amount(invoice: &str) -> Option<u64> {
+    let invoice = invoice.trim().to_lowercase();
+    let invoice = invoice.strip_prefix("lightning:").unwrap_or(&invoice);
+    // The human readable part ends at the last `1`: "ln" + currency + amount
+    let hrp = &invoice[..invoice.rfind('1')?];
+    let rest = hrp.strip_prefix("ln")?;
+    let amount = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    if amount.is_empty() {
+        return None;
+    }
+
+    let (digits, multiplier) = match amount.chars().last()? {
+        c if c.is_ascii_digit() => (amount, None),
+        c => (&amount[..amount.len() - 1], Some(c)),
+    };
+    let value: u64 = digits.parse().ok()?;
+    match multiplier {
+        None => value.checked_mul(MSATS_PER_BTC),
+        Some('m') => value.checked_mul(MSATS_PER_BTC / 1_000),
+        Some('u') => value.checked_mul(MSATS_PER_BTC / 1_000_000),
+        Some('n') => value.checked_mul(MSATS_PER_BTC / 1_000_000_000),
+        // A pico-bitcoin is a tenth of a millisatoshi
+        Some('p') if value % 10 == 0 => Some(value / 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invoice_amounts() {
+        // Examples of the BOLT-11 specification
+        assert_eq!(
+            invoice_amount("lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh"),
+            Some(250_000_000)
+        );
+        assert_eq!(
+            invoice_amount(
+                "lnbc20m1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypq"
+            ),
+            Some(2_000_000_000)
+        );
+        assert_eq!(invoice_amount("LNBC10N1PVJLUEZ"), Some(1_000));
+        assert_eq!(invoice_amount("lntb1500p1abc"), Some(150));
+        assert_eq!(
+            invoice_amount("lnbc1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypq"),
+            None
+        );
+        assert_eq!(invoice_amount("lnbc15p1abc"), None);
+        assert_eq!(invoice_amount("not an invoice"), None);
+    }
+}
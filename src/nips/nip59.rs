@@ -0,0 +1,172 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-59 seals and gift wraps
+
+use std::fmt;
+
+use nostr_sdk::nostr::secp256k1::{Secp256k1, SecretKey, XOnlyPublicKey};
+use nostr_sdk::nostr::Event;
+
+use super::nip44;
+use super::raw::{self, now, RawEvent};
+
+pub const SEAL_KIND: u64 = 13;
+pub const GIFT_WRAP_KIND: u64 = 1059;
+
+/// Seals and gift wraps are backdated by up to two days to hide the real send time
+pub const MAX_TWEAK: u64 = 2 * 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum Error {
+    Nip44(nip44::Error),
+    Raw(raw::Error),
+    InvalidKind(u64),
+    SenderMismatch,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Nip44(e) => write!(f, "NIP-44: {}", e),
+            Self::Raw(e) => write!(f, "{}", e),
+            Self::InvalidKind(kind) => write!(f, "unexpected kind {}", kind),
+            Self::SenderMismatch => write!(f, "seal and rumor authors differ"),
+        }
+    }
+}
+
+impl From<nip44::Error> for Error {
+    fn from(e: nip44::Error) -> Self {
+        Self::Nip44(e)
+    }
+}
+
+impl From<raw::Error> for Error {
+    fn from(e: raw::Error) -> Self {
+        Self::Raw(e)
+    }
+}
+
+/// Seal `rumor` for `receiver` and wrap it with a one-time key
+pub fn gift_wrap(
+    sender: &SecretKey,
+    receiver: &XOnlyPublicKey,
+    rumor: &RawEvent,
+) -> Result<Event, Error> {
+    let secp = Secp256k1::new();
+
+    let seal = RawEvent::new(
+        sender.x_only_public_key(&secp).0,
+        tweaked_now(),
+        SEAL_KIND,
+        Vec::new(),
+        nip44::encrypt(sender, receiver, &rumor.as_json())?,
+    )
+    .sign(sender)?;
+
+    let (ephemeral, _) = secp.generate_keypair(&mut rand::thread_rng());
+    let wrap = RawEvent::new(
+        ephemeral.x_only_public_key(&secp).0,
+        tweaked_now(),
+        GIFT_WRAP_KIND,
+        vec![vec![String::from("p"), receiver.to_string()]],
+        nip44::encrypt(&ephemeral, receiver, &seal.as_json())?,
+    );
+    Ok(wrap.sign(&ephemeral)?)
+}
+
+/// Open a gift wrap addressed to `receiver`, returning the verified rumor
+pub fn unwrap(receiver: &SecretKey, gift_wrap: &Event) -> Result<RawEvent, Error> {
+    let wrap = RawEvent::from_event(gift_wrap)?;
+    if wrap.kind != GIFT_WRAP_KIND {
+        return Err(Error::InvalidKind(wrap.kind));
+    }
+
+    let seal = RawEvent::from_json(&nip44::decrypt(receiver, &wrap.pubkey()?, &wrap.content)?)?;
+    if seal.kind != SEAL_KIND {
+        return Err(Error::InvalidKind(seal.kind));
+    }
+    seal.verify()?;
+
+    let rumor = RawEvent::from_json(&nip44::decrypt(receiver, &seal.pubkey()?, &seal.content)?)?;
+    rumor.verify_id()?;
+    if rumor.pubkey != seal.pubkey {
+        return Err(Error::SenderMismatch);
+    }
+
+    Ok(rumor)
+}
+
+fn tweaked_now() -> u64 {
+    now().saturating_sub(rand::random::<u64>() % MAX_TWEAK)
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr_sdk::nostr::secp256k1::Secp256k1;
+
+    use super::*;
+
+    fn keys() -> (SecretKey, XOnlyPublicKey) {
+        let secp = Secp256k1::new();
+        let (secret_key, _) = secp.generate_keypair(&mut rand::thread_rng());
+        let public_key = secret_key.x_only_public_key(&secp).0;
+        (secret_key, public_key)
+    }
+
+    #[test]
+    fn wrap_unwrap_round_trip() {
+        let (sender, sender_pk) = keys();
+        let (receiver, receiver_pk) = keys();
+        let rumor = RawEvent::new(
+            sender_pk,
+            now(),
+            14,
+            vec![vec![String::from("p"), receiver_pk.to_string()]],
+            String::from("hello"),
+        );
+
+        let wrap = gift_wrap(&sender, &receiver_pk, &rumor).unwrap();
+        assert_eq!(wrap.kind, nostr_sdk::nostr::Kind::from(GIFT_WRAP_KIND));
+        assert_ne!(wrap.pubkey, sender_pk);
+        assert!(wrap.created_at <= now() && wrap.created_at + MAX_TWEAK >= now());
+
+        let opened = unwrap(&receiver, &wrap).unwrap();
+        assert_eq!(opened.id, rumor.id);
+        assert_eq!(opened.pubkey, rumor.pubkey);
+        assert_eq!(opened.content, "hello");
+        assert_eq!(opened.tags, rumor.tags);
+
+        let (stranger, _) = keys();
+        assert!(matches!(unwrap(&stranger, &wrap), Err(Error::Nip44(_))));
+    }
+
+    #[test]
+    fn rumor_must_come_from_the_seal_author() {
+        let (sender, _) = keys();
+        let (_, impersonated) = keys();
+        let (receiver, receiver_pk) = keys();
+        let rumor = RawEvent::new(impersonated, now(), 14, Vec::new(), String::from("hi"));
+
+        let wrap = gift_wrap(&sender, &receiver_pk, &rumor).unwrap();
+        assert!(matches!(
+            unwrap(&receiver, &wrap),
+            Err(Error::SenderMismatch)
+        ));
+    }
+
+    #[test]
+    fn only_gift_wraps_are_opened() {
+        let (secret_key, public_key) = keys();
+        let note = RawEvent::new(public_key, now(), 1, Vec::new(), String::from("note"))
+            .sign(&secret_key)
+            .unwrap();
+        assert!(matches!(
+            unwrap(&secret_key, &note),
+            Err(Error::InvalidKind(1))
+        ));
+    }
+}
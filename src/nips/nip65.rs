@@ -0,0 +1,102 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-65: relay list metadata
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{Kind, SubscriptionFilter};
+use nostr_sdk::Client;
+use serde::{Deserialize, Serialize};
+
+use super::raw::{now, RawEvent};
+
+pub const RELAY_LIST_KIND: u64 = 10002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RelayPolicy {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Default for RelayPolicy {
+    fn default() -> Self {
+        Self {
+            read: true,
+            write: true,
+        }
+    }
+}
+
+impl RelayPolicy {
+    fn marker(&self) -> Option<&'static str> {
+        match (self.read, self.write) {
+            (true, false) => Some("read"),
+            (false, true) => Some("write"),
+            _ => None,
+        }
+    }
+}
+
+/// Relays of a kind 10002 event. An `r` tag without marker means read and write.
+pub fn relay_list(raw: &RawEvent) -> Vec<(Url, RelayPolicy)> {
+    raw.tags
+        .iter()
+        .filter(|t| t.first().map(|n| n == "r").unwrap_or(false))
+        .filter_map(|t| {
+            let url = Url::parse(t.get(1)?).ok()?;
+            let policy = match t.get(2).map(|m| m.as_str()) {
+                Some("read") => RelayPolicy {
+                    read: true,
+                    write: false,
+                },
+                Some("write") => RelayPolicy {
+                    read: false,
+                    write: true,
+                },
+                _ => RelayPolicy::default(),
+            };
+            Some((url, policy))
+        })
+        .collect()
+}
+
+/// Build a kind 10002 event. Relays neither read nor written are left out.
+pub fn relay_list_event(author: XOnlyPublicKey, relays: &[(Url, RelayPolicy)]) -> RawEvent {
+    let tags = relays
+        .iter()
+        .filter(|(_, p)| p.read || p.write)
+        .map(|(url, policy)| {
+            let mut tag = vec![String::from("r"), url.to_string()];
+            if let Some(marker) = policy.marker() {
+                tag.push(marker.to_string());
+            }
+            tag
+        })
+        .collect();
+    RawEvent::new(author, now(), RELAY_LIST_KIND, tags, String::new())
+}
+
+/// Latest kind 10002 of each author
+pub async fn get_relay_lists(
+    client: &Client,
+    authors: Vec<XOnlyPublicKey>,
+) -> nostr_sdk::Result<Vec<RawEvent>> {
+    let filter = SubscriptionFilter::new()
+        .authors(authors)
+        .kind(Kind::from(RELAY_LIST_KIND));
+    let mut latest: Vec<RawEvent> = Vec::new();
+    for raw in client
+        .get_events_of(vec![filter])
+        .await?
+        .iter()
+        .filter_map(|e| RawEvent::from_event(e).ok())
+    {
+        match latest.iter_mut().find(|r| r.pubkey == raw.pubkey) {
+            Some(existing) if existing.created_at < raw.created_at => *existing = raw,
+            Some(_) => (),
+            None => latest.push(raw),
+        }
+    }
+    Ok(latest)
+}
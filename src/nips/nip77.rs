@@ -0,0 +1,681 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-77: negentropy set reconciliation (protocol version 1), initiator side
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use super::raw::from_hex;
+
+const PROTOCOL_VERSION: u8 = 0x61;
+const ID_SIZE: usize = 32;
+const FINGERPRINT_SIZE: usize = 16;
+/// Ranges a mismatching range is split into
+const BUCKETS: usize = 16;
+
+const MODE_SKIP: u64 = 0;
+const MODE_FINGERPRINT: u64 = 1;
+const MODE_ID_LIST: u64 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    UnsupportedVersion(u8),
+    Malformed,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(v) => write!(f, "unsupported protocol version 0x{:02x}", v),
+            Self::Malformed => write!(f, "malformed message"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Item {
+    pub timestamp: u64,
+    pub id: [u8; ID_SIZE],
+}
+
+impl Item {
+    pub fn new(timestamp: u64, id: &str) -> Option<Self> {
+        Some(Self {
+            timestamp,
+            id: from_hex(id)?.try_into().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bound {
+    timestamp: u64,
+    prefix: Vec<u8>,
+}
+
+impl Bound {
+    fn infinity() -> Self {
+        Self {
+            timestamp: u64::MAX,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// Whether `item` sorts before the bound
+    fn is_above(&self, item: &Item) -> bool {
+        match item.timestamp.cmp(&self.timestamp) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => {
+                let mut padded = [0u8; ID_SIZE];
+                padded[..self.prefix.len()].copy_from_slice(&self.prefix);
+                item.id < padded
+            }
+        }
+    }
+
+    /// Shortest bound separating `prev` from `curr`
+    fn between(prev: &Item, curr: &Item) -> Self {
+        if prev.timestamp != curr.timestamp {
+            return Self {
+                timestamp: curr.timestamp,
+                prefix: Vec::new(),
+            };
+        }
+        let shared = prev
+            .id
+            .iter()
+            .zip(curr.id.iter())
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(ID_SIZE - 1);
+        Self {
+            timestamp: curr.timestamp,
+            prefix: curr.id[..=shared].to_vec(),
+        }
+    }
+}
+
+fn encode_varint(mut n: u64) -> Vec<u8> {
+    let mut bytes = vec![(n & 0x7f) as u8];
+    n >>= 7;
+    while n > 0 {
+        bytes.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// Sum of the ids as 256-bit little-endian integers, hashed with the count
+fn fingerprint(items: &[Item]) -> [u8; FINGERPRINT_SIZE] {
+    let mut sum = [0u8; ID_SIZE];
+    for item in items.iter() {
+        let mut carry = 0u16;
+        for (s, b) in sum.iter_mut().zip(item.id.iter()) {
+            let total = *s as u16 + *b as u16 + carry;
+            *s = total as u8;
+            carry = total >> 8;
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(sum);
+    hasher.update(encode_varint(items.len() as u64));
+    let mut fingerprint = [0u8; FINGERPRINT_SIZE];
+    fingerprint.copy_from_slice(&hasher.finalize()[..FINGERPRINT_SIZE]);
+    fingerprint
+}
+
+struct Writer {
+    buf: Vec<u8>,
+    last_timestamp: u64,
+    /// Skips are only written when a later range needs them
+    pending_skip: Option<Bound>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self {
+            buf: vec![PROTOCOL_VERSION],
+            last_timestamp: 0,
+            pending_skip: None,
+        }
+    }
+
+    fn varint(&mut self, n: u64) {
+        self.buf.extend(encode_varint(n));
+    }
+
+    fn bound(&mut self, bound: &Bound) {
+        if bound.timestamp == u64::MAX {
+            self.last_timestamp = u64::MAX;
+            self.varint(0);
+        } else {
+            let delta = bound.timestamp.saturating_sub(self.last_timestamp);
+            self.last_timestamp = bound.timestamp;
+            self.varint(delta + 1);
+        }
+        self.varint(bound.prefix.len() as u64);
+        self.buf.extend_from_slice(&bound.prefix);
+    }
+
+    fn skip(&mut self, upper: Bound) {
+        self.pending_skip = Some(upper);
+    }
+
+    fn range(&mut self, upper: &Bound, mode: u64) {
+        if let Some(skip) = self.pending_skip.take() {
+            self.bound(&skip);
+            self.varint(MODE_SKIP);
+        }
+        self.bound(upper);
+        self.varint(mode);
+    }
+
+    /// `None` when there's nothing left to reconcile
+    fn finish(self) -> Option<Vec<u8>> {
+        if self.buf.len() > 1 {
+            Some(self.buf)
+        } else {
+            None
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    last_timestamp: u64,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.buf.len() {
+            return Err(Error::Malformed);
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut n: u64 = 0;
+        loop {
+            let byte = self.bytes(1)?[0];
+            if n > u64::MAX >> 7 {
+                return Err(Error::Malformed);
+            }
+            n = (n << 7) | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+    }
+
+    fn bound(&mut self) -> Result<Bound, Error> {
+        let timestamp = match self.varint()? {
+            0 => u64::MAX,
+            delta => self.last_timestamp.saturating_add(delta - 1),
+        };
+        self.last_timestamp = timestamp;
+        let len = self.varint()? as usize;
+        if len > ID_SIZE {
+            return Err(Error::Malformed);
+        }
+        Ok(Bound {
+            timestamp,
+            prefix: self.bytes(len)?.to_vec(),
+        })
+    }
+}
+
+/// Local side of a reconciliation
+pub struct Negentropy {
+    items: Vec<Item>,
+}
+
+impl Negentropy {
+    pub fn new(mut items: Vec<Item>) -> Self {
+        items.sort();
+        items.dedup();
+        Self { items }
+    }
+
+    /// First message, covering the whole set
+    pub fn initiate(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.split(&mut writer, &self.items, Bound::infinity());
+        writer.buf
+    }
+
+    /// Process a message from the relay, adding the ids only the relay has to `need`.
+    /// Returns the next message, or `None` once the sets are reconciled.
+    pub fn reconcile(
+        &self,
+        msg: &[u8],
+        need: &mut Vec<[u8; ID_SIZE]>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut reader = Reader {
+            buf: msg,
+            last_timestamp: 0,
+        };
+        let version = reader.bytes(1)?[0];
+        if version != PROTOCOL_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut writer = Writer::new();
+        let mut lower = 0;
+        while !reader.is_empty() {
+            let upper = reader.bound()?;
+            let end = lower
+                + self.items[lower..]
+                    .iter()
+                    .take_while(|item| upper.is_above(item))
+                    .count();
+            let range = &self.items[lower..end];
+
+            match reader.varint()? {
+                MODE_SKIP => writer.skip(upper),
+                MODE_FINGERPRINT => {
+                    if reader.bytes(FINGERPRINT_SIZE)? == fingerprint(range) {
+                        writer.skip(upper);
+                    } else {
+                        self.split(&mut writer, range, upper);
+                    }
+                }
+                MODE_ID_LIST => {
+                    let ours: HashSet<&[u8; ID_SIZE]> = range.iter().map(|i| &i.id).collect();
+                    let count = reader.varint()?;
+                    for _ in 0..count {
+                        let id: [u8; ID_SIZE] = reader
+                            .bytes(ID_SIZE)?
+                            .try_into()
+                            .map_err(|_| Error::Malformed)?;
+                        if !ours.contains(&id) {
+                            need.push(id);
+                        }
+                    }
+                    writer.skip(upper);
+                }
+                _ => return Err(Error::Malformed),
+            }
+
+            lower = end;
+        }
+
+        Ok(writer.finish())
+    }
+
+    /// Send small ranges as id lists, and split the others into fingerprinted buckets
+    fn split(&self, writer: &mut Writer, items: &[Item], upper: Bound) {
+        if items.len() < BUCKETS * 2 {
+            writer.range(&upper, MODE_ID_LIST);
+            writer.varint(items.len() as u64);
+            for item in items.iter() {
+                writer.buf.extend_from_slice(&item.id);
+            }
+            return;
+        }
+
+        let per_bucket = items.len() / BUCKETS;
+        let extra = items.len() % BUCKETS;
+        let mut start = 0;
+        for bucket in 0..BUCKETS {
+            let len = per_bucket + usize::from(bucket < extra);
+            let end = start + len;
+            let bound = if end == items.len() {
+                upper.clone()
+            } else {
+                Bound::between(&items[end - 1], &items[end])
+            };
+            writer.range(&bound, MODE_FINGERPRINT);
+            writer
+                .buf
+                .extend_from_slice(&fingerprint(&items[start..end]));
+            start = end;
+        }
+    }
+}
+
+#[cfg(test)]
+impl Negentropy {
+    /// Relay side of a round, as strfry answers: mismatching ranges are split and id
+    /// lists are answered with ours
+    pub(crate) fn respond(&self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut reader = Reader {
+            buf: msg,
+            last_timestamp: 0,
+        };
+        let version = reader.bytes(1)?[0];
+        if version != PROTOCOL_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut writer = Writer::new();
+        let mut lower = 0;
+        while !reader.is_empty() {
+            let upper = reader.bound()?;
+            let end = lower
+                + self.items[lower..]
+                    .iter()
+                    .take_while(|item| upper.is_above(item))
+                    .count();
+            let range = &self.items[lower..end];
+
+            match reader.varint()? {
+                MODE_SKIP => writer.skip(upper),
+                MODE_FINGERPRINT => {
+                    if reader.bytes(FINGERPRINT_SIZE)? == fingerprint(range) {
+                        writer.skip(upper);
+                    } else {
+                        self.split(&mut writer, range, upper);
+                    }
+                }
+                MODE_ID_LIST => {
+                    let count = reader.varint()? as usize;
+                    reader.bytes(count * ID_SIZE)?;
+                    writer.range(&upper, MODE_ID_LIST);
+                    writer.varint(range.len() as u64);
+                    for item in range.iter() {
+                        writer.buf.extend_from_slice(&item.id);
+                    }
+                }
+                _ => return Err(Error::Malformed),
+            }
+
+            lower = end;
+        }
+
+        Ok(writer.buf)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Deterministic item, `n` seconds after a fixed time
+    pub(crate) fn item(seed: u64, n: u64) -> Item {
+        let mut id = [0u8; ID_SIZE];
+        id.copy_from_slice(&Sha256::digest(seed.to_le_bytes()));
+        Item {
+            timestamp: 1_700_000_000 + n,
+            id,
+        }
+    }
+
+    /// Reconcile `local` against `relay` in memory, returning the ids we miss
+    fn reconcile_sets(local: Vec<Item>, relay: Vec<Item>) -> Vec<[u8; ID_SIZE]> {
+        let local = Negentropy::new(local);
+        let relay = Negentropy::new(relay);
+        let mut need = Vec::new();
+        let mut msg = local.initiate();
+        for _ in 0..32 {
+            let reply = relay.respond(&msg).unwrap();
+            match local.reconcile(&reply, &mut need).unwrap() {
+                Some(next) => msg = next,
+                None => {
+                    need.sort();
+                    return need;
+                }
+            }
+        }
+        panic!("reconciliation didn't converge");
+    }
+
+    fn ids(items: &[Item]) -> Vec<[u8; ID_SIZE]> {
+        let mut ids: Vec<[u8; ID_SIZE]> = items.iter().map(|i| i.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [
+            0,
+            1,
+            127,
+            128,
+            255,
+            16_383,
+            16_384,
+            1_700_000_000,
+            u64::MAX >> 1,
+        ];
+        for value in values {
+            let bytes = encode_varint(value);
+            let mut reader = Reader {
+                buf: &bytes,
+                last_timestamp: 0,
+            };
+            assert_eq!(reader.varint().unwrap(), value);
+            assert!(reader.is_empty());
+        }
+        assert_eq!(encode_varint(300), vec![0x82, 0x2c]);
+    }
+
+    #[test]
+    fn bound_round_trip() {
+        let bounds = [
+            Bound {
+                timestamp: 1_700_000_000,
+                prefix: Vec::new(),
+            },
+            Bound {
+                timestamp: 1_700_000_000,
+                prefix: vec![0xab, 0xcd],
+            },
+            Bound {
+                timestamp: 1_700_000_500,
+                prefix: vec![0x01; ID_SIZE],
+            },
+            Bound::infinity(),
+        ];
+        let mut writer = Writer::new();
+        for bound in bounds.iter() {
+            writer.bound(bound);
+        }
+
+        let mut reader = Reader {
+            buf: &writer.buf[1..],
+            last_timestamp: 0,
+        };
+        for bound in bounds.iter() {
+            let read = reader.bound().unwrap();
+            assert_eq!(read.timestamp, bound.timestamp);
+            assert_eq!(read.prefix, bound.prefix);
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn bound_between_items() {
+        let a = item(1, 0);
+        let mut b = a;
+        b.id[0] = a.id[0].wrapping_add(1);
+        b.id[1] = 0;
+        let bound = Bound::between(&a, &b);
+        assert_eq!(bound.prefix, b.id[..1].to_vec());
+        assert!(!bound.is_above(&b));
+        assert!(bound.is_above(&a));
+
+        let later = item(2, 10);
+        let bound = Bound::between(&a, &later);
+        assert_eq!(bound.timestamp, later.timestamp);
+        assert!(bound.prefix.is_empty());
+    }
+
+    #[test]
+    fn fingerprint_of_sets() {
+        let mut expected = [0u8; FINGERPRINT_SIZE];
+        let mut hasher = Sha256::new();
+        hasher.update([0u8; ID_SIZE]);
+        hasher.update([0u8]);
+        expected.copy_from_slice(&hasher.finalize()[..FINGERPRINT_SIZE]);
+        assert_eq!(fingerprint(&[]), expected);
+
+        let items: Vec<Item> = (0..10).map(|i| item(i, i)).collect();
+        let mut reversed = items.clone();
+        reversed.reverse();
+        assert_eq!(fingerprint(&items), fingerprint(&reversed));
+        assert_ne!(fingerprint(&items), fingerprint(&items[1..]));
+
+        // The sum carries across bytes and wraps at 2^256
+        let full = Item {
+            timestamp: 0,
+            id: [0xff; ID_SIZE],
+        };
+        let mut one = Item {
+            timestamp: 0,
+            id: [0; ID_SIZE],
+        };
+        one.id[0] = 1;
+        let mut hasher = Sha256::new();
+        hasher.update([0u8; ID_SIZE]);
+        hasher.update([2u8]);
+        expected.copy_from_slice(&hasher.finalize()[..FINGERPRINT_SIZE]);
+        assert_eq!(fingerprint(&[full, one]), expected);
+    }
+
+    #[test]
+    fn small_sets_are_sent_as_id_lists() {
+        let items: Vec<Item> = (0..5).map(|i| item(i, i)).collect();
+        let negentropy = Negentropy::new(items.clone());
+        let msg = negentropy.initiate();
+
+        let mut reader = Reader {
+            buf: &msg[1..],
+            last_timestamp: 0,
+        };
+        assert_eq!(msg[0], PROTOCOL_VERSION);
+        assert_eq!(reader.bound().unwrap().timestamp, u64::MAX);
+        assert_eq!(reader.varint().unwrap(), MODE_ID_LIST);
+        assert_eq!(reader.varint().unwrap(), 5);
+        let sent: Vec<[u8; ID_SIZE]> = (0..5)
+            .map(|_| reader.bytes(ID_SIZE).unwrap().try_into().unwrap())
+            .collect();
+        assert_eq!(
+            sent,
+            negentropy.items.iter().map(|i| i.id).collect::<Vec<_>>()
+        );
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn large_sets_are_split_in_buckets() {
+        let items: Vec<Item> = (0..100).map(|i| item(i, i / 3)).collect();
+        let negentropy = Negentropy::new(items);
+        let msg = negentropy.initiate();
+
+        let mut reader = Reader {
+            buf: &msg[1..],
+            last_timestamp: 0,
+        };
+        let mut lower = 0;
+        for bucket in 0..BUCKETS {
+            let upper = reader.bound().unwrap();
+            assert_eq!(reader.varint().unwrap(), MODE_FINGERPRINT);
+            let end = lower
+                + negentropy.items[lower..]
+                    .iter()
+                    .take_while(|item| upper.is_above(item))
+                    .count();
+            // 100 items in 16 buckets: the first 4 get 7, the others 6
+            assert_eq!(end - lower, if bucket < 4 { 7 } else { 6 });
+            assert_eq!(
+                reader.bytes(FINGERPRINT_SIZE).unwrap(),
+                fingerprint(&negentropy.items[lower..end])
+            );
+            lower = end;
+        }
+        assert_eq!(lower, 100);
+        assert!(reader.is_empty());
+    }
+
+    /// Messages encoded by hand from the message format of NIP-77, as a relay running
+    /// strfry's negentropy sends them, rather than with our own encoder
+    #[test]
+    fn reference_messages() {
+        let a = Item {
+            timestamp: 1_700_000_000,
+            id: [0x11; ID_SIZE],
+        };
+        let b = Item {
+            timestamp: 1_700_000_010,
+            id: [0x22; ID_SIZE],
+        };
+        let c = [0x33; ID_SIZE];
+
+        // Empty set: version, infinity bound without prefix, id list of no id
+        assert_eq!(
+            Negentropy::new(Vec::new()).initiate(),
+            from_hex("6100000200").unwrap()
+        );
+
+        // Small set: a single id list up to infinity, ids in order
+        let negentropy = Negentropy::new(vec![b, a]);
+        let expected = [
+            from_hex("6100000202").unwrap(),
+            a.id.to_vec(),
+            b.id.to_vec(),
+        ]
+        .concat();
+        assert_eq!(negentropy.initiate(), expected);
+
+        // The relay has a and c: the fingerprint of the range below b matches ours, and
+        // the range above is answered with its ids
+        let reply = [
+            from_hex("61").unwrap(),
+            // Bound at b's timestamp (delta + 1 from 0), no prefix, fingerprint of {a}
+            from_hex("86aacfe20b0001").unwrap(),
+            from_hex("8fd3214da593ffd1bcde5420d6bd5a64").unwrap(),
+            // Infinity bound, no prefix, id list of one id
+            from_hex("00000201").unwrap(),
+            c.to_vec(),
+        ]
+        .concat();
+        let mut need = Vec::new();
+        assert!(negentropy.reconcile(&reply, &mut need).unwrap().is_none());
+        assert_eq!(need, vec![c]);
+    }
+
+    #[test]
+    fn reconcile_finds_missing_items() {
+        let shared: Vec<Item> = (0..1000).map(|i| item(i, i / 10)).collect();
+        let only_relay: Vec<Item> = (1000..1040).map(|i| item(i, i % 100)).collect();
+        let only_local: Vec<Item> = (2000..2025).map(|i| item(i, i % 100)).collect();
+
+        let local = [shared.clone(), only_local.clone()].concat();
+        let relay = [shared.clone(), only_relay.clone()].concat();
+        assert_eq!(reconcile_sets(local.clone(), relay), ids(&only_relay));
+
+        // Same sets, nothing to download
+        assert!(reconcile_sets(local.clone(), local).is_empty());
+        // Empty side
+        assert_eq!(reconcile_sets(Vec::new(), shared.clone()), ids(&shared));
+        assert!(reconcile_sets(shared, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let negentropy = Negentropy::new(Vec::new());
+        assert!(matches!(
+            negentropy.reconcile(&[0x62], &mut Vec::new()),
+            Err(Error::UnsupportedVersion(0x62))
+        ));
+        assert!(matches!(
+            negentropy.reconcile(&[PROTOCOL_VERSION, 0x80], &mut Vec::new()),
+            Err(Error::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Events in their JSON wire format, for kinds the SDK doesn't model
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nostr_sdk::nostr::secp256k1::schnorr::Signature;
+use nostr_sdk::nostr::secp256k1::{KeyPair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use nostr_sdk::nostr::Event;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json::Error),
+    Secp256k1(nostr_sdk::nostr::secp256k1::Error),
+    InvalidId,
+    InvalidEvent,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "json: {}", e),
+            Self::Secp256k1(e) => write!(f, "secp256k1: {}", e),
+            Self::InvalidId => write!(f, "event id doesn't match its content"),
+            Self::InvalidEvent => write!(f, "invalid event"),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<nostr_sdk::nostr::secp256k1::Error> for Error {
+    fn from(e: nostr_sdk::nostr::secp256k1::Error) -> Self {
+        Self::Secp256k1(e)
+    }
+}
+
+/// Raw event. Rumors are unsigned, so `sig` is optional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<String>,
+}
+
+impl RawEvent {
+    /// Build an unsigned event with its id already computed
+    pub fn new(
+        pubkey: XOnlyPublicKey,
+        created_at: u64,
+        kind: u64,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Self {
+        let mut event = Self {
+            id: String::new(),
+            pubkey: pubkey.to_string(),
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: None,
+        };
+        event.id = to_hex(&event.compute_id());
+        event
+    }
+
+    pub fn from_event(event: &Event) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&event.as_json())?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn as_json(&self) -> String {
+        json!(self).to_string()
+    }
+
+    pub fn pubkey(&self) -> Result<XOnlyPublicKey, Error> {
+        Ok(self.pubkey.parse()?)
+    }
+
+    /// Values of every tag named `name`
+    pub fn tag_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        tag_values(&self.tags, name)
+    }
+
+    pub fn sign(mut self, secret_key: &SecretKey) -> Result<Event, Error> {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_secret_key(&secp, secret_key);
+        let message = Message::from_slice(&self.compute_id())?;
+        self.sig = Some(secp.sign_schnorr(&message, &keypair).to_string());
+        Event::from_json(self.as_json()).map_err(|_| Error::InvalidEvent)
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        let id = self.verify_id()?;
+        let sig: Signature = self.sig.as_deref().unwrap_or_default().parse()?;
+        let secp = Secp256k1::verification_only();
+        secp.verify_schnorr(&sig, &Message::from_slice(&id)?, &self.pubkey()?)?;
+        Ok(())
+    }
+
+    pub fn verify_id(&self) -> Result<[u8; 32], Error> {
+        let id = self.compute_id();
+        if to_hex(&id) == self.id {
+            Ok(id)
+        } else {
+            Err(Error::InvalidId)
+        }
+    }
+
+    fn compute_id(&self) -> [u8; 32] {
+        let serialized = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ]);
+        Sha256::digest(serialized.to_string().as_bytes()).into()
+    }
+}
+
+/// Values of every tag named `name`
+pub fn tag_values<'a>(tags: &'a [Vec<String>], name: &'a str) -> impl Iterator<Item = &'a str> {
+    tags.iter()
+        .filter(move |t| t.first().map(|n| n == name).unwrap_or(false))
+        .filter_map(|t| t.get(1).map(|v| v.as_str()))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
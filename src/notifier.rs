@@ -0,0 +1,554 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Desktop notifications
+//!
+//! Notifications go through the freedesktop D-Bus interface of the session bus, so they
+//! can be checked against a mock daemon by pointing `DBUS_SESSION_BUS_ADDRESS` at it.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::Event;
+use nostr_sdk::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::nips::nip59::{GIFT_WRAP_KIND, MAX_TWEAK};
+use crate::nips::raw::{self, RawEvent};
+
+/// At most `RATE_LIMIT` notifications are shown every `RATE_LIMIT_WINDOW`
+const RATE_LIMIT: usize = 5;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const PREVIEW_LEN: usize = 140;
+/// Number of handled event ids remembered to skip the ones relays send again
+const SEEN_CAPACITY: usize = 1024;
+
+const TEXT_NOTE_KIND: u64 = 1;
+const ENCRYPTED_DIRECT_MESSAGE_KIND: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    DirectMessage,
+    Mention,
+    Reply,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifierSettings {
+    pub direct_messages: bool,
+    pub mentions: bool,
+    pub replies: bool,
+    pub do_not_disturb: bool,
+}
+
+impl Default for NotifierSettings {
+    fn default() -> Self {
+        Self {
+            direct_messages: true,
+            mentions: true,
+            replies: true,
+            do_not_disturb: false,
+        }
+    }
+}
+
+impl NotifierSettings {
+    fn allows(&self, kind: NotificationKind) -> bool {
+        !self.do_not_disturb
+            && match kind {
+                NotificationKind::DirectMessage => self.direct_messages,
+                NotificationKind::Mention => self.mentions,
+                NotificationKind::Reply => self.replies,
+            }
+    }
+}
+
+pub trait Backend: Send + Sync {
+    fn show(&self, summary: &str, body: &str) -> Result<(), String>;
+}
+
+/// Freedesktop notifications over D-Bus
+pub struct Freedesktop;
+
+impl Backend for Freedesktop {
+    fn show(&self, summary: &str, body: &str) -> Result<(), String> {
+        notify_rust::Notification::new()
+            .appname(env!("CARGO_PKG_NAME"))
+            .summary(summary)
+            .body(body)
+            .show()
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Default)]
+struct RateLimiter {
+    sent: VecDeque<Instant>,
+    suppressed: usize,
+}
+
+impl RateLimiter {
+    /// Returns how many notifications were suppressed since the last one, or `None` if
+    /// this one must be suppressed too
+    fn check(&mut self, now: Instant) -> Option<usize> {
+        while let Some(t) = self.sent.front() {
+            if now.duration_since(*t) > RATE_LIMIT_WINDOW {
+                self.sent.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.sent.len() >= RATE_LIMIT {
+            self.suppressed += 1;
+            None
+        } else {
+            self.sent.push_back(now);
+            Some(std::mem::take(&mut self.suppressed))
+        }
+    }
+}
+
+#[derive(Default)]
+struct SeenIds {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl SeenIds {
+    /// Whether `id` wasn't seen yet, remembering it. The oldest id is forgotten past
+    /// `SEEN_CAPACITY`.
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.order.push_back(id.to_string());
+        self.ids.insert(id.to_string());
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    backend: Arc<dyn Backend>,
+    focused: Arc<AtomicBool>,
+    settings: Arc<Mutex<NotifierSettings>>,
+    limiter: Arc<Mutex<RateLimiter>>,
+    seen: Arc<Mutex<SeenIds>>,
+    /// Events created before are only history the relays send back
+    started_at: u64,
+}
+
+impl Notifier {
+    pub fn new(settings: NotifierSettings) -> Self {
+        Self::with_backend(Arc::new(Freedesktop), settings)
+    }
+
+    pub fn with_backend(backend: Arc<dyn Backend>, settings: NotifierSettings) -> Self {
+        Self {
+            backend,
+            focused: Arc::new(AtomicBool::new(true)),
+            settings: Arc::new(Mutex::new(settings)),
+            limiter: Arc::new(Mutex::new(RateLimiter::default())),
+            seen: Arc::new(Mutex::new(SeenIds::default())),
+            started_at: raw::now(),
+        }
+    }
+
+    pub fn set_focused(&self, focused: bool) {
+        self.focused.store(focused, Ordering::SeqCst);
+    }
+
+    pub fn settings(&self) -> NotifierSettings {
+        match self.settings.lock() {
+            Ok(settings) => *settings,
+            Err(_) => NotifierSettings::default(),
+        }
+    }
+
+    pub fn set_settings(&self, settings: NotifierSettings) {
+        if let Ok(mut s) = self.settings.lock() {
+            *s = settings;
+        }
+    }
+
+    /// Notify about DMs, mentions and replies addressed to us. Showing the notification
+    /// may block on D-Bus, so it's done off the async runtime.
+    pub fn handle_event(&self, client: &Client, event: &Event) {
+        let raw = match RawEvent::from_event(event) {
+            Ok(raw) => raw,
+            Err(_) => return,
+        };
+        let my_public_key = client.keys().public_key();
+        if let Some((kind, summary, body)) =
+            self.prepare(&my_public_key, &raw, |raw| display_name(client, raw))
+        {
+            let notifier = self.clone();
+            tokio::task::spawn_blocking(move || notifier.notify(kind, &summary, &body));
+        }
+    }
+
+    /// Summary and body of the notification about `raw`, if there's one to show. Events
+    /// created before the session started, or already handled, are skipped: relays send
+    /// them again on every subscription.
+    fn prepare<F>(
+        &self,
+        my_public_key: &XOnlyPublicKey,
+        raw: &RawEvent,
+        name_of: F,
+    ) -> Option<(NotificationKind, String, String)>
+    where
+        F: Fn(&RawEvent) -> String,
+    {
+        let kind = classify(my_public_key, raw)?;
+
+        // Gift wraps are backdated by up to `MAX_TWEAK` to hide when they were sent
+        let since = match raw.kind {
+            GIFT_WRAP_KIND => self.started_at.saturating_sub(MAX_TWEAK),
+            _ => self.started_at,
+        };
+        if raw.created_at < since {
+            return None;
+        }
+        let is_new = match self.seen.lock() {
+            Ok(mut seen) => seen.insert(&raw.id),
+            Err(_) => false,
+        };
+        if !is_new {
+            return None;
+        }
+
+        if self.focused.load(Ordering::SeqCst) || !self.settings().allows(kind) {
+            return None;
+        }
+
+        let (summary, body) = match kind {
+            // Never leak message content, even for NIP-04 where we could decrypt it
+            NotificationKind::DirectMessage => match raw.kind {
+                GIFT_WRAP_KIND => (String::from("New private message"), String::new()),
+                _ => (format!("New message from {}", name_of(raw)), String::new()),
+            },
+            NotificationKind::Mention => (
+                format!("{} mentioned you", name_of(raw)),
+                preview(&raw.content),
+            ),
+            NotificationKind::Reply => (
+                format!("{} replied to your note", name_of(raw)),
+                preview(&raw.content),
+            ),
+        };
+        Some((kind, summary, body))
+    }
+
+    /// Show a notification, unless the window is focused, the kind is disabled, do not
+    /// disturb is on or the rate limit is hit
+    pub fn notify(&self, kind: NotificationKind, summary: &str, body: &str) {
+        if self.focused.load(Ordering::SeqCst) || !self.settings().allows(kind) {
+            return;
+        }
+
+        let suppressed = match self.limiter.lock() {
+            Ok(mut limiter) => match limiter.check(Instant::now()) {
+                Some(suppressed) => suppressed,
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let body = if suppressed > 0 {
+            format!("{}\n(+{} more)", body, suppressed)
+        } else {
+            body.to_string()
+        };
+        if let Err(e) = self.backend.show(summary, &body) {
+            log::error!("Impossible to show desktop notification: {}", e);
+        }
+    }
+}
+
+fn classify(my_public_key: &XOnlyPublicKey, raw: &RawEvent) -> Option<NotificationKind> {
+    let my_public_key = my_public_key.to_string();
+    if raw.pubkey == my_public_key || !raw.tag_values("p").any(|pk| pk == my_public_key) {
+        return None;
+    }
+    match raw.kind {
+        ENCRYPTED_DIRECT_MESSAGE_KIND | GIFT_WRAP_KIND => Some(NotificationKind::DirectMessage),
+        TEXT_NOTE_KIND if raw.tag_values("e").next().is_some() => Some(NotificationKind::Reply),
+        TEXT_NOTE_KIND => Some(NotificationKind::Mention),
+        _ => None,
+    }
+}
+
+fn display_name(client: &Client, raw: &RawEvent) -> String {
+    if let Ok(public_key) = raw.pubkey() {
+        if let Ok(Ok(profile)) = client.store().map(|store| store.get_profile(public_key)) {
+            if let Some(display_name) = profile.display_name {
+                return display_name;
+            }
+        }
+    }
+    format!(
+        "{}:{}",
+        &raw.pubkey[0..8],
+        &raw.pubkey[raw.pubkey.len() - 8..]
+    )
+}
+
+fn preview(content: &str) -> String {
+    match content.char_indices().nth(PREVIEW_LEN) {
+        Some((i, _)) => format!("{}…", &content[..i]),
+        None => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::process;
+
+    use nostr_sdk::nostr::secp256k1::{KeyPair, Secp256k1, SecretKey};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        shown: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Recorder {
+        fn shown(&self) -> Vec<(String, String)> {
+            self.shown.lock().unwrap().clone()
+        }
+    }
+
+    impl Backend for Recorder {
+        fn show(&self, summary: &str, body: &str) -> Result<(), String> {
+            self.shown
+                .lock()
+                .unwrap()
+                .push((summary.to_string(), body.to_string()));
+            Ok(())
+        }
+    }
+
+    fn public_key(secret: u8) -> XOnlyPublicKey {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[secret; 32]).unwrap();
+        XOnlyPublicKey::from_keypair(&KeyPair::from_secret_key(&secp, &secret_key)).0
+    }
+
+    fn event(kind: u64, created_at: u64, tags: Vec<Vec<String>>, content: &str) -> RawEvent {
+        RawEvent::new(public_key(2), created_at, kind, tags, content.to_string())
+    }
+
+    fn to_me(kind: u64, created_at: u64, content: &str) -> RawEvent {
+        let tags = vec![vec![String::from("p"), public_key(1).to_string()]];
+        event(kind, created_at, tags, content)
+    }
+
+    fn notifier(settings: NotifierSettings) -> (Notifier, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let notifier = Notifier::with_backend(recorder.clone(), settings);
+        notifier.set_focused(false);
+        (notifier, recorder)
+    }
+
+    fn handle(notifier: &Notifier, raw: &RawEvent) {
+        if let Some((kind, summary, body)) =
+            notifier.prepare(&public_key(1), raw, |_| String::from("alice"))
+        {
+            notifier.notify(kind, &summary, &body);
+        }
+    }
+
+    #[test]
+    fn content() {
+        let (notifier, recorder) = notifier(NotifierSettings::default());
+        let now = raw::now();
+
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, now, "gm"));
+        let mut tags = to_me(TEXT_NOTE_KIND, now, "").tags;
+        tags.push(vec![String::from("e"), String::from("1")]);
+        handle(
+            &notifier,
+            &event(TEXT_NOTE_KIND, now, tags, "gm to you too"),
+        );
+        handle(
+            &notifier,
+            &to_me(ENCRYPTED_DIRECT_MESSAGE_KIND, now, "secret"),
+        );
+        handle(&notifier, &to_me(GIFT_WRAP_KIND, now, "secret"));
+        // Not addressed to us
+        handle(&notifier, &event(TEXT_NOTE_KIND, now, Vec::new(), "gm"));
+
+        let long = "a".repeat(PREVIEW_LEN + 10);
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, now, &long));
+
+        let shown = recorder.shown();
+        assert_eq!(
+            shown[..4],
+            [
+                (String::from("alice mentioned you"), String::from("gm")),
+                (
+                    String::from("alice replied to your note"),
+                    String::from("gm to you too")
+                ),
+                (String::from("New message from alice"), String::new()),
+                (String::from("New private message"), String::new()),
+            ]
+        );
+        assert_eq!(shown[4].1, format!("{}…", "a".repeat(PREVIEW_LEN)));
+        assert_eq!(shown.len(), 5);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let (notifier, recorder) = notifier(NotifierSettings::default());
+        let now = raw::now();
+        for i in 0..RATE_LIMIT + 2 {
+            handle(&notifier, &to_me(TEXT_NOTE_KIND, now, &i.to_string()));
+        }
+        assert_eq!(recorder.shown().len(), RATE_LIMIT);
+
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        for _ in 0..RATE_LIMIT {
+            assert_eq!(limiter.check(start), Some(0));
+        }
+        assert_eq!(limiter.check(start), None);
+        assert_eq!(limiter.check(start), None);
+        let later = start + RATE_LIMIT_WINDOW + Duration::from_secs(1);
+        assert_eq!(limiter.check(later), Some(2));
+    }
+
+    #[test]
+    fn do_not_disturb() {
+        let settings = NotifierSettings {
+            do_not_disturb: true,
+            ..NotifierSettings::default()
+        };
+        let (notifier, recorder) = notifier(settings);
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, raw::now(), "1"));
+        assert!(recorder.shown().is_empty());
+
+        let settings = NotifierSettings {
+            mentions: false,
+            ..NotifierSettings::default()
+        };
+        notifier.set_settings(settings);
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, raw::now(), "2"));
+        assert!(recorder.shown().is_empty());
+
+        notifier.set_settings(NotifierSettings::default());
+        notifier.set_focused(true);
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, raw::now(), "3"));
+        assert!(recorder.shown().is_empty());
+    }
+
+    #[test]
+    fn duplicates_and_history() {
+        let (notifier, recorder) = notifier(NotifierSettings::default());
+        let now = raw::now();
+        let note = to_me(TEXT_NOTE_KIND, now, "gm");
+        handle(&notifier, &note);
+        handle(&notifier, &note);
+        handle(&notifier, &to_me(TEXT_NOTE_KIND, now - 60, "gm"));
+        // Backdated gift wrap
+        handle(&notifier, &to_me(GIFT_WRAP_KIND, now - 60, ""));
+        assert_eq!(recorder.shown().len(), 2);
+
+        let mut seen = SeenIds::default();
+        for i in 0..=SEEN_CAPACITY {
+            assert!(seen.insert(&i.to_string()));
+        }
+        assert_eq!(seen.order.len(), SEEN_CAPACITY);
+        assert!(seen.insert("0"));
+        assert!(!seen.insert(&SEEN_CAPACITY.to_string()));
+    }
+
+    /// Notification daemon answering on the test session bus
+    struct StubDaemon {
+        received: Arc<Mutex<Vec<(String, String, String)>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl StubDaemon {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: String,
+            _replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            _hints: HashMap<String, zbus::zvariant::OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut received = self.received.lock().unwrap();
+            received.push((app_name, summary, body));
+            received.len() as u32
+        }
+    }
+
+    /// Send through `Freedesktop` to a stub daemon on a session bus of our own. Skipped
+    /// when `dbus-daemon` isn't installed.
+    #[test]
+    fn freedesktop() {
+        let mut bus = match process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(process::Stdio::piped())
+            .spawn()
+        {
+            Ok(bus) => bus,
+            Err(e) => {
+                eprintln!("Skipping the D-Bus test, dbus-daemon can't be run: {}", e);
+                return;
+            }
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let daemon = StubDaemon {
+            received: received.clone(),
+        };
+        let _connection = zbus::blocking::connection::Builder::address(address)
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at("/org/freedesktop/Notifications", daemon)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address);
+        let result = Freedesktop.show("alice mentioned you", "gm");
+        bus.kill().unwrap();
+        bus.wait().unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            *received.lock().unwrap(),
+            [(
+                String::from(env!("CARGO_PKG_NAME")),
+                String::from("alice mentioned you"),
+                String::from("gm")
+            )]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Outbox model: read the notes of the followed authors from the relays they write to
+//!
+//! The outbox relays are connected through a client of their own, without a store, so
+//! they're neither saved nor listed with our relays, and never written to. The sync
+//! reads its notifications with the ones of the app client.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, Kind, SubscriptionFilter};
+use nostr_sdk::Client;
+use tokio::sync::Mutex;
+
+use crate::checkpoint::Checkpoints;
+use crate::net;
+use crate::nips::nip65;
+use crate::nips::raw::now;
+use crate::stage::dashboard::relay_policy::RelayPolicies;
+
+/// Cap of the extra connections opened for the outbox model
+const MAX_OUTBOX_RELAYS: usize = 8;
+/// Authors per kind 10002 request
+const AUTHORS_CHUNK: usize = 200;
+const TEXT_NOTE_KIND: u64 = 1;
+/// How far back to read relays without a checkpoint
+const SINCE: u64 = 24 * 60 * 60;
+const OUTBOX_FILTER: &str = "outbox";
+const LIMIT: usize = 100;
+
+/// Greedy set cover: pick the relay writing the most uncovered authors, until every
+/// author is covered or `max` relays are picked. Authors already read through
+/// `connected` relays don't need an extra connection.
+pub fn cover(
+    write_relays: &HashMap<XOnlyPublicKey, Vec<Url>>,
+    connected: &HashSet<Url>,
+    max: usize,
+) -> Vec<(Url, Vec<XOnlyPublicKey>)> {
+    let mut uncovered: HashSet<XOnlyPublicKey> = write_relays
+        .iter()
+        .filter(|(_, relays)| !relays.iter().any(|r| connected.contains(r)))
+        .map(|(author, _)| *author)
+        .collect();
+
+    let mut authors_of: HashMap<&Url, HashSet<XOnlyPublicKey>> = HashMap::new();
+    for author in uncovered.iter() {
+        for url in write_relays.get(author).into_iter().flatten() {
+            authors_of.entry(url).or_default().insert(*author);
+        }
+    }
+
+    let mut picked = Vec::new();
+    while !uncovered.is_empty() && picked.len() < max {
+        let best = authors_of
+            .iter()
+            .map(|(url, authors)| (*url, authors.intersection(&uncovered).count()))
+            .filter(|(_, count)| *count > 0)
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)));
+        let url = match best {
+            Some((url, _)) => url.clone(),
+            None => break,
+        };
+        let authors: Vec<XOnlyPublicKey> = authors_of
+            .remove(&url)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|a| uncovered.remove(a))
+            .collect();
+        picked.push((url, authors));
+    }
+
+    if !uncovered.is_empty() {
+        log::debug!(
+            "{} authors not covered by the outbox relays",
+            uncovered.len()
+        );
+    }
+
+    picked
+}
+
+/// Write relays of each followed author, from their kind 10002
+async fn write_relays(
+    client: &Client,
+    authors: Vec<XOnlyPublicKey>,
+) -> HashMap<XOnlyPublicKey, Vec<Url>> {
+    let mut write_relays = HashMap::new();
+    for chunk in authors.chunks(AUTHORS_CHUNK) {
+        match nip65::get_relay_lists(client, chunk.to_vec()).await {
+            Ok(lists) => {
+                for raw in lists.iter() {
+                    if let Ok(author) = raw.pubkey() {
+                        let relays: Vec<Url> = nip65::relay_list(raw)
+                            .into_iter()
+                            .filter(|(_, policy)| policy.write)
+                            .map(|(url, _)| url)
+                            .collect();
+                        write_relays.insert(author, relays);
+                    }
+                }
+            }
+            Err(e) => log::error!("Impossible to get relay lists: {}", e),
+        }
+    }
+    write_relays
+}
+
+/// Relays the outbox model connected to, with the authors read from each
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    client: Client,
+    groups: Arc<Mutex<HashMap<Url, Vec<XOnlyPublicKey>>>>,
+}
+
+impl Outbox {
+    /// Outbox of the account of `client`, with no relay yet
+    pub fn new(client: &Client) -> Self {
+        Self {
+            client: Client::new(&client.keys()),
+            groups: Arc::default(),
+        }
+    }
+
+    /// Client of the outbox relays
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Connect to the relays covering the followed authors `client` doesn't read yet,
+    /// and read their notes there. The relays not needed anymore are removed, so it's
+    /// called again when the contact list changes.
+    pub async fn update(
+        &self,
+        client: &Client,
+        policies: &RelayPolicies,
+        checkpoints: &Checkpoints,
+    ) -> nostr_sdk::Result<()> {
+        let mut groups = self.groups.lock().await;
+
+        let authors: Vec<XOnlyPublicKey> = client
+            .store()?
+            .get_contacts()?
+            .into_iter()
+            .map(|profile| profile.pubkey)
+            .collect();
+        let own: HashSet<Url> = client.relays().await.into_keys().collect();
+        let connected: HashSet<Url> = own
+            .iter()
+            .filter(|url| policies.get(url).read)
+            .cloned()
+            .collect();
+        let mut write_relays = if authors.is_empty() {
+            HashMap::new()
+        } else {
+            write_relays(client, authors).await
+        };
+        // Our relays we don't read from can't be picked either
+        for urls in write_relays.values_mut() {
+            urls.retain(|url| connected.contains(url) || !own.contains(url));
+        }
+        let picked: HashMap<Url, Vec<XOnlyPublicKey>> =
+            cover(&write_relays, &connected, MAX_OUTBOX_RELAYS)
+                .into_iter()
+                .collect();
+
+        for url in groups.keys().filter(|url| !picked.contains_key(*url)) {
+            log::debug!("Outbox relay {} not needed anymore", url);
+            if let Err(e) = self.client.remove_relay(url.as_str()).await {
+                log::error!("Impossible to remove outbox relay {}: {}", url, e);
+            }
+        }
+        for url in picked.keys().filter(|url| !groups.contains_key(*url)) {
+            let result = match net::relay_proxy(url) {
+                Ok(proxy) => self
+                    .client
+                    .add_relay(url.clone(), proxy)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = result {
+                log::error!("Impossible to add outbox relay {}: {}", url, e);
+            }
+        }
+        *groups = picked;
+        if groups.is_empty() {
+            return Ok(());
+        }
+        self.client.connect().await;
+
+        let relays = self.client.relays().await;
+        for (url, authors) in groups.iter() {
+            log::debug!("Reading {} authors from {}", authors.len(), url);
+            checkpoints.set_authors(url, OUTBOX_FILTER, authors);
+            let filter = SubscriptionFilter::new()
+                .authors(authors.clone())
+                .kind(Kind::from(TEXT_NOTE_KIND))
+                .since(
+                    checkpoints
+                        .since(url, OUTBOX_FILTER)
+                        .unwrap_or_else(|| now().saturating_sub(SINCE)),
+                )
+                .limit(LIMIT);
+            if let Some(relay) = relays.get(url) {
+                if let Err(e) = relay
+                    .send_msg(ClientMessage::new_req(
+                        checkpoints.subscription_id(url, OUTBOX_FILTER),
+                        vec![filter],
+                    ))
+                    .await
+                {
+                    log::error!("Impossible to subscribe to {}: {}", url, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Disconnect from the outbox relays
+    pub async fn shutdown(&self) {
+        if let Err(e) = self.client.clone().shutdown().await {
+            log::error!("Impossible to shutdown outbox client: {}", e);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use iced_futures::futures::{SinkExt, StreamExt};
+use nostr_sdk::nostr::url::Url;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::tungstenite::{self, Message as WsMessage};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::net;
+
+#[derive(Debug)]
+pub enum Error {
+    WebSocket(tungstenite::Error),
+    Socks(tokio_socks::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Net(net::Error),
+    InvalidUrl,
+    Timeout,
+    Closed,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WebSocket(e) => write!(f, "websocket: {}", e),
+            Self::Socks(e) => write!(f, "socks5: {}", e),
+            Self::Io(e) => write!(f, "io: {}", e),
+            Self::Json(e) => write!(f, "json: {}", e),
+            Self::Net(e) => write!(f, "{}", e),
+            Self::InvalidUrl => write!(f, "invalid relay url"),
+            Self::Timeout => write!(f, "timeout"),
+            Self::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Self::WebSocket(e)
+    }
+}
+
+impl From<tokio_socks::Error> for Error {
+    fn from(e: tokio_socks::Error) -> Self {
+        Self::Socks(e)
+    }
+}
+
+impl From<net::Error> for Error {
+    fn from(e: net::Error) -> Self {
+        Self::Net(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> Io for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+/// WebSocket connection to a single relay, exchanging raw JSON messages
+pub struct Connection {
+    stream: WebSocketStream<MaybeTlsStream<Box<dyn Io>>>,
+}
+
+impl Connection {
+    /// Connect to `url`, through the SOCKS5 `proxy` if any
+    pub async fn connect(
+        url: &Url,
+        proxy: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        time::timeout(timeout, Self::connect_inner(url, proxy))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    async fn connect_inner(url: &Url, proxy: Option<SocketAddr>) -> Result<Self, Error> {
+        net::check(proxy)?;
+        let host = url.host_str().ok_or(Error::InvalidUrl)?.to_string();
+        let port = url.port_or_known_default().ok_or(Error::InvalidUrl)?;
+        let tcp: Box<dyn Io> = match proxy {
+            Some(proxy) => Box::new(Socks5Stream::connect(proxy, (host, port)).await?),
+            None => Box::new(TcpStream::connect((host, port)).await?),
+        };
+        let (stream, _) = tokio_tungstenite::client_async_tls(url.as_str(), tcp).await?;
+        Ok(Self { stream })
+    }
+
+    /// Send a message, returning its size in bytes
+    pub async fn send(&mut self, msg: &Value) -> Result<usize, Error> {
+        let text = msg.to_string();
+        let len = text.len();
+        self.stream.send(WsMessage::Text(text)).await?;
+        Ok(len)
+    }
+
+    /// Next JSON message and its size in bytes
+    pub async fn recv(&mut self, timeout: Duration) -> Result<(Value, usize), Error> {
+        loop {
+            let msg = time::timeout(timeout, self.stream.next())
+                .await
+                .map_err(|_| Error::Timeout)?;
+            match msg {
+                Some(Ok(WsMessage::Text(text))) => {
+                    return Ok((serde_json::from_str(&text)?, text.len()))
+                }
+                Some(Ok(WsMessage::Binary(bytes))) => {
+                    return Ok((serde_json::from_slice(&bytes)?, bytes.len()))
+                }
+                Some(Ok(WsMessage::Close(_))) | None => return Err(Error::Closed),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn close(mut self) {
+        if let Err(e) = self.stream.close(None).await {
+            log::debug!("Impossible to close relay connection: {}", e);
+        }
+    }
+}
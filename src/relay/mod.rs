@@ -0,0 +1,9 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Direct relay connections, for what the SDK relay pool doesn't expose
+
+pub mod connection;
+pub mod negentropy;
+
+pub use self::connection::Connection;
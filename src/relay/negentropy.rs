@@ -0,0 +1,314 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-77 session with a single relay: find the events of a filter missing locally
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use nostr_sdk::nostr::url::Url;
+use serde_json::{json, Value};
+
+use super::{connection, Connection};
+use crate::nips::nip77::{self, Item, Negentropy};
+use crate::nips::raw::{from_hex, to_hex};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const SUBSCRIPTION_ID: &str = "negentropy";
+const MAX_ROUNDS: usize = 32;
+
+#[derive(Debug)]
+pub enum Error {
+    Connection(connection::Error),
+    Protocol(nip77::Error),
+    /// `NEG-ERR`, or a relay not knowing NIP-77
+    Rejected(String),
+    TooManyRounds,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connection(e) => write!(f, "{}", e),
+            Self::Protocol(e) => write!(f, "negentropy: {}", e),
+            Self::Rejected(reason) => write!(f, "rejected: {}", reason),
+            Self::TooManyRounds => write!(f, "too many rounds"),
+        }
+    }
+}
+
+impl From<connection::Error> for Error {
+    fn from(e: connection::Error) -> Self {
+        Self::Connection(e)
+    }
+}
+
+impl From<nip77::Error> for Error {
+    fn from(e: nip77::Error) -> Self {
+        Self::Protocol(e)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Reconciliation {
+    /// Ids of the events only the relay has
+    pub need: Vec<[u8; 32]>,
+    pub rounds: usize,
+    pub bytes: usize,
+}
+
+/// Reconcile `items`, the local events matching `filter`, against the relay
+pub async fn reconcile(
+    url: &Url,
+    proxy: Option<SocketAddr>,
+    filter: Value,
+    items: Vec<Item>,
+) -> Result<Reconciliation, Error> {
+    let negentropy = Negentropy::new(items);
+    let mut result = Reconciliation::default();
+
+    let mut conn = Connection::connect(url, proxy, TIMEOUT).await?;
+    let session = run(&mut conn, &negentropy, filter, &mut result).await;
+    if let Ok(bytes) = conn.send(&json!(["NEG-CLOSE", SUBSCRIPTION_ID])).await {
+        result.bytes += bytes;
+    }
+    conn.close().await;
+
+    session.map(|_| result)
+}
+
+async fn run(
+    conn: &mut Connection,
+    negentropy: &Negentropy,
+    filter: Value,
+    result: &mut Reconciliation,
+) -> Result<(), Error> {
+    let mut msg = json!([
+        "NEG-OPEN",
+        SUBSCRIPTION_ID,
+        filter,
+        to_hex(&negentropy.initiate())
+    ]);
+    loop {
+        if result.rounds >= MAX_ROUNDS {
+            return Err(Error::TooManyRounds);
+        }
+        result.bytes += conn.send(&msg).await?;
+        result.rounds += 1;
+
+        let reply = recv_reply(conn, result).await?;
+        let reply = from_hex(&reply).ok_or(nip77::Error::Malformed)?;
+        match negentropy.reconcile(&reply, &mut result.need)? {
+            Some(next) => msg = json!(["NEG-MSG", SUBSCRIPTION_ID, to_hex(&next)]),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Hex payload of the next `NEG-MSG`
+async fn recv_reply(conn: &mut Connection, result: &mut Reconciliation) -> Result<String, Error> {
+    loop {
+        let (msg, bytes) = conn.recv(TIMEOUT).await?;
+        result.bytes += bytes;
+        match msg[0].as_str() {
+            Some("NEG-MSG") if msg[1] == SUBSCRIPTION_ID => {
+                return msg[2]
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .ok_or(Error::Protocol(nip77::Error::Malformed))
+            }
+            Some("NEG-ERR") | Some("CLOSED") | Some("NOTICE") => {
+                let reason = msg
+                    .as_array()
+                    .and_then(|a| a.last())
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                return Err(Error::Rejected(reason.to_string()));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iced_futures::futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use super::*;
+    use crate::nips::nip77::tests::item;
+
+    /// What the relay stand-in received, and the bytes of the whole exchange
+    type Exchange = (Vec<Value>, usize);
+
+    /// Text note of `item`, about the size of a real one
+    fn event(item: &Item) -> Value {
+        json!({
+            "id": to_hex(&item.id),
+            "pubkey": "ab".repeat(32),
+            "created_at": item.timestamp,
+            "kind": 1,
+            "tags": [],
+            "content": "A note from a followed author, about as long as most notes are.",
+            "sig": "cd".repeat(64),
+        })
+    }
+
+    /// Relay answering `REQ` with the events of `items` matching its `ids`, if any, and
+    /// `NEG-OPEN` and `NEG-MSG` from `items`, or with `NEG-ERR` if `None`, until
+    /// `NEG-CLOSE` or `CLOSE`
+    async fn relay(items: Option<Vec<Item>>) -> (Url, JoinHandle<Exchange>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+        let handle = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let negentropy = items.clone().map(Negentropy::new);
+            let mut received = Vec::new();
+            let mut bytes = 0;
+            while let Some(Ok(WsMessage::Text(text))) = ws.next().await {
+                bytes += text.len();
+                let msg: Value = serde_json::from_str(&text).unwrap();
+                received.push(msg.clone());
+                if msg[0] == "REQ" {
+                    let ids: Option<Vec<&str>> = msg[2]["ids"]
+                        .as_array()
+                        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect());
+                    for item in items.iter().flatten() {
+                        let id = to_hex(&item.id);
+                        let wanted = match &ids {
+                            Some(ids) => ids.contains(&id.as_str()),
+                            None => true,
+                        };
+                        if wanted {
+                            let reply = json!(["EVENT", msg[1], event(item)]).to_string();
+                            bytes += reply.len();
+                            ws.send(WsMessage::Text(reply)).await.unwrap();
+                        }
+                    }
+                    let reply = json!(["EOSE", msg[1]]).to_string();
+                    bytes += reply.len();
+                    ws.send(WsMessage::Text(reply)).await.unwrap();
+                    continue;
+                }
+                let query = match msg[0].as_str() {
+                    Some("NEG-OPEN") => &msg[3],
+                    Some("NEG-MSG") => &msg[2],
+                    _ => break,
+                };
+                let reply = match &negentropy {
+                    Some(negentropy) => {
+                        let query = from_hex(query.as_str().unwrap()).unwrap();
+                        let reply = negentropy.respond(&query).unwrap();
+                        json!(["NEG-MSG", msg[1], to_hex(&reply)])
+                    }
+                    None => json!(["NEG-ERR", msg[1], "blocked: not allowed"]),
+                };
+                let reply = reply.to_string();
+                bytes += reply.len();
+                ws.send(WsMessage::Text(reply)).await.unwrap();
+            }
+            (received, bytes)
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn reconcile_with_relay() {
+        let shared: Vec<Item> = (0..500).map(|i| item(i, i / 5)).collect();
+        let missing: Vec<Item> = (500..530).map(|i| item(i, i % 100)).collect();
+        let (url, relay) = relay(Some([shared.clone(), missing.clone()].concat())).await;
+
+        let filter = json!({"kinds": [1], "since": 1_700_000_000});
+        let result = reconcile(&url, None, filter.clone(), shared).await.unwrap();
+        let (received, bytes) = relay.await.unwrap();
+
+        let mut need = result.need.clone();
+        need.sort();
+        let mut expected: Vec<[u8; 32]> = missing.iter().map(|i| i.id).collect();
+        expected.sort();
+        assert_eq!(need, expected);
+
+        assert_eq!(received[0][0], "NEG-OPEN");
+        assert_eq!(received[0][1], SUBSCRIPTION_ID);
+        assert_eq!(received[0][2], filter);
+        assert!(received[1..received.len() - 1]
+            .iter()
+            .all(|msg| msg[0] == "NEG-MSG"));
+        assert_eq!(received.last().unwrap()[0], "NEG-CLOSE");
+        assert_eq!(result.rounds, received.len() - 1);
+        assert_eq!(result.bytes, bytes);
+    }
+
+    /// Events and bytes of a REQ for `filter` up to its EOSE, as the sync sends it to the
+    /// relays without NIP-77
+    async fn req(url: &Url, filter: Value) -> (usize, usize) {
+        let mut conn = Connection::connect(url, None, TIMEOUT).await.unwrap();
+        let mut bytes = conn.send(&json!(["REQ", "req", filter])).await.unwrap();
+        let mut events = 0;
+        loop {
+            let (msg, len) = conn.recv(TIMEOUT).await.unwrap();
+            bytes += len;
+            match msg[0].as_str() {
+                Some("EVENT") => events += 1,
+                Some("EOSE") => break,
+                _ => (),
+            }
+        }
+        bytes += conn.send(&json!(["CLOSE", "req"])).await.unwrap();
+        conn.close().await;
+        (events, bytes)
+    }
+
+    #[tokio::test]
+    async fn reconcile_uses_fewer_bytes_than_req() {
+        let shared: Vec<Item> = (0..500).map(|i| item(i, i / 5)).collect();
+        let missing: Vec<Item> = (500..530).map(|i| item(i, i % 100)).collect();
+        let items = [shared.clone(), missing.clone()].concat();
+        let filter = json!({"kinds": [1], "since": 1_700_000_000});
+
+        // Without NIP-77, every note of the filter is downloaded again
+        let (url, relay_task) = relay(Some(items.clone())).await;
+        let (events, req_bytes) = req(&url, filter.clone()).await;
+        relay_task.await.unwrap();
+        assert_eq!(events, items.len());
+
+        // With it, the missing notes are found, then downloaded by id
+        let (url, relay_task) = relay(Some(items.clone())).await;
+        let result = reconcile(&url, None, filter, shared).await.unwrap();
+        relay_task.await.unwrap();
+        let ids: Vec<String> = result.need.iter().map(|id| to_hex(id)).collect();
+        let (url, relay_task) = relay(Some(items)).await;
+        let (events, missing_bytes) = req(&url, json!({ "ids": ids })).await;
+        relay_task.await.unwrap();
+        assert_eq!(events, missing.len());
+
+        assert!(result.bytes < req_bytes);
+        assert!(
+            result.bytes + missing_bytes < req_bytes,
+            "negentropy: {} + {} bytes, REQ: {} bytes",
+            result.bytes,
+            missing_bytes,
+            req_bytes
+        );
+    }
+
+    #[tokio::test]
+    async fn relay_errors_are_reported() {
+        let (url, relay) = relay(None).await;
+        let items = (0..10).map(|i| item(i, i)).collect();
+        let result = reconcile(&url, None, json!({"kinds": [1]}), items).await;
+        let (received, _) = relay.await.unwrap();
+
+        match result {
+            Err(Error::Rejected(reason)) => assert_eq!(reason, "blocked: not allowed"),
+            other => panic!("unexpected result: {:?}", other.map(|r| r.need.len())),
+        }
+        assert_eq!(received.last().unwrap()[0], "NEG-CLOSE");
+    }
+}
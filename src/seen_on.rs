@@ -0,0 +1,128 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Relays each event was received from
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::util::dir;
+use crate::util::json_store::{self, Debounce};
+
+/// Events remembered, oldest forgotten first
+const MAX_EVENTS: usize = 10_000;
+/// Relay hints put in the `nevent` of an event
+const MAX_RELAY_HINTS: usize = 3;
+
+#[derive(Debug, Default)]
+struct Inner {
+    relays: HashMap<String, Vec<String>>,
+    order: VecDeque<String>,
+    save: Debounce,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeenOn {
+    public_key: XOnlyPublicKey,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Persisted {
+    /// Oldest first
+    events: Vec<(String, Vec<String>)>,
+}
+
+impl SeenOn {
+    pub fn load(public_key: XOnlyPublicKey) -> Self {
+        let mut inner = Inner::default();
+        if let Some(persisted) = json_store::load::<Persisted>(path(&public_key)) {
+            for (id, relays) in persisted.events.into_iter() {
+                inner.order.push_back(id.clone());
+                inner.relays.insert(id, relays);
+            }
+        }
+        Self {
+            public_key,
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Remember that `relay` sent the event. Returns whether it was already received,
+    /// from any relay.
+    pub fn record(&self, event_id: &str, relay: &Url) -> bool {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return false,
+        };
+        let relay = relay.to_string();
+        let duplicate = match inner.relays.get_mut(event_id) {
+            Some(relays) if relays.contains(&relay) => return true,
+            Some(relays) => {
+                relays.push(relay);
+                true
+            }
+            None => {
+                inner.relays.insert(event_id.to_string(), vec![relay]);
+                inner.order.push_back(event_id.to_string());
+                while inner.order.len() > MAX_EVENTS {
+                    if let Some(oldest) = inner.order.pop_front() {
+                        inner.relays.remove(&oldest);
+                    }
+                }
+                false
+            }
+        };
+        inner.save.touch();
+        if inner.save.is_due() {
+            self.save_inner(&mut inner);
+        }
+        duplicate
+    }
+
+    pub fn get(&self, event_id: &str) -> Vec<String> {
+        self.inner
+            .lock()
+            .ok()
+            .and_then(|inner| inner.relays.get(event_id).cloned())
+            .unwrap_or_default()
+    }
+
+    /// First relays the event was received from, to hint where to find it
+    pub fn hints(&self, event_id: &str) -> Vec<String> {
+        let mut relays = self.get(event_id);
+        relays.truncate(MAX_RELAY_HINTS);
+        relays
+    }
+
+    /// Write the relays if they changed
+    pub fn save(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            self.save_inner(&mut inner);
+        }
+    }
+
+    fn save_inner(&self, inner: &mut Inner) {
+        if inner.save.take() {
+            let persisted = Persisted {
+                events: inner
+                    .order
+                    .iter()
+                    .filter_map(|id| Some((id.clone(), inner.relays.get(id)?.clone())))
+                    .collect(),
+            };
+            json_store::save(path(&self.public_key), &persisted, "seen-on relays");
+        }
+    }
+}
+
+fn path(public_key: &XOnlyPublicKey) -> Option<PathBuf> {
+    dir::default_dir()
+        .ok()
+        .map(|dir| dir.join(format!("seen-on-{}.json", public_key)))
+}
@@ -0,0 +1,153 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Keyboard shortcuts
+//!
+//! Each action is bound to a sequence of keys separated by spaces, e.g. `"g h"`. A key is
+//! a character or the name of a named key, e.g. `Escape`.
+
+use iced::keyboard::key::Named;
+use iced::keyboard::Key;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Next,
+    Previous,
+    Like,
+    Reply,
+    Repost,
+    GoHome,
+    GoNotifications,
+    GoChats,
+    Search,
+    Back,
+    Help,
+}
+
+impl Action {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Next => "Next post",
+            Self::Previous => "Previous post",
+            Self::Like => "Like the selected post",
+            Self::Reply => "Reply to the selected post",
+            Self::Repost => "Repost the selected post",
+            Self::GoHome => "Go to home",
+            Self::GoNotifications => "Go to notifications",
+            Self::GoChats => "Go to chats",
+            Self::Search => "Search",
+            Self::Back => "Go back",
+            Self::Help => "Show or hide this list",
+        }
+    }
+}
+
+/// `[keys]` section of the settings file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub next: String,
+    pub previous: String,
+    pub like: String,
+    pub reply: String,
+    pub repost: String,
+    pub home: String,
+    pub notifications: String,
+    pub chats: String,
+    pub search: String,
+    pub back: String,
+    pub help: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            next: String::from("j"),
+            previous: String::from("k"),
+            like: String::from("l"),
+            reply: String::from("r"),
+            repost: String::from("t"),
+            home: String::from("g h"),
+            notifications: String::from("g n"),
+            chats: String::from("g c"),
+            search: String::from("/"),
+            back: String::from("Escape"),
+            help: String::from("?"),
+        }
+    }
+}
+
+/// Outcome of the keys pressed so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    Action(Action),
+    /// Start of a longer binding, wait for the next key
+    Prefix,
+    None,
+}
+
+impl KeyBindings {
+    /// Bindings in the order they're listed in the cheat sheet
+    pub fn all(&self) -> Vec<(Action, &str)> {
+        vec![
+            (Action::Next, &self.next),
+            (Action::Previous, &self.previous),
+            (Action::Like, &self.like),
+            (Action::Reply, &self.reply),
+            (Action::Repost, &self.repost),
+            (Action::GoHome, &self.home),
+            (Action::GoNotifications, &self.notifications),
+            (Action::GoChats, &self.chats),
+            (Action::Search, &self.search),
+            (Action::Back, &self.back),
+            (Action::Help, &self.help),
+        ]
+    }
+
+    pub fn resolve(&self, pressed: &[String]) -> Match {
+        let mut prefix = false;
+        for (action, binding) in self.all().into_iter() {
+            let keys: Vec<&str> = binding.split_whitespace().collect();
+            if keys.is_empty() || keys.len() < pressed.len() {
+                continue;
+            }
+            if keys.iter().zip(pressed.iter()).all(|(k, p)| k == p) {
+                if keys.len() == pressed.len() {
+                    return Match::Action(action);
+                }
+                prefix = true;
+            }
+        }
+        if prefix {
+            Match::Prefix
+        } else {
+            Match::None
+        }
+    }
+}
+
+/// Name of `key` in the bindings, `None` for keys that can't be bound
+pub fn key_name(key: &Key) -> Option<String> {
+    match key {
+        Key::Character(c) => Some(c.to_string()),
+        Key::Named(named) => match named {
+            Named::Escape => Some(String::from("Escape")),
+            Named::Enter => Some(String::from("Enter")),
+            Named::Tab => Some(String::from("Tab")),
+            Named::Space => Some(String::from("Space")),
+            Named::Backspace => Some(String::from("Backspace")),
+            Named::Delete => Some(String::from("Delete")),
+            Named::Home => Some(String::from("Home")),
+            Named::End => Some(String::from("End")),
+            Named::PageUp => Some(String::from("PageUp")),
+            Named::PageDown => Some(String::from("PageDown")),
+            Named::ArrowUp => Some(String::from("ArrowUp")),
+            Named::ArrowDown => Some(String::from("ArrowDown")),
+            Named::ArrowLeft => Some(String::from("ArrowLeft")),
+            Named::ArrowRight => Some(String::from("ArrowRight")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
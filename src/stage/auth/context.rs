@@ -1,6 +1,8 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Stage {
     Login,
@@ -15,11 +17,13 @@ impl Default for Stage {
 
 pub struct Context {
     pub stage: Stage,
+    /// Account given with `--account`, the only one that can log in
+    pub account: Option<XOnlyPublicKey>,
 }
 
 impl Context {
-    pub fn new(stage: Stage) -> Self {
-        Self { stage }
+    pub fn new(stage: Stage, account: Option<XOnlyPublicKey>) -> Self {
+        Self { stage, account }
     }
 
     pub fn set_stage(&mut self, stage: Stage) {
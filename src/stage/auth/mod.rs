@@ -2,6 +2,7 @@
 // Distributed under the MIT software license
 
 use iced::{Command, Element, Subscription};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
 
 mod context;
 pub mod screen;
@@ -9,12 +10,18 @@ pub mod screen;
 pub use self::context::{Context, Stage};
 use self::screen::LoginState;
 use super::App;
+use crate::config::Config;
 use crate::message::Message;
+use crate::nips::nip19::Entity;
 use crate::NostrDesktop;
 
 pub struct Auth {
     state: Box<dyn State>,
     context: Context,
+    /// Handed over to the dashboard after login
+    pub config: Config,
+    /// `nostr:` URI to open after login
+    uri: Option<Entity>,
 }
 
 pub fn new_state(context: &Context) -> Box<dyn State> {
@@ -37,18 +44,29 @@ pub trait State {
 }
 
 impl Auth {
-    pub fn new() -> (Self, Command<Message>) {
+    pub fn new(
+        config: Config,
+        account: Option<XOnlyPublicKey>,
+        uri: Option<Entity>,
+    ) -> (Self, Command<Message>) {
         // read local db
         // if key exists, load main app
         // else load login/register view
-        let context = Context::new(Stage::default());
+        let context = Context::new(Stage::default(), account);
         let app = Self {
             state: new_state(&context),
             context,
+            config,
+            uri,
         };
         (app, Command::none())
     }
 
+    /// Open `uri` once logged in
+    pub fn set_uri(&mut self, uri: Entity) {
+        self.uri = Some(uri);
+    }
+
     pub fn title(&self) -> String {
         self.state.title()
     }
@@ -65,8 +83,14 @@ impl Auth {
                 (self.state.update(&mut self.context, message), None)
             }
             Message::LoginResult(client) => {
-                let app = App::new(client);
-                (app.1, Some(NostrDesktop::Dashboard(app.0)))
+                let (app, mut command) = App::new(client, self.config.clone());
+                if let Some(entity) = self.uri.take() {
+                    command = Command::batch(vec![
+                        command,
+                        Command::perform(async {}, move |_| Message::Open(entity)),
+                    ]);
+                }
+                (command, Some(NostrDesktop::Dashboard(app)))
             }
             _ => (self.state.update(&mut self.context, message), None),
         }
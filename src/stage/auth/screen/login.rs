@@ -1,14 +1,13 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use std::path::Path;
-
 use iced::widget::{button, column, container, row, text, text_input};
 use iced::{Command, Element, Length};
 use nostr_sdk::nostr::key::{FromSkStr, Keys};
 use nostr_sdk::Client;
 
 use crate::message::Message;
+use crate::nips::nip19;
 use crate::stage::auth::context::Context;
 use crate::stage::auth::State;
 use crate::util::dir;
@@ -41,20 +40,23 @@ impl State for LoginState {
         String::from("Nostr - Login")
     }
 
-    fn update(&mut self, _ctx: &mut Context, message: Message) -> Command<Message> {
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
         if let Message::Login(msg) = message {
             match msg {
                 LoginMessage::SecretKeyChanged(secret_key) => self.secret_key = secret_key,
                 LoginMessage::ButtonPressed => match Keys::from_sk_str(&self.secret_key) {
-                    Ok(keys) => match Client::new_with_store(
-                        &keys,
-                        dir::default_dir().unwrap_or_else(|_| Path::new("./").to_path_buf()),
-                    ) {
-                        Ok(client) => {
-                            return Command::perform(async {}, move |_| {
-                                Message::LoginResult(client)
-                            })
-                        }
+                    Ok(keys) if ctx.account.is_some_and(|pk| pk != keys.public_key()) => {
+                        self.error = Some(String::from("This key doesn't belong to the account"))
+                    }
+                    Ok(keys) => match dir::default_dir() {
+                        Ok(path) => match Client::new_with_store(&keys, path) {
+                            Ok(client) => {
+                                return Command::perform(async {}, move |_| {
+                                    Message::LoginResult(client)
+                                })
+                            }
+                            Err(e) => self.error = Some(e.to_string()),
+                        },
                         Err(e) => self.error = Some(e.to_string()),
                     },
                     Err(e) => self.error = Some(e.to_string()),
@@ -65,7 +67,7 @@ impl State for LoginState {
         Command::none()
     }
 
-    fn view(&self, _ctx: &Context) -> Element<Message> {
+    fn view(&self, ctx: &Context) -> Element<Message> {
         let text_input = text_input("Secret key", &self.secret_key)
             .on_input(|secret_key| Message::Login(LoginMessage::SecretKeyChanged(secret_key)))
             .on_submit(Message::Login(LoginMessage::ButtonPressed))
@@ -76,7 +78,13 @@ impl State for LoginState {
             .padding(10)
             .on_press(Message::Login(LoginMessage::ButtonPressed));
 
+        let account = match &ctx.account {
+            Some(public_key) => row![text(format!("Log in as {}", nip19::npub(public_key)))],
+            None => row![],
+        };
+
         let content = column![
+            account,
             row![text_input, button].spacing(10),
             if let Some(error) = &self.error {
                 row![text(error)]
@@ -1,18 +1,24 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::widget::{Column, Container, Row, Rule, Scrollable};
+use iced::widget::{scrollable, Column, Container, Row, Rule, Scrollable};
 use iced::{Element, Length};
+use once_cell::sync::Lazy;
 
 use crate::stage::dashboard::Context;
 use crate::Message;
 
 mod navbar;
+mod shortcuts;
 mod sidebar;
 
 use self::navbar::Navbar;
+use self::shortcuts::Shortcuts;
 use self::sidebar::Sidebar;
 
+/// Scrollable of the screen content, so its position can be restored
+pub static CONTENT_ID: Lazy<scrollable::Id> = Lazy::new(scrollable::Id::unique);
+
 #[derive(Clone, Default)]
 pub struct Dashboard;
 
@@ -25,6 +31,11 @@ impl Dashboard {
     where
         T: Into<Element<'a, Message>>,
     {
+        let content: Element<'a, Message> = if ctx.show_shortcuts {
+            Shortcuts::view(ctx).into()
+        } else {
+            content.into()
+        };
         Column::new()
             .push(
                 Row::new()
@@ -37,11 +48,13 @@ impl Dashboard {
                     .push(Rule::vertical(1))
                     .push(
                         Column::new()
-                            .push(Navbar::view())
+                            .push(Navbar::view(ctx))
                             .push(Rule::horizontal(1))
                             .push(
                                 Container::new(
-                                    Scrollable::new(content).on_scroll(Message::Scrolled),
+                                    Scrollable::new(content)
+                                        .id(CONTENT_ID.clone())
+                                        .on_scroll(Message::Scrolled),
                                 )
                                 //.max_width(600)
                                 .width(Length::Fill)
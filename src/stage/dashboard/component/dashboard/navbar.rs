@@ -1,23 +1,85 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use chrono::{DateTime, Local, Utc};
 use iced::alignment::Horizontal;
-use iced::widget::{Button, Container, Row};
-use iced::{theme, Length};
+use iced::widget::{Button, Container, Row, Space, Text};
+use iced::{theme, Alignment, Length};
 
 use crate::component::Icon;
-use crate::message::Message;
+use crate::message::{Message, SyncStatus};
 use crate::stage::dashboard::component::post::TransparentStyle;
-use crate::stage::dashboard::Stage;
-use crate::theme::icon::{LOCK, NOTIFICATION};
+use crate::stage::dashboard::{Context, Setting, Stage};
+use crate::theme::icon::{ARROW_LEFT, ARROW_RIGHT, LOCK, NOTIFICATION};
 
 pub struct Navbar;
 
 impl Navbar {
-    pub fn view<'a>() -> Container<'a, Message> {
-        let content = Row::new()
+    pub fn view<'a>(ctx: &Context) -> Container<'a, Message> {
+        let palette = ctx.palette();
+        let mut notification = Row::new()
+            .push(Icon::view(&NOTIFICATION))
+            .align_items(Alignment::Center);
+        let unread = ctx.notifications.unread_count();
+        if unread > 0 {
+            notification =
+                notification.push(Text::new(unread.to_string()).size(14).style(palette.red));
+        }
+
+        let mut back =
+            Button::new(Icon::view(&ARROW_LEFT)).style(
+                <TransparentStyle as Into<theme::Button>>::into(TransparentStyle),
+            );
+        if ctx.can_go_back {
+            back = back.on_press(Message::NavigateBack);
+        }
+        let mut forward =
+            Button::new(Icon::view(&ARROW_RIGHT)).style(<TransparentStyle as Into<
+                theme::Button,
+            >>::into(TransparentStyle));
+        if ctx.can_go_forward {
+            forward = forward.on_press(Message::NavigateForward);
+        }
+
+        let mut content = Row::new()
+            .push(back)
+            .push(forward)
+            .push(Space::with_width(Length::Fill))
+            .width(Length::Fill)
+            .align_items(Alignment::Center);
+        if let Some(SyncStatus::Failed { error, retry_at }) = &ctx.sync_status {
+            let dt: DateTime<Local> = DateTime::<Utc>::from_timestamp(*retry_at as i64, 0)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC)
+                .into();
+            content = content.push(
+                Text::new(format!(
+                    "Sync failed: {}. Retrying at {}",
+                    error,
+                    dt.format("%H:%M:%S")
+                ))
+                .size(14)
+                .style(palette.red),
+            );
+        }
+
+        let challenged = ctx.relay_auth.challenged().count();
+        if challenged > 0 {
+            content = content.push(
+                Button::new(
+                    Text::new(format!("{} relays request authentication", challenged))
+                        .size(14)
+                        .style(palette.yellow),
+                )
+                .on_press(Message::SetDashboardStage(Stage::Setting(Setting::Relays)))
+                .style(<TransparentStyle as Into<theme::Button>>::into(
+                    TransparentStyle,
+                )),
+            );
+        }
+
+        let content = content
             .push(
-                Button::new(Icon::view(&NOTIFICATION))
+                Button::new(notification)
                     .on_press(Message::SetDashboardStage(Stage::Notifications))
                     .style(<TransparentStyle as Into<theme::Button>>::into(
                         TransparentStyle,
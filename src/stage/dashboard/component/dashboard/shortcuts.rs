@@ -0,0 +1,44 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use iced::widget::{Button, Column, Row, Rule, Text};
+use iced::{theme, Alignment, Length};
+
+use crate::message::Message;
+use crate::stage::dashboard::Context;
+
+/// Cheat sheet of the keyboard shortcuts, shown over the screen content
+pub struct Shortcuts;
+
+impl Shortcuts {
+    pub fn view<'a>(ctx: &Context) -> Column<'a, Message> {
+        let keys = &ctx.config.keys;
+        let mut content = Column::new()
+            .push(
+                Row::new()
+                    .push(Text::new("Keyboard shortcuts").size(30).width(Length::Fill))
+                    .push(
+                        Button::new(Text::new("Close").size(14))
+                            .padding(5)
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::ToggleShortcuts),
+                    )
+                    .align_items(Alignment::Center),
+            )
+            .push(Rule::horizontal(1));
+        for (action, binding) in keys.all().into_iter() {
+            content = content.push(
+                Row::new()
+                    .push(Text::new(binding.to_string()).width(Length::Fixed(150.0)))
+                    .push(Text::new(action.description())),
+            );
+        }
+        content
+            .push(
+                Text::new("Bindings can be changed in the [keys] section of settings.toml")
+                    .size(14),
+            )
+            .spacing(10)
+            .padding(20)
+    }
+}
@@ -7,7 +7,7 @@ use iced::{theme, Alignment, Background, Border, Length, Shadow, Theme, Vector};
 
 use crate::message::Message;
 use crate::stage::dashboard::{Context, Stage};
-use crate::theme::color::{PRIMARY, TRANSPARENT, WHITE};
+use crate::theme::color::TRANSPARENT;
 
 pub const BUTTON_SIZE: f32 = 180.0;
 
@@ -16,14 +16,15 @@ pub struct ActiveStyle;
 impl button::StyleSheet for ActiveStyle {
     type Style = Theme;
 
-    fn active(&self, _style: &Self::Style) -> button::Appearance {
+    fn active(&self, style: &Self::Style) -> button::Appearance {
+        let primary = style.extended_palette().primary.base;
         button::Appearance {
             shadow_offset: Vector::default(),
-            background: Some(Background::Color(PRIMARY)),
-            text_color: WHITE,
+            background: Some(Background::Color(primary.color)),
+            text_color: primary.text,
             border: Border {
                 width: 1.0,
-                color: WHITE,
+                color: primary.text,
                 radius: Radius::from(10),
             },
             shadow: Shadow::default(),
@@ -42,14 +43,15 @@ pub struct TransparentStyle;
 impl button::StyleSheet for TransparentStyle {
     type Style = Theme;
 
-    fn active(&self, _style: &Self::Style) -> button::Appearance {
+    fn active(&self, style: &Self::Style) -> button::Appearance {
+        let text = style.palette().text;
         button::Appearance {
             shadow_offset: Vector::default(),
             background: Some(Background::Color(TRANSPARENT)),
-            text_color: WHITE,
+            text_color: text,
             border: Border {
                 width: 1.0,
-                color: WHITE,
+                color: text,
                 radius: Radius::from(10),
             },
             shadow: Shadow::default(),
@@ -6,4 +6,4 @@ mod dashboard;
 pub mod post;
 
 pub use self::contact::Contact;
-pub use self::dashboard::Dashboard;
+pub use self::dashboard::{Dashboard, CONTENT_ID};
@@ -9,8 +9,11 @@ use nostr_sdk::nostr::Event;
 
 use crate::component::Icon;
 use crate::message::Message;
+use crate::nips::nip19;
+use crate::nips::raw::RawEvent;
+use crate::stage::dashboard::screen::HomeMessage;
 use crate::stage::dashboard::Context;
-use crate::theme::color::{TRANSPARENT, WHITE};
+use crate::theme::color::TRANSPARENT;
 use crate::theme::icon::{CHAT, HEART, REPEAT};
 
 pub struct TransparentStyle;
@@ -18,11 +21,11 @@ pub struct TransparentStyle;
 impl button::StyleSheet for TransparentStyle {
     type Style = Theme;
 
-    fn active(&self, _style: &Self::Style) -> button::Appearance {
+    fn active(&self, style: &Self::Style) -> button::Appearance {
         button::Appearance {
             shadow_offset: Vector::default(),
             background: Some(Background::Color(TRANSPARENT)),
-            text_color: WHITE,
+            text_color: style.palette().text,
             border: Border {
                 width: 0.0,
                 color: TRANSPARENT,
@@ -48,12 +51,42 @@ impl Post {
         Self { event }
     }
 
+    fn view_details<'a>(&self, ctx: &Context, id: &str) -> Column<'a, Message> {
+        let seen_on = ctx.seen_on.get(id);
+        let kind = RawEvent::from_event(&self.event)
+            .ok()
+            .map(|raw| raw.kind as u32);
+        let hints = ctx.seen_on.hints(id);
+
+        let mut details = Column::new()
+            .push(Text::new(format!("Id: {}", id)).size(14))
+            .push(
+                Text::new(if seen_on.is_empty() {
+                    String::from("Seen on: unknown")
+                } else {
+                    format!("Seen on: {}", seen_on.join(", "))
+                })
+                .size(14),
+            )
+            .spacing(5);
+        if let Some(nevent) = nip19::nevent(id, &hints, Some(&self.event.pubkey), kind) {
+            details = details.push(
+                Button::new(Text::new("Copy nevent").size(14))
+                    .padding(5)
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::Clipboard(nevent)),
+            );
+        }
+        details
+    }
+
     fn format_pubkey(&self) -> String {
         let pk = self.event.pubkey.to_string();
         format!("{}:{}", &pk[0..8], &pk[pk.len() - 8..])
     }
 
     pub fn view<'a>(&self, ctx: &Context) -> Container<'a, Message> {
+        let palette = ctx.palette();
         let mut display_name = self.format_pubkey();
 
         if let Ok(Ok(profile)) = ctx
@@ -67,40 +100,83 @@ impl Post {
         }
         let buttons = Row::new()
             .push(
-                Button::new(Icon::view(&CHAT).size(18)).style(<TransparentStyle as Into<
-                    theme::Button,
-                >>::into(
-                    TransparentStyle
-                )),
+                Button::new(Icon::view(&CHAT).size(18))
+                    .on_press(HomeMessage::Reply(self.event.clone()).into())
+                    .style(<TransparentStyle as Into<theme::Button>>::into(
+                        TransparentStyle,
+                    )),
             )
             .push(
-                Button::new(Icon::view(&REPEAT).size(18)).style(<TransparentStyle as Into<
-                    theme::Button,
-                >>::into(
-                    TransparentStyle
-                )),
+                Button::new(Icon::view(&REPEAT).size(18))
+                    .on_press(HomeMessage::Repost(self.event.clone()).into())
+                    .style(<TransparentStyle as Into<theme::Button>>::into(
+                        TransparentStyle,
+                    )),
             )
             .push(
-                Button::new(Icon::view(&HEART).size(18)).style(<TransparentStyle as Into<
-                    theme::Button,
-                >>::into(
-                    TransparentStyle
-                )),
+                Button::new(Icon::view(&HEART).size(18))
+                    .on_press(HomeMessage::Like(self.event.clone()).into())
+                    .style(<TransparentStyle as Into<theme::Button>>::into(
+                        TransparentStyle,
+                    )),
+            )
+            .push(
+                Button::new(Text::new("Details").size(14))
+                    .on_press(Message::ToggleEventDetails(self.event.id.to_hex()))
+                    .style(<TransparentStyle as Into<theme::Button>>::into(
+                        TransparentStyle,
+                    )),
             )
             .spacing(20);
 
         let dt: DateTime<Utc> = DateTime::from_timestamp(self.event.created_at as i64, 0)
             .unwrap_or(DateTime::<Utc>::MIN_UTC);
 
-        let post = Column::new()
+        let mut date = Row::new()
+            .push(Text::new(dt.format("%Y-%m-%d %H:%M:%S").to_string()).size(14))
+            .spacing(20);
+        let id = self.event.id.to_hex();
+        if let Some(status) = ctx.publisher.status(&id) {
+            date = date.push(
+                if status.total == 0 || status.accepted + status.rejected == 0 {
+                    Text::new("Pending").size(14).style(palette.yellow)
+                } else if status.accepted == 0 {
+                    let reasons: Vec<String> = ctx
+                        .publisher
+                        .rejections(&id)
+                        .into_iter()
+                        .map(|(relay, reason)| format!("{} ({})", reason, relay))
+                        .collect();
+                    Text::new(format!("Rejected: {}", reasons.join(", ")))
+                        .size(14)
+                        .style(palette.red)
+                } else {
+                    Text::new(format!(
+                        "Sent to {} of {} relays",
+                        status.accepted, status.total
+                    ))
+                    .size(14)
+                    .style(palette.grey)
+                },
+            );
+        }
+
+        let mut post = Column::new()
             .push(Row::new().push(Text::new(display_name)))
             .push(Row::new().push(Text::new(self.event.content.clone())))
             .push(Space::with_height(Length::Fixed(15.0)))
-            .push(Row::new().push(Text::new(dt.format("%Y-%m-%d %H:%M:%S").to_string()).size(14)))
-            .push(buttons)
-            .push(Rule::horizontal(1))
-            .spacing(10);
+            .push(date)
+            .push(buttons);
+        if ctx.expanded_event.as_ref() == Some(&id) {
+            post = post.push(self.view_details(ctx, &id));
+        }
+        let post = post.push(Rule::horizontal(1)).spacing(10);
 
-        Container::new(post).padding(15)
+        let post = Container::new(post).padding(15);
+        if ctx.selected_event.as_ref() == Some(&id) {
+            post.style(theme::Container::Box)
+        } else {
+            post
+        }
     }
 }
@@ -1,8 +1,25 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
 use nostr_sdk::Client;
 
+use super::notification::Notifications;
+use super::publisher::Publisher;
+use super::relay_auth::RelayAuth;
+use super::relay_health::RelayHealth;
+use super::relay_info::RelayInfoCache;
+use super::relay_policy::RelayPolicies;
+use crate::checkpoint::Checkpoints;
+use crate::config::Config;
+use crate::db::Database;
+use crate::message::SyncStatus;
+use crate::net;
+use crate::nips::nip19::Entity;
+use crate::notifier::Notifier;
+use crate::seen_on::SeenOn;
+use crate::theme::Palette;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Setting {
     Main,
@@ -17,6 +34,7 @@ pub enum Stage {
     Contacts,
     Notifications,
     Profile,
+    Thread,
     Setting(Setting),
 }
 
@@ -28,14 +46,63 @@ impl Default for Stage {
 
 #[derive(Clone)]
 pub struct Context {
-    //pub config: ConfigContext,
+    pub config: Config,
     pub stage: Stage,
     pub client: Client,
+    pub db: Database,
+    pub notifications: Notifications,
+    pub notifier: Notifier,
+    pub relay_info: RelayInfoCache,
+    pub relay_auth: RelayAuth,
+    pub relay_health: RelayHealth,
+    pub relay_policies: RelayPolicies,
+    pub publisher: Publisher,
+    pub seen_on: SeenOn,
+    /// Sync checkpoints, shared with the sync to reopen its subscriptions on a relay
+    pub checkpoints: Checkpoints,
+    /// Post selected with the keyboard
+    pub selected_event: Option<String>,
+    /// Whether the shortcut cheat sheet is shown
+    pub show_shortcuts: bool,
+    /// Post whose details panel is open
+    pub expanded_event: Option<String>,
+    /// Profile or thread opened from a `nostr:` URI, `None` for our own profile
+    pub open: Option<Entity>,
+    pub sync_status: Option<SyncStatus>,
+    /// Whether there are screens in the history, in each direction
+    pub can_go_back: bool,
+    pub can_go_forward: bool,
 }
 
 impl Context {
-    pub fn new(stage: Stage, client: Client) -> Self {
-        Self { stage, client }
+    pub fn new(stage: Stage, client: Client, config: Config) -> Self {
+        let public_key = client.keys().public_key();
+        let notifications = Notifications::new(public_key);
+        let notifier = Notifier::new(config.notifications);
+        let relay_policies = RelayPolicies::new(config.relay_policies(&public_key));
+        let db = Database::load(&public_key);
+        Self {
+            config,
+            stage,
+            client,
+            publisher: Publisher::new(public_key, db.clone()),
+            db,
+            notifications,
+            notifier,
+            relay_info: RelayInfoCache::load(),
+            relay_auth: RelayAuth::default(),
+            relay_health: RelayHealth::default(),
+            relay_policies,
+            seen_on: SeenOn::load(public_key),
+            checkpoints: Checkpoints::load(public_key),
+            selected_event: None,
+            show_shortcuts: false,
+            expanded_event: None,
+            open: None,
+            sync_status: None,
+            can_go_back: false,
+            can_go_forward: false,
+        }
     }
 
     pub fn set_stage(&mut self, stage: Stage) {
@@ -45,4 +112,38 @@ impl Context {
     pub fn set_client(&mut self, client: Client) {
         self.client = client;
     }
+
+    /// Write the relay policies of the account in the settings file
+    pub fn save_relay_policies(&mut self) {
+        let public_key = self.client.keys().public_key();
+        self.config
+            .set_relay_policies(&public_key, self.relay_policies.state());
+        self.config.save();
+    }
+
+    /// Write the proxy settings the connections use in the settings file
+    pub fn save_network(&mut self) {
+        self.config.network = net::settings();
+        self.config.save();
+    }
+
+    /// Colors of the active theme
+    pub fn palette(&self) -> Palette {
+        self.config.appearance.theme.palette()
+    }
+
+    /// Profile display name, or a shortened public key if we don't know it
+    pub fn display_name(&self, public_key: &XOnlyPublicKey) -> String {
+        if let Ok(Ok(profile)) = self
+            .client
+            .store()
+            .map(|store| store.get_profile(*public_key))
+        {
+            if let Some(display_name) = profile.display_name {
+                return display_name;
+            }
+        }
+        let pk = public_key.to_string();
+        format!("{}:{}", &pk[0..8], &pk[pk.len() - 8..])
+    }
 }
@@ -0,0 +1,69 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Back/forward navigation between screens
+
+use iced::widget::scrollable::RelativeOffset;
+
+use super::{Stage, State};
+use crate::nips::nip19::Entity;
+
+/// Screens kept in each direction, oldest dropped first
+const MAX_PAGES: usize = 50;
+
+/// Screen left for another one, kept with its state as it was
+pub struct Page {
+    pub stage: Stage,
+    pub open: Option<Entity>,
+    pub state: Box<dyn State>,
+    pub offset: RelativeOffset,
+}
+
+#[derive(Default)]
+pub struct History {
+    back: Vec<Page>,
+    forward: Vec<Page>,
+}
+
+impl History {
+    /// Leave `page` for a new screen. The forward pages are dropped.
+    pub fn push(&mut self, page: Page) {
+        self.forward.clear();
+        push_bounded(&mut self.back, page);
+    }
+
+    /// Page to go back to
+    pub fn pop_back(&mut self) -> Option<Page> {
+        self.back.pop()
+    }
+
+    /// Page to go forward to
+    pub fn pop_forward(&mut self) -> Option<Page> {
+        self.forward.pop()
+    }
+
+    /// Page left going back, reachable going forward
+    pub fn push_forward(&mut self, page: Page) {
+        push_bounded(&mut self.forward, page);
+    }
+
+    /// Page left going forward, reachable going back
+    pub fn push_back(&mut self, page: Page) {
+        push_bounded(&mut self.back, page);
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+fn push_bounded(pages: &mut Vec<Page>, page: Page) {
+    pages.push(page);
+    if pages.len() > MAX_PAGES {
+        pages.remove(0);
+    }
+}
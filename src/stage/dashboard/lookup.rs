@@ -0,0 +1,63 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! One-off queries for events that may not be in the store
+
+use std::collections::HashSet;
+
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{Event, SubscriptionFilter};
+use nostr_sdk::Client;
+
+use crate::net;
+
+/// Events matching `filters` on our relays and on the relay `hints`, newest first
+pub async fn get_events(
+    client: &Client,
+    hints: &[String],
+    filters: Vec<SubscriptionFilter>,
+) -> Vec<Event> {
+    let mut events = match client.get_events_of(filters.clone()).await {
+        Ok(events) => events,
+        Err(e) => {
+            log::error!("Impossible to get events: {}", e);
+            Vec::new()
+        }
+    };
+
+    let connected = client.relays().await;
+    let hints: Vec<Url> = hints
+        .iter()
+        .filter_map(|hint| Url::parse(hint).ok())
+        .filter(|url| !connected.contains_key(url))
+        .collect();
+    if !hints.is_empty() {
+        // Hints are queried through a short-lived client sharing our keys
+        let temp = Client::new(&client.keys());
+        for url in hints.into_iter() {
+            let result = match net::relay_proxy(&url) {
+                Ok(proxy) => temp
+                    .add_relay(url.clone(), proxy)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = result {
+                log::error!("Impossible to add {}: {}", url, e);
+            }
+        }
+        temp.connect().await;
+        match temp.get_events_of(filters).await {
+            Ok(hinted) => events.extend(hinted),
+            Err(e) => log::error!("Impossible to get events from relay hints: {}", e),
+        }
+        if let Err(e) = temp.shutdown().await {
+            log::error!("Impossible to shutdown client: {}", e);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    events.retain(|event| seen.insert(event.id.to_hex()));
+    events.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+    events
+}
@@ -1,24 +1,77 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::{Command, Element, Subscription};
-use nostr_sdk::Client;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use iced::futures::future;
+use iced::keyboard::key::Named;
+use iced::keyboard::{self, Key};
+use iced::widget::scrollable::{self, RelativeOffset};
+use iced::widget::text_input;
+use iced::{
+    clipboard, event, mouse, time, window, Command, Element, Event as IcedEvent, Subscription,
+};
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, Event, EventId, RelayMessage, SubscriptionFilter};
+use nostr_sdk::{Client, Relay, RelayPoolNotifications, RelayStatus};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{self};
+use tokio::time::Instant;
 
 pub mod component;
 mod context;
+pub mod history;
+pub mod lookup;
+pub mod notification;
+pub mod publisher;
+pub mod relay_auth;
+pub mod relay_health;
+pub mod relay_info;
+pub mod relay_policy;
 pub mod screen;
+pub mod subscription;
 
+use self::component::CONTENT_ID;
 pub use self::context::{Context, Setting, Stage};
+use self::history::{History, Page};
+use self::publisher::{Delivery, PendingEvent};
+use self::relay_auth::{AuthPolicy, AuthState};
+use self::relay_policy::RelayPolicies;
 use self::screen::{
-    ChatState, ContactsState, ExploreState, HomeMessage, HomeState, NotificationsState,
-    ProfileState, RelaysState, SettingState,
+    ChatMessage, ChatState, ContactsState, ExploreMessage, ExploreState, HomeMessage, HomeState,
+    NotificationsMessage, NotificationsState, ProfileMessage, ProfileState, RelaysMessage,
+    RelaysState, SettingMessage, SettingState, ThreadMessage, ThreadState, COMPOSER_ID,
+    SEARCH_INPUT_ID,
 };
-use crate::message::Message;
-use crate::sync::NostrSync;
+use self::subscription::ScreenSubscription;
+use crate::config::Config;
+use crate::message::{DashboardMessage, Message, SyncStatus};
+use crate::nips::nip19::Entity;
+use crate::nips::nip65::{self, RelayPolicy};
+use crate::nips::raw::{now, RawEvent};
+use crate::shortcut::{self, Action, Match};
+use crate::sync::{self, NostrSync, SyncEvent};
+use crate::{net, theme};
+
+const AUTH_NIP: u16 = 42;
+const REPOST_KIND: u64 = 6;
+const REACTION_KIND: u64 = 7;
+
+/// How long a relay has to answer a published event
+const OK_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct App {
     pub state: Box<dyn State>,
     pub context: Context,
+    screen_subscription: Option<ScreenSubscription>,
+    /// Events of the delivery rounds running
+    delivering: HashSet<String>,
+    history: History,
+    /// Scroll position of the active screen, kept with it in the history
+    offset: RelativeOffset,
+    /// Keys of a shortcut being typed
+    pending_keys: Vec<String>,
 }
 
 pub fn new_state(context: &Context) -> Box<dyn State> {
@@ -28,7 +81,13 @@ pub fn new_state(context: &Context) -> Box<dyn State> {
         Stage::Chats => ChatState::new().into(),
         Stage::Contacts => ContactsState::new().into(),
         Stage::Notifications => NotificationsState::new().into(),
-        Stage::Profile => ProfileState::new().into(),
+        Stage::Profile => match &context.open {
+            Some(Entity::Profile { public_key, relays }) => {
+                ProfileState::new(Some(*public_key), relays.clone()).into()
+            }
+            _ => ProfileState::new(None, Vec::new()).into(),
+        },
+        Stage::Thread => ThreadState::new(context.open.clone()).into(),
         Stage::Setting(s) => match s {
             Setting::Main => SettingState::new().into(),
             Setting::Relays => RelaysState::new().into(),
@@ -43,29 +102,56 @@ pub trait State {
     fn subscription(&self) -> Subscription<Message> {
         Subscription::none()
     }
+    /// Filters of the relay subscription kept open while the screen is active
+    fn filters(&self, _ctx: &Context) -> Vec<SubscriptionFilter> {
+        Vec::new()
+    }
     fn load(&mut self, _ctx: &Context) -> Command<Message> {
         Command::none()
     }
+    /// Posts listed by the screen, in order, for the keyboard selection
+    fn posts(&self, _ctx: &Context) -> Vec<Event> {
+        Vec::new()
+    }
 }
 
 impl App {
-    pub fn new(client: Client) -> (Self, Command<Message>) {
-        let context = Context::new(Stage::default(), client.clone());
+    pub fn new(client: Client, config: Config) -> (Self, Command<Message>) {
+        let context = Context::new(Stage::default(), client.clone(), config);
         let app = Self {
             state: new_state(&context),
             context,
+            screen_subscription: None,
+            delivering: HashSet::new(),
+            history: History::default(),
+            offset: RelativeOffset::START,
+            pending_keys: Vec::new(),
         };
+        let notifications = NotificationsState::fetch(client.clone());
+        let import_relay_list = !app.context.relay_policies.is_imported();
         (
             app,
-            Command::perform(
-                async move {
-                    if let Err(e) = client.restore_relays().await {
-                        log::error!("Impossible to load relays: {}", e.to_string());
-                    }
-                    client.connect().await;
-                },
-                |_| Message::Tick,
-            ),
+            Command::batch(vec![
+                Command::perform(
+                    async move {
+                        if let Err(e) = client.restore_relays().await {
+                            log::error!("Impossible to load relays: {}", e.to_string());
+                        }
+                        let adopted = net::adopt_relay_proxies(&client).await;
+                        let reconnected = net::apply(&client).await;
+                        let relay_list = if import_relay_list {
+                            Some(import_relay_list_of(&client).await)
+                        } else {
+                            None
+                        };
+                        (adopted, reconnected, relay_list)
+                    },
+                    |(adopted, reconnected, relay_list)| {
+                        RelaysMessage::RelaysRestored(adopted, reconnected, relay_list).into()
+                    },
+                ),
+                notifications,
+            ]),
         )
     }
 
@@ -74,28 +160,799 @@ impl App {
     }
 
     pub fn subscription(&self) -> Subscription<Message> {
-        let sync = NostrSync::subscription(self.context.client.clone()).map(Message::Sync);
-        Subscription::batch(vec![sync, self.state.subscription()])
+        let sync = NostrSync::subscription(
+            self.context.client.clone(),
+            self.context.db.clone(),
+            self.context.relay_info.clone(),
+            self.context.relay_policies.clone(),
+            self.context.relay_health.clone(),
+            self.context.notifier.clone(),
+            self.context.seen_on.clone(),
+            self.context.checkpoints.clone(),
+        )
+        .map(|event| match event {
+            SyncEvent::Event(event) => Message::Sync(event),
+            SyncEvent::Started => Message::SyncStatus(SyncStatus::Started),
+            SyncEvent::Failed { error, retry_in } => Message::SyncStatus(SyncStatus::Failed {
+                error,
+                retry_at: now() + retry_in.as_secs(),
+            }),
+            SyncEvent::AuthChallenge { challenge, relays } => {
+                RelaysMessage::AuthChallenge(challenge, relays).into()
+            }
+            SyncEvent::Ok {
+                event_id,
+                accepted,
+                message,
+            } => Message::RelayOk {
+                event_id,
+                accepted,
+                message,
+            },
+        });
+        let notifications =
+            time::every(Duration::from_secs(60)).map(|_| NotificationsMessage::Refresh.into());
+        let relay_health =
+            time::every(Duration::from_secs(10)).map(|_| RelaysMessage::ProbeRelays.into());
+        let publish = time::every(Duration::from_secs(30)).map(|_| Message::RetryPublish);
+        let events = event::listen_with(|event, status| match event {
+            IcedEvent::Window(_, window::Event::Focused) => Some(Message::WindowFocused(true)),
+            IcedEvent::Window(_, window::Event::Unfocused) => Some(Message::WindowFocused(false)),
+            IcedEvent::Mouse(mouse::Event::ButtonPressed(mouse::Button::Back)) => {
+                Some(Message::NavigateBack)
+            }
+            IcedEvent::Mouse(mouse::Event::ButtonPressed(mouse::Button::Forward)) => {
+                Some(Message::NavigateForward)
+            }
+            IcedEvent::Keyboard(keyboard::Event::KeyPressed {
+                key: Key::Named(key),
+                modifiers,
+                ..
+            }) if modifiers.alt() => match key {
+                Named::ArrowLeft => Some(Message::NavigateBack),
+                Named::ArrowRight => Some(Message::NavigateForward),
+                _ => None,
+            },
+            // Keys typed in a text input are captured by it
+            IcedEvent::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. })
+                if status == event::Status::Ignored
+                    && !(modifiers.control() || modifiers.alt() || modifiers.logo()) =>
+            {
+                shortcut::key_name(&key).map(Message::Shortcut)
+            }
+            _ => None,
+        });
+        Subscription::batch(vec![
+            sync,
+            notifications,
+            relay_health,
+            publish,
+            events,
+            self.state.subscription(),
+        ])
     }
 
     pub fn update(&mut self, message: Message) -> Command<Message> {
+        // Filters change with the screen, or with what the screen shows (channel, hashtag,
+        // loaded thread...), so sync events and timers don't need a check
+        let changes_screen = matches!(
+            message,
+            Message::SetDashboardStage(_)
+                | Message::Open(_)
+                | Message::NavigateBack
+                | Message::NavigateForward
+                | Message::Shortcut(_)
+                | Message::Dashboard(_)
+        );
+        let command = self.handle_message(message);
+        if changes_screen {
+            Command::batch(vec![command, self.update_screen_subscription()])
+        } else {
+            command
+        }
+    }
+
+    /// Open the subscription of the active screen, closing the previous one, when the
+    /// filters it needs change
+    fn update_screen_subscription(&mut self) -> Command<Message> {
+        let filters = self.state.filters(&self.context);
+        match &self.screen_subscription {
+            Some(current) if current.has_filters(&filters) => return Command::none(),
+            None if filters.is_empty() => return Command::none(),
+            _ => (),
+        }
+
+        let previous = self.screen_subscription.take().map(|s| s.id().to_string());
+        let next = if filters.is_empty() {
+            None
+        } else {
+            let stage = format!("{:?}", self.context.stage).to_lowercase();
+            Some(ScreenSubscription::new(&stage, &filters))
+        };
+        let next_req = next.as_ref().map(|s| (s.id().to_string(), filters));
+        self.screen_subscription = next;
+
+        let client = self.context.client.clone();
+        let policies = self.context.relay_policies.clone();
+        Command::perform(
+            async move { subscription::replace(&client, &policies, previous, next_req).await },
+            |_| Message::Tick,
+        )
+    }
+
+    /// Open or close the sync and screen subscriptions on `urls`, when we start or stop
+    /// reading from them
+    fn set_relays_read(&self, urls: Vec<Url>, read: bool) -> Command<Message> {
+        let client = self.context.client.clone();
+        let relay_info = self.context.relay_info.clone();
+        let checkpoints = self.context.checkpoints.clone();
+        let screen = self
+            .screen_subscription
+            .as_ref()
+            .map(|s| (s.id().to_string(), s.filters().to_vec()));
+        Command::perform(
+            async move {
+                for url in urls.into_iter() {
+                    if read {
+                        if let Err(e) =
+                            sync::resubscribe(&client, &relay_info, &checkpoints, &url).await
+                        {
+                            log::error!("Impossible to subscribe to {}: {}", url, e);
+                        }
+                    } else {
+                        sync::unsubscribe(&client, &checkpoints, &url).await;
+                    }
+                    if let Some((id, filters)) = screen.clone() {
+                        subscription::set_read(&client, &url, id, filters, read).await;
+                    }
+                }
+            },
+            |_| Message::Tick,
+        )
+    }
+
+    /// Open the subscriptions again on the relays we read from among `urls`: a relay
+    /// accepting our auth may have refused them until then, and a relay added again to
+    /// the pool comes back without them
+    fn resubscribe(&self, urls: Vec<Url>) -> Command<Message> {
+        let urls: Vec<Url> = urls
+            .into_iter()
+            .filter(|url| self.context.relay_policies.get(url).read)
+            .collect();
+        if urls.is_empty() {
+            Command::none()
+        } else {
+            self.set_relays_read(urls, true)
+        }
+    }
+
+    fn handle_message(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::SetDashboardStage(stage) => {
-                self.context.set_stage(stage);
-                self.state = new_state(&self.context);
+                let scroll = self.navigate(stage, None);
+                let command = self.state.update(&mut self.context, message);
+                Command::batch(vec![command, scroll])
+            }
+            Message::Open(entity) => {
+                let stage = match entity {
+                    Entity::Profile { .. } => Stage::Profile,
+                    Entity::Event { .. } | Entity::Address { .. } => Stage::Thread,
+                };
+                let scroll = self.navigate(stage, Some(entity));
+                let command = self.state.load(&self.context);
+                Command::batch(vec![command, scroll])
+            }
+            Message::NavigateBack => match self.history.pop_back() {
+                Some(page) => {
+                    let current = self.restore(page);
+                    self.history.push_forward(current);
+                    self.after_navigation()
+                }
+                None => Command::none(),
+            },
+            Message::NavigateForward => match self.history.pop_forward() {
+                Some(page) => {
+                    let current = self.restore(page);
+                    self.history.push_back(current);
+                    self.after_navigation()
+                }
+                None => Command::none(),
+            },
+            Message::Scrolled(viewport) => {
+                self.offset = viewport.relative_offset();
                 self.state.update(&mut self.context, message)
             }
-            Message::Sync(event) => match self.context.stage {
-                Stage::Home => self
+            Message::Dashboard(DashboardMessage::Notifications(NotificationsMessage::Refresh)) => {
+                NotificationsState::fetch(self.context.client.clone())
+            }
+            Message::Dashboard(DashboardMessage::Notifications(
+                NotificationsMessage::SetEvents(events),
+            )) => {
+                for event in events.iter() {
+                    self.context.notifications.handle_event(event);
+                }
+                Command::none()
+            }
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::RelaysRestored(adopted, reconnected, relay_list),
+            ))) => {
+                if adopted {
+                    self.context.save_network();
+                }
+                if let Some(Some(relays)) = relay_list {
+                    self.context.relay_policies.import(relays);
+                    self.context.save_relay_policies();
+                }
+                self.resubscribe(reconnected)
+            }
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::Reconnected(reconnected),
+            ))) => {
+                let resubscribe = self.resubscribe(reconnected);
+                let update = self
                     .state
-                    .update(&mut self.context, HomeMessage::PushTextNote(event).into()),
-                _ => Command::none(),
-            },
+                    .update(&mut self.context, RelaysMessage::UpdateRelays.into());
+                Command::batch(vec![resubscribe, update])
+            }
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::SetPolicy(url, policy),
+            ))) => {
+                let read_changed = self.context.relay_policies.get(&url).read != policy.read;
+                self.context.relay_policies.set(&url, policy);
+                self.context.save_relay_policies();
+                if read_changed {
+                    self.set_relays_read(vec![url], policy.read)
+                } else {
+                    Command::none()
+                }
+            }
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::ProbeRelays,
+            ))) => {
+                let client = self.context.client.clone();
+                let relay_health = self.context.relay_health.clone();
+                Command::perform(
+                    async move { relay_health::probe(&client, &relay_health).await },
+                    |_| Message::Tick,
+                )
+            }
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::AuthChallenge(challenge, relays),
+            ))) => self.handle_auth_challenge(challenge, relays),
+            Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
+                RelaysMessage::AuthSent(results),
+            ))) => {
+                for (url, result) in results.into_iter() {
+                    let state = match result {
+                        Ok(event_id) => AuthState::Pending(event_id),
+                        Err(e) => AuthState::Failed(e),
+                    };
+                    self.context.relay_auth.set_state(&url, state);
+                }
+                Command::none()
+            }
+            Message::RelayOk {
+                event_id,
+                accepted,
+                message,
+            } => {
+                match self
+                    .context
+                    .relay_auth
+                    .handle_ok(&event_id, accepted, &message)
+                {
+                    Some(url) => self.resubscribe(vec![url]),
+                    None => Command::none(),
+                }
+            }
+            Message::Dashboard(DashboardMessage::Home(HomeMessage::Like(event))) => {
+                let raw = RawEvent::new(
+                    self.context.client.keys().public_key(),
+                    now(),
+                    REACTION_KIND,
+                    vec![
+                        vec![String::from("e"), event.id.to_hex()],
+                        vec![String::from("p"), event.pubkey.to_string()],
+                    ],
+                    String::from("+"),
+                );
+                self.sign_and_publish(raw, "reaction")
+            }
+            Message::Dashboard(DashboardMessage::Home(HomeMessage::Repost(event))) => {
+                let content = RawEvent::from_event(&event)
+                    .map(|raw| raw.as_json())
+                    .unwrap_or_default();
+                let raw = RawEvent::new(
+                    self.context.client.keys().public_key(),
+                    now(),
+                    REPOST_KIND,
+                    vec![
+                        vec![String::from("e"), event.id.to_hex()],
+                        vec![String::from("p"), event.pubkey.to_string()],
+                    ],
+                    content,
+                );
+                self.sign_and_publish(raw, "repost")
+            }
+            Message::Dashboard(DashboardMessage::Home(HomeMessage::Reply(event))) => {
+                let id = event.id.to_hex();
+                let relays = self.context.seen_on.hints(&id);
+                let entity = Entity::Event {
+                    id,
+                    relays,
+                    author: Some(event.pubkey),
+                };
+                let command = self.handle_message(Message::Open(entity));
+                Command::batch(vec![command, text_input::focus(COMPOSER_ID.clone())])
+            }
+            Message::Shortcut(key) => self.handle_shortcut(key),
+            Message::ToggleShortcuts => {
+                self.context.show_shortcuts = !self.context.show_shortcuts;
+                Command::none()
+            }
+            Message::Publish(event) => {
+                self.context.publisher.enqueue(&event);
+                self.deliver_pending()
+            }
+            Message::RetryPublish => self.deliver_pending(),
+            Message::Delivered(ids, results) => {
+                for id in ids.iter() {
+                    self.delivering.remove(id);
+                }
+                self.context.publisher.record(results);
+                Command::none()
+            }
+            Message::ToggleEventDetails(id) => {
+                if self.context.expanded_event.as_ref() == Some(&id) {
+                    self.context.expanded_event = None;
+                } else {
+                    self.context.expanded_event = Some(id);
+                }
+                Command::none()
+            }
+            Message::Clipboard(text) => clipboard::write(text),
+            Message::WindowFocused(focused) => {
+                self.context.notifier.set_focused(focused);
+                if focused {
+                    theme::refresh_system();
+                }
+                Command::none()
+            }
+            Message::SyncStatus(status) => {
+                let started = status == SyncStatus::Started;
+                self.context.sync_status = Some(status);
+                if started {
+                    self.deliver_pending()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::Sync(event) => {
+                self.context.notifications.handle_event(&event);
+                self.handle_sync(event)
+            }
             _ => self.state.update(&mut self.context, message),
         }
     }
 
+    fn sign_and_publish(&mut self, raw: RawEvent, what: &str) -> Command<Message> {
+        let keys = self.context.client.keys();
+        match keys.secret_key().map(|secret_key| raw.sign(&secret_key)) {
+            Ok(Ok(event)) => {
+                self.context.publisher.enqueue(&event);
+                self.deliver_pending()
+            }
+            Ok(Err(e)) => {
+                log::error!("Impossible to sign {}: {}", what, e);
+                Command::none()
+            }
+            Err(e) => {
+                log::error!("Impossible to sign {}: {}", what, e);
+                Command::none()
+            }
+        }
+    }
+
+    /// Add `key` to the shortcut being typed and run its action once it's complete
+    fn handle_shortcut(&mut self, key: String) -> Command<Message> {
+        self.pending_keys.push(key);
+        let mut matched = self.context.config.keys.resolve(&self.pending_keys);
+        // Not the end of the sequence started, but maybe a shortcut on its own
+        if matched == Match::None && self.pending_keys.len() > 1 {
+            self.pending_keys.drain(..self.pending_keys.len() - 1);
+            matched = self.context.config.keys.resolve(&self.pending_keys);
+        }
+        let action = match matched {
+            Match::Action(action) => action,
+            Match::Prefix => return Command::none(),
+            Match::None => {
+                self.pending_keys.clear();
+                return Command::none();
+            }
+        };
+        self.pending_keys.clear();
+
+        match action {
+            Action::Next => self.move_selection(true),
+            Action::Previous => self.move_selection(false),
+            Action::Like => match self.selected_post() {
+                Some(event) => self.handle_message(HomeMessage::Like(event).into()),
+                None => Command::none(),
+            },
+            Action::Reply => match self.selected_post() {
+                Some(event) => self.handle_message(HomeMessage::Reply(event).into()),
+                None => Command::none(),
+            },
+            Action::Repost => match self.selected_post() {
+                Some(event) => self.handle_message(HomeMessage::Repost(event).into()),
+                None => Command::none(),
+            },
+            Action::GoHome => self.handle_message(Message::SetDashboardStage(Stage::Home)),
+            Action::GoNotifications => {
+                self.handle_message(Message::SetDashboardStage(Stage::Notifications))
+            }
+            Action::GoChats => self.handle_message(Message::SetDashboardStage(Stage::Chats)),
+            Action::Search => {
+                let command = self.handle_message(Message::SetDashboardStage(Stage::Explore));
+                Command::batch(vec![command, text_input::focus(SEARCH_INPUT_ID.clone())])
+            }
+            Action::Back if self.context.show_shortcuts => {
+                self.context.show_shortcuts = false;
+                Command::none()
+            }
+            Action::Back => self.handle_message(Message::NavigateBack),
+            Action::Help => self.handle_message(Message::ToggleShortcuts),
+        }
+    }
+
+    fn selected_post(&self) -> Option<Event> {
+        let id = self.context.selected_event.as_ref()?;
+        self.state
+            .posts(&self.context)
+            .into_iter()
+            .find(|event| &event.id.to_hex() == id)
+    }
+
+    /// Select the next or previous post of the screen and scroll to it
+    fn move_selection(&mut self, next: bool) -> Command<Message> {
+        let posts = self.state.posts(&self.context);
+        if posts.is_empty() {
+            return Command::none();
+        }
+        let current = self
+            .context
+            .selected_event
+            .as_ref()
+            .and_then(|id| posts.iter().position(|event| &event.id.to_hex() == id));
+        let index = match current {
+            Some(i) if next => (i + 1).min(posts.len() - 1),
+            Some(i) => i.saturating_sub(1),
+            None => 0,
+        };
+        self.context.selected_event = Some(posts[index].id.to_hex());
+        // Posts are roughly the same height
+        let y = if posts.len() > 1 {
+            index as f32 / (posts.len() - 1) as f32
+        } else {
+            0.0
+        };
+        scrollable::snap_to(CONTENT_ID.clone(), RelativeOffset { x: 0.0, y })
+    }
+
+    /// Open a new screen, keeping the current one in the history unless it's the same
+    fn navigate(&mut self, stage: Stage, open: Option<Entity>) -> Command<Message> {
+        let same = self.context.stage == stage && self.context.open == open;
+        let previous_stage = self.context.stage;
+        let previous_open = std::mem::replace(&mut self.context.open, open);
+        self.context.set_stage(stage);
+        let previous = Page {
+            stage: previous_stage,
+            open: previous_open,
+            state: std::mem::replace(&mut self.state, new_state(&self.context)),
+            offset: self.offset,
+        };
+        if !same {
+            self.history.push(previous);
+        }
+        self.offset = RelativeOffset::START;
+        self.after_navigation()
+    }
+
+    /// Bring back a page of the history, returning the one it replaces
+    fn restore(&mut self, page: Page) -> Page {
+        let Page {
+            stage,
+            open,
+            state,
+            offset,
+        } = page;
+        let current = Page {
+            stage: self.context.stage,
+            open: std::mem::replace(&mut self.context.open, open),
+            state: std::mem::replace(&mut self.state, state),
+            offset: std::mem::replace(&mut self.offset, offset),
+        };
+        self.context.set_stage(stage);
+        current
+    }
+
+    /// Update the navbar buttons and scroll to where the screen was left
+    fn after_navigation(&mut self) -> Command<Message> {
+        self.context.can_go_back = self.history.can_go_back();
+        self.context.can_go_forward = self.history.can_go_forward();
+        self.context.selected_event = None;
+        self.context.show_shortcuts = false;
+        scrollable::snap_to(CONTENT_ID.clone(), self.offset)
+    }
+
+    /// Start a delivery round of the events of the publish queue not already being
+    /// delivered
+    fn deliver_pending(&mut self) -> Command<Message> {
+        let pending: Vec<PendingEvent> = self
+            .context
+            .publisher
+            .pending()
+            .into_iter()
+            .filter(|pending| !self.delivering.contains(&pending.id))
+            .collect();
+        if pending.is_empty() {
+            return Command::none();
+        }
+        let ids: Vec<String> = pending.iter().map(|pending| pending.id.clone()).collect();
+        self.delivering.extend(ids.iter().cloned());
+        let client = self.context.client.clone();
+        let policies = self.context.relay_policies.clone();
+        Command::perform(
+            async move { deliver(&client, &policies, pending).await },
+            move |results| Message::Delivered(ids, results),
+        )
+    }
+
+    /// Answer a NIP-42 challenge according to the auth policy of the relay it comes from.
+    /// The SDK doesn't tell which relay sent it, so it's only answered when a single
+    /// connected relay can have sent it, relays advertising NIP-42 first: signing it for
+    /// the others would give our identity away to relays that didn't ask.
+    fn handle_auth_challenge(&mut self, challenge: String, relays: Vec<Url>) -> Command<Message> {
+        let supports_auth = |url: &Url| {
+            self.context
+                .relay_info
+                .get(url)
+                .map(|d| d.supports(AUTH_NIP) || d.limitation.auth_required)
+                .unwrap_or(false)
+        };
+        let advertising: Vec<&Url> = relays.iter().filter(|url| supports_auth(url)).collect();
+        let url = match (advertising.as_slice(), relays.as_slice()) {
+            ([url], _) => (*url).clone(),
+            ([], [url]) => url.clone(),
+            _ => {
+                log::warn!(
+                    "Ignoring auth challenge: can't tell which of {} relays sent it",
+                    relays.len()
+                );
+                self.context.relay_auth.set_ignored(relays.len());
+                return Command::none();
+            }
+        };
+
+        match self.context.config.relays.auth_policy(&url) {
+            AuthPolicy::Always => {
+                let client = self.context.client.clone();
+                Command::perform(
+                    async move { relay_auth::authenticate(&client, vec![url], challenge).await },
+                    |results| RelaysMessage::AuthSent(results).into(),
+                )
+            }
+            AuthPolicy::Ask => {
+                self.context
+                    .relay_auth
+                    .set_state(&url, AuthState::Challenged(challenge));
+                Command::none()
+            }
+            AuthPolicy::Never => {
+                self.context.relay_auth.set_state(&url, AuthState::Refused);
+                Command::none()
+            }
+        }
+    }
+
+    fn handle_sync(&mut self, event: Event) -> Command<Message> {
+        match self.context.stage {
+            Stage::Home => self
+                .state
+                .update(&mut self.context, HomeMessage::PushTextNote(event).into()),
+            Stage::Chats => self
+                .state
+                .update(&mut self.context, ChatMessage::Received(event).into()),
+            Stage::Explore => self
+                .state
+                .update(&mut self.context, ExploreMessage::Received(event).into()),
+            Stage::Thread => self
+                .state
+                .update(&mut self.context, ThreadMessage::Received(event).into()),
+            Stage::Profile => self
+                .state
+                .update(&mut self.context, ProfileMessage::Received(event).into()),
+            _ => Command::none(),
+        }
+    }
+
     pub fn view(&self) -> Element<Message> {
         self.state.view(&self.context)
     }
 }
+
+/// Send the pending events to the connected write relays, all at once, and wait for
+/// their `OK`. Disconnected relays, and the ones not answering, stay pending, so they're
+/// tried again next round.
+async fn deliver(
+    client: &Client,
+    policies: &RelayPolicies,
+    pending: Vec<PendingEvent>,
+) -> Vec<(String, Url, Delivery)> {
+    let events: Vec<(PendingEvent, Event)> = pending
+        .into_iter()
+        .filter_map(|pending| match Event::from_json(pending.event.clone()) {
+            Ok(event) => Some((pending, event)),
+            Err(e) => {
+                log::error!("Impossible to parse queued event {}: {}", pending.id, e);
+                None
+            }
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    let mut connected: Vec<(Url, Relay)> = Vec::new();
+    for (url, relay) in client.relays().await.into_iter() {
+        if !policies.get(&url).write {
+            continue;
+        }
+        if matches!(relay.status().await, RelayStatus::Connected) {
+            connected.push((url, relay));
+        } else {
+            for (pending, _) in events.iter() {
+                if !pending.settled.iter().any(|relay| relay == url.as_str()) {
+                    results.push((pending.id.clone(), url.clone(), Delivery::Pending));
+                }
+            }
+        }
+    }
+
+    let deliveries = future::join_all(events.iter().map(|(pending, event)| {
+        let relays: Vec<(Url, Relay)> = connected
+            .iter()
+            .filter(|(url, _)| !pending.settled.iter().any(|relay| relay == url.as_str()))
+            .cloned()
+            .collect();
+        deliver_event(client, event, relays)
+    }))
+    .await;
+    for ((pending, _), deliveries) in events.iter().zip(deliveries.into_iter()) {
+        results.extend(
+            deliveries
+                .into_iter()
+                .map(|(url, delivery)| (pending.id.clone(), url, delivery)),
+        );
+    }
+    results
+}
+
+/// Send `event` to `relays` at once. The SDK doesn't tell which relay an `OK` comes from:
+/// when every relay answers the same, that's the answer of each. Otherwise the event is
+/// sent again to one relay at a time, each with its own timeout, relays answering an
+/// event they already have with `OK` too.
+async fn deliver_event(
+    client: &Client,
+    event: &Event,
+    relays: Vec<(Url, Relay)>,
+) -> Vec<(Url, Delivery)> {
+    let mut notifications = client.notifications();
+    let sent = future::join_all(relays.into_iter().map(|(url, relay)| async move {
+        let result = relay
+            .send_msg(ClientMessage::new_event(event.clone()))
+            .await;
+        (url, relay, result)
+    }))
+    .await;
+
+    let mut results = Vec::new();
+    let mut sent_to = Vec::new();
+    for (url, relay, result) in sent.into_iter() {
+        match result {
+            Ok(_) => sent_to.push((url, relay)),
+            Err(e) => {
+                log::debug!("Impossible to send event {} to {}: {}", event.id, url, e);
+                results.push((url, Delivery::Pending));
+            }
+        }
+    }
+
+    let answers = wait_oks(&mut notifications, &event.id, sent_to.len()).await;
+    match answers.first() {
+        None => results.extend(sent_to.into_iter().map(|(url, _)| (url, Delivery::Pending))),
+        Some(answer) if answers.len() == sent_to.len() && answers.iter().all(|a| a == answer) => {
+            let answer = answer.clone();
+            results.extend(sent_to.into_iter().map(|(url, _)| (url, answer.clone())));
+        }
+        Some(_) => {
+            for (url, relay) in sent_to.into_iter() {
+                let delivery = match relay
+                    .send_msg(ClientMessage::new_event(event.clone()))
+                    .await
+                {
+                    Ok(_) => wait_oks(&mut notifications, &event.id, 1)
+                        .await
+                        .pop()
+                        .unwrap_or(Delivery::Pending),
+                    Err(e) => {
+                        log::debug!("Impossible to send event {} to {}: {}", event.id, url, e);
+                        Delivery::Pending
+                    }
+                };
+                results.push((url, delivery));
+            }
+        }
+    }
+    results
+}
+
+/// First `count` `OK` of the event `id` that come in time
+async fn wait_oks(
+    notifications: &mut broadcast::Receiver<RelayPoolNotifications>,
+    id: &EventId,
+    count: usize,
+) -> Vec<Delivery> {
+    let deadline = Instant::now() + OK_TIMEOUT;
+    let mut answers = Vec::new();
+    while answers.len() < count {
+        match tokio::time::timeout_at(deadline, notifications.recv()).await {
+            Ok(Ok(RelayPoolNotifications::ReceivedMessage(RelayMessage::Ok {
+                event_id,
+                status,
+                message,
+            }))) if event_id == *id => {
+                answers.push(if status {
+                    Delivery::Accepted
+                } else {
+                    Delivery::Rejected(message)
+                });
+            }
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => (),
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        }
+    }
+    answers
+}
+
+/// Add the relays of our kind 10002, published by another client, and return them.
+/// `None` if the list couldn't be fetched, so the import is tried again next time.
+async fn import_relay_list_of(client: &Client) -> Option<Vec<(Url, RelayPolicy)>> {
+    let public_key = client.keys().public_key();
+    let relay_list = match nip65::get_relay_lists(client, vec![public_key]).await {
+        Ok(lists) => lists.into_iter().next(),
+        Err(e) => {
+            log::error!("Impossible to get relay list: {}", e);
+            return None;
+        }
+    };
+
+    let relays = relay_list
+        .map(|raw| nip65::relay_list(&raw))
+        .unwrap_or_default();
+    let connected = client.relays().await;
+    for (url, _) in relays.iter() {
+        if !connected.contains_key(url) {
+            let proxy = match net::relay_proxy(url) {
+                Ok(proxy) => proxy,
+                Err(e) => {
+                    log::error!("Impossible to add {}: {}", url, e);
+                    continue;
+                }
+            };
+            if let Err(e) = client.add_relay(url.clone(), proxy).await {
+                log::error!("Impossible to add {}: {}", url, e);
+            }
+        }
+    }
+    client.connect().await;
+    Some(relays)
+}
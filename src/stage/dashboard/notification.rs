@@ -0,0 +1,260 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::nips::raw::{now, RawEvent};
+use crate::nips::{nip10, nip57};
+use crate::util::{dir, json_store};
+
+pub const TEXT_NOTE_KIND: u64 = 1;
+pub const REPOST_KIND: u64 = 6;
+pub const REACTION_KIND: u64 = 7;
+pub const ZAP_RECEIPT_KIND: u64 = 9735;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    Mention,
+    Reply,
+    Reaction,
+    Repost,
+    Zap,
+}
+
+impl NotificationKind {
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Mention => "mentioned you",
+            Self::Reply => "replied to your note",
+            Self::Reaction => "liked your note",
+            Self::Repost => "reposted your note",
+            Self::Zap => "zapped your note",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotificationGroup {
+    pub kind: NotificationKind,
+    /// Note the notification is about. Mentions are grouped by the mentioning note.
+    pub target: String,
+    pub actors: Vec<XOnlyPublicKey>,
+    /// Content of the most recent mention or reply
+    pub preview: Option<String>,
+    pub sats: u64,
+    pub latest_at: u64,
+    events: HashSet<String>,
+    unread: HashSet<String>,
+}
+
+impl NotificationGroup {
+    pub fn is_unread(&self) -> bool {
+        !self.unread.is_empty()
+    }
+
+    /// "Alice and 4 others liked your note"
+    pub fn summary<F>(&self, display_name: F) -> String
+    where
+        F: Fn(&XOnlyPublicKey) -> String,
+    {
+        let who = match self.actors.len() {
+            0 => String::from("Someone"),
+            1 => display_name(&self.actors[0]),
+            2 => format!(
+                "{} and {}",
+                display_name(&self.actors[0]),
+                display_name(&self.actors[1])
+            ),
+            n => format!("{} and {} others", display_name(&self.actors[0]), n - 1),
+        };
+        if self.kind == NotificationKind::Zap && self.sats > 0 {
+            format!("{} {} ({} sats)", who, self.kind.verb(), self.sats)
+        } else {
+            format!("{} {}", who, self.kind.verb())
+        }
+    }
+}
+
+/// Read state persisted between sessions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ReadState {
+    last_read_at: u64,
+    read: HashSet<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notifications {
+    public_key: XOnlyPublicKey,
+    groups: HashMap<(NotificationKind, String), NotificationGroup>,
+    read_state: ReadState,
+    /// Our notes seen so far, a note replying to one of them is a reply
+    own_events: HashSet<String>,
+    /// Replies to notes not known to be ours, filed as mentions until they are
+    undecided: HashMap<String, Vec<RawEvent>>,
+}
+
+impl Notifications {
+    pub fn new(public_key: XOnlyPublicKey) -> Self {
+        let read_state = json_store::load(path(&public_key)).unwrap_or_default();
+        Self {
+            public_key,
+            groups: HashMap::new(),
+            read_state,
+            own_events: HashSet::new(),
+            undecided: HashMap::new(),
+        }
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.groups.values().filter(|g| g.is_unread()).count()
+    }
+
+    /// Most recent first
+    pub fn groups(&self) -> Vec<&NotificationGroup> {
+        let mut groups: Vec<&NotificationGroup> = self.groups.values().collect();
+        groups.sort_by_key(|g| std::cmp::Reverse(g.latest_at));
+        groups
+    }
+
+    pub fn mark_read(&mut self, kind: NotificationKind, target: &str) {
+        if let Some(group) = self.groups.get_mut(&(kind, target.to_string())) {
+            self.read_state.read.extend(group.unread.drain());
+            self.save();
+        }
+    }
+
+    pub fn mark_all_read(&mut self) {
+        for group in self.groups.values_mut() {
+            group.unread.clear();
+        }
+        self.read_state.last_read_at = now();
+        self.read_state.read.clear();
+        self.save();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Ok(raw) = RawEvent::from_event(event) {
+            self.handle_raw(raw);
+        }
+    }
+
+    /// Refile the replies to `id` that were filed as mentions, now that it's ours
+    fn handle_own_note(&mut self, id: String) {
+        if !self.own_events.insert(id.clone()) {
+            return;
+        }
+        for reply in self.undecided.remove(&id).unwrap_or_default().into_iter() {
+            self.groups
+                .remove(&(NotificationKind::Mention, reply.id.clone()));
+            self.handle_raw(reply);
+        }
+    }
+
+    fn handle_raw(&mut self, raw: RawEvent) {
+        let my_public_key = self.public_key.to_string();
+        if raw.pubkey == my_public_key {
+            if raw.kind == TEXT_NOTE_KIND {
+                self.handle_own_note(raw.id);
+            }
+            return;
+        }
+        if !raw.tag_values("p").any(|pk| pk == my_public_key) {
+            return;
+        }
+
+        let last_e_tag = raw.tag_values("e").last().map(String::from);
+        let (kind, target, actor, sats) = match raw.kind {
+            TEXT_NOTE_KIND => match nip10::replied_to(&raw) {
+                Some(parent) if self.own_events.contains(&parent) => {
+                    (NotificationKind::Reply, parent, raw.pubkey().ok(), 0)
+                }
+                parent => {
+                    if let Some(parent) = parent {
+                        self.undecided.entry(parent).or_default().push(raw.clone());
+                    }
+                    (
+                        NotificationKind::Mention,
+                        raw.id.clone(),
+                        raw.pubkey().ok(),
+                        0,
+                    )
+                }
+            },
+            REACTION_KIND if raw.content != "-" => match last_e_tag {
+                Some(target) => (NotificationKind::Reaction, target, raw.pubkey().ok(), 0),
+                None => return,
+            },
+            REPOST_KIND => match last_e_tag {
+                Some(target) => (NotificationKind::Repost, target, raw.pubkey().ok(), 0),
+                None => return,
+            },
+            ZAP_RECEIPT_KIND => {
+                // The zapper is the author of the zap request embedded in the receipt
+                let request = raw
+                    .tag_values("description")
+                    .next()
+                    .and_then(|json| RawEvent::from_json(json).ok());
+                let actor = request.as_ref().and_then(|r| r.pubkey().ok());
+                let msats = nip57::receipt_amount(&raw).unwrap_or_default();
+                let target = last_e_tag.unwrap_or_else(|| raw.id.clone());
+                (NotificationKind::Zap, target, actor, msats / 1000)
+            }
+            _ => return,
+        };
+
+        let unread = raw.created_at > self.read_state.last_read_at
+            && !self.read_state.read.contains(&raw.id);
+
+        let group = self
+            .groups
+            .entry((kind, target.clone()))
+            .or_insert_with(|| NotificationGroup {
+                kind,
+                target,
+                actors: Vec::new(),
+                preview: None,
+                sats: 0,
+                latest_at: 0,
+                events: HashSet::new(),
+                unread: HashSet::new(),
+            });
+
+        if !group.events.insert(raw.id.clone()) {
+            return;
+        }
+        if let Some(actor) = actor {
+            if !group.actors.contains(&actor) {
+                group.actors.insert(0, actor);
+            }
+        }
+        if raw.created_at >= group.latest_at {
+            group.latest_at = raw.created_at;
+            if raw.kind == TEXT_NOTE_KIND {
+                group.preview = Some(raw.content.clone());
+            }
+        }
+        group.sats += sats;
+        if unread {
+            group.unread.insert(raw.id);
+        }
+    }
+
+    fn save(&self) {
+        json_store::save(
+            path(&self.public_key),
+            &self.read_state,
+            "notifications read state",
+        );
+    }
+}
+
+fn path(public_key: &XOnlyPublicKey) -> Option<PathBuf> {
+    dir::default_dir()
+        .ok()
+        .map(|dir| dir.join(format!("notifications-{}.json", public_key)))
+}
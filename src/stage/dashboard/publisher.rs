@@ -0,0 +1,215 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, QueuedEvent};
+use crate::nips::raw::now;
+use crate::util::{dir, json_store};
+
+/// Delivered events kept, to show their status
+const MAX_ENTRIES: usize = 500;
+/// Events not delivered after this are given up
+const MAX_AGE: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "lowercase")]
+pub enum Delivery {
+    /// Not sent yet, or the relay was unreachable
+    Pending,
+    Accepted,
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Entry {
+    id: String,
+    /// Signed event, as JSON
+    event: String,
+    queued_at: u64,
+    /// Relay url -> delivery
+    deliveries: HashMap<String, Delivery>,
+}
+
+impl Entry {
+    fn is_settled(&self, relay: &str) -> bool {
+        !matches!(self.deliveries.get(relay), None | Some(Delivery::Pending))
+    }
+
+    fn from_queued(queued: QueuedEvent) -> Self {
+        Self {
+            deliveries: serde_json::from_str(&queued.deliveries).unwrap_or_default(),
+            id: queued.id,
+            event: queued.json,
+            queued_at: queued.queued_at,
+        }
+    }
+
+    fn to_queued(&self) -> QueuedEvent {
+        QueuedEvent {
+            id: self.id.clone(),
+            json: self.event.clone(),
+            queued_at: self.queued_at,
+            deliveries: self.deliveries_json(),
+        }
+    }
+
+    fn deliveries_json(&self) -> String {
+        serde_json::to_string(&self.deliveries).unwrap_or_else(|_| String::from("{}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryStatus {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub total: usize,
+}
+
+/// Event waiting to be sent to some relays
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    pub id: String,
+    pub event: String,
+    /// Relays that already accepted or rejected it
+    pub settled: Vec<String>,
+}
+
+/// Signed outgoing events and their delivery to each relay, persisted in the database so
+/// events published while offline are sent on the next connection
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    db: Database,
+    entries: Vec<Entry>,
+}
+
+impl Publisher {
+    pub fn new(public_key: XOnlyPublicKey, db: Database) -> Self {
+        import_json_queue(&public_key, &db);
+        let entries = match db.queued() {
+            Ok(queued) => queued.into_iter().map(Entry::from_queued).collect(),
+            Err(e) => {
+                log::error!("Impossible to load publish queue: {}", e);
+                Vec::new()
+            }
+        };
+        Self { db, entries }
+    }
+
+    pub fn enqueue(&mut self, event: &Event) {
+        let id = event.id.to_hex();
+        if self.entries.iter().any(|e| e.id == id) {
+            return;
+        }
+        let entry = Entry {
+            id,
+            event: event.as_json(),
+            queued_at: now(),
+            deliveries: HashMap::new(),
+        };
+        if let Err(e) = self.db.enqueue(&entry.to_queued(), MAX_ENTRIES) {
+            log::error!("Impossible to save publish queue: {}", e);
+        }
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Recent events not yet accepted or rejected by every relay
+    pub fn pending(&self) -> Vec<PendingEvent> {
+        let since = now().saturating_sub(MAX_AGE);
+        self.entries
+            .iter()
+            .filter(|e| e.queued_at >= since)
+            .filter(|e| {
+                e.deliveries.is_empty() || e.deliveries.values().any(|d| *d == Delivery::Pending)
+            })
+            .map(|e| PendingEvent {
+                id: e.id.clone(),
+                event: e.event.clone(),
+                settled: e
+                    .deliveries
+                    .keys()
+                    .filter(|relay| e.is_settled(relay))
+                    .cloned()
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn record(&mut self, results: Vec<(String, Url, Delivery)>) {
+        for (id, url, delivery) in results.into_iter() {
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+                if !entry.is_settled(url.as_str()) {
+                    entry.deliveries.insert(url.to_string(), delivery);
+                    if let Err(e) = self.db.set_deliveries(&id, &entry.deliveries_json()) {
+                        log::error!("Impossible to save publish queue: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, id: &str) -> Option<DeliveryStatus> {
+        let entry = self.entries.iter().find(|e| e.id == id)?;
+        let count = |f: fn(&Delivery) -> bool| entry.deliveries.values().filter(|d| f(d)).count();
+        Some(DeliveryStatus {
+            accepted: count(|d| *d == Delivery::Accepted),
+            rejected: count(|d| matches!(d, Delivery::Rejected(_))),
+            total: entry.deliveries.len(),
+        })
+    }
+
+    /// Rejection reasons of `id`, by relay
+    pub fn rejections(&self, id: &str) -> Vec<(String, String)> {
+        self.entries
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| {
+                e.deliveries
+                    .iter()
+                    .filter_map(|(relay, d)| match d {
+                        Delivery::Rejected(reason) => Some((relay.clone(), reason.clone())),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Move the queue of the previous versions, kept in a JSON file, to the database
+fn import_json_queue(public_key: &XOnlyPublicKey, db: &Database) {
+    let path = match path(public_key) {
+        Some(path) if path.exists() => path,
+        _ => return,
+    };
+    // Kept if it can't be read, not to lose the queue
+    let entries: Vec<Entry> = match json_store::load(Some(path.clone())) {
+        Some(entries) => entries,
+        None => return,
+    };
+    for entry in entries.iter() {
+        if let Err(e) = db.enqueue(&entry.to_queued(), MAX_ENTRIES) {
+            log::error!("Impossible to import publish queue: {}", e);
+            return;
+        }
+    }
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::error!("Impossible to remove {}: {}", path.display(), e);
+    }
+}
+
+fn path(public_key: &XOnlyPublicKey) -> Option<PathBuf> {
+    dir::default_dir()
+        .ok()
+        .map(|dir| dir.join(format!("publish-queue-{}.json", public_key)))
+}
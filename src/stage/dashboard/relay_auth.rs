@@ -0,0 +1,143 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashMap;
+use std::fmt;
+
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::ClientMessage;
+use nostr_sdk::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::nips::nip42;
+use crate::nips::raw::now;
+
+/// Whether to answer the NIP-42 challenges of a relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthPolicy {
+    Always,
+    Ask,
+    Never,
+}
+
+impl Default for AuthPolicy {
+    fn default() -> Self {
+        Self::Ask
+    }
+}
+
+impl AuthPolicy {
+    pub const ALL: [AuthPolicy; 3] = [AuthPolicy::Always, AuthPolicy::Ask, AuthPolicy::Never];
+}
+
+impl fmt::Display for AuthPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Always => write!(f, "Auth: always"),
+            Self::Ask => write!(f, "Auth: ask"),
+            Self::Never => write!(f, "Auth: never"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthState {
+    /// Waiting for the user to accept the challenge
+    Challenged(String),
+    /// Auth event sent, waiting for the relay `OK`
+    Pending(String),
+    Authenticated,
+    Failed(String),
+    Refused,
+}
+
+/// Auth state of the current connections. The policies are in the settings.
+#[derive(Debug, Clone, Default)]
+pub struct RelayAuth {
+    states: HashMap<Url, AuthState>,
+    /// Last challenge left unanswered because several relays could have sent it: when,
+    /// and how many
+    ignored: Option<(u64, usize)>,
+}
+
+impl RelayAuth {
+    pub fn state(&self, url: &Url) -> Option<&AuthState> {
+        self.states.get(url)
+    }
+
+    pub fn set_state(&mut self, url: &Url, state: AuthState) {
+        self.states.insert(url.clone(), state);
+    }
+
+    /// Relays waiting for the user to accept their challenge
+    pub fn challenged(&self) -> impl Iterator<Item = &Url> {
+        self.states
+            .iter()
+            .filter(|(_, state)| matches!(state, AuthState::Challenged(_)))
+            .map(|(url, _)| url)
+    }
+
+    pub fn ignored(&self) -> Option<(u64, usize)> {
+        self.ignored
+    }
+
+    pub fn set_ignored(&mut self, candidates: usize) {
+        self.ignored = Some((now(), candidates));
+    }
+
+    /// Update the relay which was sent the auth event `event_id`. Returns the relay if
+    /// it accepted it.
+    pub fn handle_ok(&mut self, event_id: &str, accepted: bool, message: &str) -> Option<Url> {
+        let url = self
+            .states
+            .iter()
+            .find(|(_, state)| matches!(state, AuthState::Pending(id) if id == event_id))
+            .map(|(url, _)| url.clone());
+        match url {
+            Some(url) if accepted => {
+                self.states.insert(url.clone(), AuthState::Authenticated);
+                Some(url)
+            }
+            Some(url) => {
+                self.states
+                    .insert(url, AuthState::Failed(message.to_string()));
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+/// Answer `challenge` on each of `relays`, returning the id of the auth event sent to
+/// each relay or the reason it couldn't be sent
+pub async fn authenticate(
+    client: &Client,
+    relays: Vec<Url>,
+    challenge: String,
+) -> Vec<(Url, Result<String, String>)> {
+    let keys = client.keys();
+    let connected = client.relays().await;
+    let mut results = Vec::new();
+    for url in relays.into_iter() {
+        let result = match (connected.get(&url), keys.secret_key()) {
+            (Some(relay), Ok(secret_key)) => {
+                match nip42::auth_event(keys.public_key(), &url, &challenge).sign(&secret_key) {
+                    Ok(event) => {
+                        let event_id = event.id.to_hex();
+                        relay
+                            .send_msg(ClientMessage::new_auth(event))
+                            .await
+                            .map(|_| event_id)
+                            .map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+            (None, _) => Err(String::from("relay not connected")),
+            (_, Err(e)) => Err(e.to_string()),
+        };
+        results.push((url, result));
+    }
+    results
+}
@@ -0,0 +1,245 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, EventId, SubscriptionFilter};
+use nostr_sdk::{Client, RelayStatus};
+
+use crate::nips::raw::now;
+
+const HISTORY_LEN: usize = 20;
+/// Seconds between two latency samples of a relay
+const PROBE_INTERVAL: u64 = 60;
+/// How long a relay has to answer a probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Id no event has, so the relay answers a probe with EOSE only
+const NO_EVENT_ID: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+static NEXT_PROBE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub at: u64,
+    /// `None` if the probe failed
+    pub latency_ms: Option<u64>,
+}
+
+/// Connection state of a relay the last time the pool was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    Connecting,
+    Connected,
+    Down,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RelayStats {
+    /// Connection attempts of the pool
+    pub attempts: u64,
+    pub failures: u64,
+    pub last_error: Option<(u64, String)>,
+    pub events: u64,
+    pub duplicates: u64,
+    pub bytes: u64,
+    pub history: VecDeque<Sample>,
+    link: Option<Link>,
+}
+
+impl RelayStats {
+    pub fn latency_ms(&self) -> Option<u64> {
+        self.history.back().and_then(|s| s.latency_ms)
+    }
+
+    /// Latency history as a tiny bar chart, failed probes shown as `×`
+    pub fn sparkline(&self) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let max = self
+            .history
+            .iter()
+            .filter_map(|s| s.latency_ms)
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        self.history
+            .iter()
+            .map(|s| match s.latency_ms {
+                Some(ms) => BARS[((ms * 7) / max) as usize],
+                None => '×',
+            })
+            .collect()
+    }
+
+    fn push(&mut self, sample: Sample) {
+        self.history.push_back(sample);
+        while self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    fn probe_due(&self, at: u64) -> bool {
+        self.history
+            .back()
+            .map(|s| s.at + PROBE_INTERVAL <= at)
+            .unwrap_or(true)
+    }
+}
+
+/// Connection health of the relays of the pool: connection attempts from their status,
+/// latency from probes sent on their connection, traffic counted by the sync as messages
+/// arrive. Clones share the same stats.
+#[derive(Debug, Clone, Default)]
+pub struct RelayHealth {
+    stats: Arc<Mutex<HashMap<Url, RelayStats>>>,
+    /// Probes waiting for their EOSE: subscription id -> relay, sent at
+    probes: Arc<Mutex<HashMap<String, (Url, Instant)>>>,
+}
+
+impl RelayHealth {
+    pub fn is_empty(&self) -> bool {
+        self.stats.lock().map(|s| s.is_empty()).unwrap_or(true)
+    }
+
+    pub fn get(&self, url: &Url) -> Option<RelayStats> {
+        self.stats.lock().ok()?.get(url).cloned()
+    }
+
+    /// Count an event of `bytes` received from `url`. `duplicate` if it was already
+    /// received from another relay, or before.
+    pub fn count_event(&self, url: &Url, bytes: usize, duplicate: bool) {
+        if let Ok(mut stats) = self.stats.lock() {
+            let stats = stats.entry(url.clone()).or_default();
+            stats.events += 1;
+            stats.bytes += bytes as u64;
+            if duplicate {
+                stats.duplicates += 1;
+            }
+        }
+    }
+
+    /// Count the EOSE of a subscription on `url`: the relay answers, so the last error is
+    /// cleared
+    pub fn count_eose(&self, url: &Url, bytes: usize) {
+        if let Ok(mut stats) = self.stats.lock() {
+            let stats = stats.entry(url.clone()).or_default();
+            stats.bytes += bytes as u64;
+            stats.last_error = None;
+        }
+    }
+
+    /// Record the status the pool gives `url`, counting the connection attempts
+    fn set_status(&self, url: &Url, status: &RelayStatus) {
+        let link = match status {
+            RelayStatus::Connecting => Link::Connecting,
+            RelayStatus::Connected => Link::Connected,
+            RelayStatus::Disconnected => Link::Down,
+            RelayStatus::Initialized | RelayStatus::Terminated => return,
+        };
+        let mut stats = match self.stats.lock() {
+            Ok(stats) => stats,
+            Err(_) => return,
+        };
+        let stats = stats.entry(url.clone()).or_default();
+        let previous = stats.link.replace(link);
+        if previous == Some(link) {
+            return;
+        }
+        match (previous, link) {
+            // A connection may have been made between two reads
+            (_, Link::Connecting)
+            | (None, Link::Connected)
+            | (Some(Link::Down), Link::Connected) => stats.attempts += 1,
+            (Some(Link::Connecting), Link::Down) => {
+                stats.failures += 1;
+                stats.last_error = Some((now(), String::from("connection failed")));
+            }
+            (Some(Link::Connected), Link::Down) => {
+                stats.last_error = Some((now(), String::from("disconnected")));
+            }
+            _ => (),
+        }
+    }
+
+    /// Id of the probe to send to `url`, if one is due. A probe that got no answer in
+    /// time, and a relay not connected when one is due, are recorded as failed.
+    fn start_probe(&self, url: &Url, connected: bool) -> Option<String> {
+        let (mut stats, mut probes) = match (self.stats.lock(), self.probes.lock()) {
+            (Ok(stats), Ok(probes)) => (stats, probes),
+            _ => return None,
+        };
+        let at = now();
+        let stats = stats.entry(url.clone()).or_default();
+
+        let pending: Option<(String, Instant)> = probes
+            .iter()
+            .find(|(_, (u, _))| u == url)
+            .map(|(id, (_, sent_at))| (id.clone(), *sent_at));
+        if let Some((id, sent_at)) = pending {
+            if sent_at.elapsed() < PROBE_TIMEOUT {
+                return None;
+            }
+            probes.remove(&id);
+            stats.last_error = Some((at, String::from("no answer to the probe")));
+            stats.push(Sample {
+                at,
+                latency_ms: None,
+            });
+        }
+
+        if !stats.probe_due(at) {
+            return None;
+        }
+        if !connected {
+            stats.push(Sample {
+                at,
+                latency_ms: None,
+            });
+            return None;
+        }
+        let id = format!("health-{}", NEXT_PROBE.fetch_add(1, Ordering::Relaxed));
+        probes.insert(id.clone(), (url.clone(), Instant::now()));
+        Some(id)
+    }
+
+    /// Record the latency of the probe `subscription_id` on its EOSE, and return the relay
+    /// it was sent to. `None` if it isn't a probe.
+    pub fn end_probe(&self, subscription_id: &str) -> Option<Url> {
+        let (url, sent_at) = self.probes.lock().ok()?.remove(subscription_id)?;
+        if let Ok(mut stats) = self.stats.lock() {
+            let stats = stats.entry(url.clone()).or_default();
+            stats.last_error = None;
+            stats.push(Sample {
+                at: now(),
+                latency_ms: Some(sent_at.elapsed().as_millis() as u64),
+            });
+        }
+        Some(url)
+    }
+}
+
+/// Read the status of the pool relays, and send a REQ matching no event on the
+/// connection of the ones due for a probe. The sync times their EOSE.
+pub async fn probe(client: &Client, health: &RelayHealth) {
+    let filter = match EventId::from_hex(NO_EVENT_ID) {
+        Ok(id) => SubscriptionFilter::new().ids(vec![id]).limit(1),
+        Err(_) => return,
+    };
+    for (url, relay) in client.relays().await.into_iter() {
+        let status = relay.status().await;
+        health.set_status(&url, &status);
+        let connected = matches!(status, RelayStatus::Connected);
+        if let Some(id) = health.start_probe(&url, connected) {
+            if let Err(e) = relay
+                .send_msg(ClientMessage::new_req(id, vec![filter.clone()]))
+                .await
+            {
+                // Left pending, so it's recorded as failed next round
+                log::debug!("Impossible to probe {}: {}", url, e);
+            }
+        }
+    }
+}
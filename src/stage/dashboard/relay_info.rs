@@ -0,0 +1,104 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use nostr_sdk::nostr::url::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::nips::nip11::{self, RelayInformationDocument};
+use crate::nips::raw::now;
+use crate::util::{dir, json_store};
+
+/// Documents older than this are fetched again
+const MAX_AGE: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDocument {
+    fetched_at: u64,
+    document: RelayInformationDocument,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Persisted {
+    documents: HashMap<String, CachedDocument>,
+}
+
+/// NIP-11 documents of the relays, persisted between sessions. Clones share the same
+/// documents.
+#[derive(Debug, Clone, Default)]
+pub struct RelayInfoCache {
+    documents: Arc<Mutex<HashMap<String, CachedDocument>>>,
+}
+
+impl RelayInfoCache {
+    pub fn load() -> Self {
+        let persisted: Persisted = json_store::load(path()).unwrap_or_default();
+        Self {
+            documents: Arc::new(Mutex::new(persisted.documents)),
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<RelayInformationDocument> {
+        self.documents
+            .lock()
+            .ok()?
+            .get(url.as_str())
+            .map(|c| c.document.clone())
+    }
+
+    pub fn is_stale(&self, url: &Url) -> bool {
+        match self.documents.lock() {
+            Ok(documents) => match documents.get(url.as_str()) {
+                Some(cached) => now().saturating_sub(cached.fetched_at) > MAX_AGE,
+                None => true,
+            },
+            Err(_) => true,
+        }
+    }
+
+    pub fn insert(&self, url: &Url, document: RelayInformationDocument) {
+        if let Ok(mut documents) = self.documents.lock() {
+            documents.insert(
+                url.to_string(),
+                CachedDocument {
+                    fetched_at: now(),
+                    document,
+                },
+            );
+            let persisted = Persisted {
+                documents: documents.clone(),
+            };
+            json_store::save(path(), &persisted, "relay information cache");
+        }
+    }
+
+    /// Document of `url`, fetched through `proxy` only if the cached one is missing or
+    /// stale. A stale document is kept when the relay can't be reached.
+    pub async fn fetch(
+        &self,
+        url: &Url,
+        proxy: Option<SocketAddr>,
+    ) -> Option<RelayInformationDocument> {
+        if !self.is_stale(url) {
+            return self.get(url);
+        }
+        match nip11::fetch(url, proxy).await {
+            Ok(document) => {
+                self.insert(url, document.clone());
+                Some(document)
+            }
+            Err(e) => {
+                log::warn!("Impossible to fetch NIP-11 document of {}: {}", url, e);
+                self.get(url)
+            }
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    dir::cache_dir().ok().map(|dir| dir.join("relay-info.json"))
+}
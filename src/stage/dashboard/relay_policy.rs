@@ -0,0 +1,68 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use nostr_sdk::nostr::url::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::nips::nip65::RelayPolicy;
+
+/// Read/write policy of each relay, kept in the account section of the settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyState {
+    policies: BTreeMap<String, RelayPolicy>,
+    /// Whether the kind 10002 published by other clients was already imported
+    imported: bool,
+}
+
+/// Policies shared by the screens and the sync, which only reads from the relays with
+/// the read policy
+#[derive(Debug, Clone, Default)]
+pub struct RelayPolicies {
+    state: Arc<RwLock<PolicyState>>,
+}
+
+impl RelayPolicies {
+    pub fn new(state: PolicyState) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(state)),
+        }
+    }
+
+    /// State to write in the settings
+    pub fn state(&self) -> PolicyState {
+        self.state.read().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Relays without an explicit policy are read and written
+    pub fn get(&self, url: &Url) -> RelayPolicy {
+        self.state
+            .read()
+            .ok()
+            .and_then(|s| s.policies.get(url.as_str()).copied())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, url: &Url, policy: RelayPolicy) {
+        if let Ok(mut state) = self.state.write() {
+            state.policies.insert(url.to_string(), policy);
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        self.state.read().map(|s| s.imported).unwrap_or(false)
+    }
+
+    /// Replace the policies with the ones of an imported relay list
+    pub fn import(&self, relays: Vec<(Url, RelayPolicy)>) {
+        if let Ok(mut state) = self.state.write() {
+            for (url, policy) in relays.into_iter() {
+                state.policies.insert(url.to_string(), policy);
+            }
+            state.imported = true;
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+
+use crate::nips::nip28::{self, ChannelMetadata};
+use crate::nips::raw::{now, RawEvent};
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub creator: XOnlyPublicKey,
+    pub metadata: ChannelMetadata,
+    metadata_at: u64,
+}
+
+impl Channel {
+    pub fn name(&self, id: &str) -> String {
+        self.metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("#{}", &id[..8.min(id.len())]))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelEntry {
+    pub id: String,
+    pub author: XOnlyPublicKey,
+    pub content: String,
+    pub reply_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Channels {
+    channels: HashMap<String, Channel>,
+    /// Keyed by `(created_at, id)` so messages stay sorted and deduplicated
+    messages: HashMap<String, BTreeMap<(u64, String), ChannelEntry>>,
+    joined: HashSet<String>,
+    joined_at: u64,
+    /// Whether our joined list was looked up. Editing it before would replace the
+    /// published one.
+    joined_loaded: bool,
+    /// Kind 41 events received before the kind 40 of their channel
+    pending_metadata: HashMap<String, Vec<RawEvent>>,
+    hidden: HashSet<String>,
+    muted: HashSet<XOnlyPublicKey>,
+}
+
+impl Channels {
+    pub fn get(&self, id: &str) -> Option<&Channel> {
+        self.channels.get(id)
+    }
+
+    pub fn name(&self, id: &str) -> String {
+        match self.channels.get(id) {
+            Some(channel) => channel.name(id),
+            None => format!("#{}", &id[..8.min(id.len())]),
+        }
+    }
+
+    /// Channels sorted by name, joined ones first
+    pub fn sorted(&self) -> Vec<(&String, &Channel)> {
+        let mut list: Vec<(&String, &Channel)> = self.channels.iter().collect();
+        list.sort_by_key(|(id, c)| (!self.joined.contains(*id), c.name(id).to_lowercase()));
+        list
+    }
+
+    pub fn is_joined(&self, id: &str) -> bool {
+        self.joined.contains(id)
+    }
+
+    pub fn joined(&self) -> &HashSet<String> {
+        &self.joined
+    }
+
+    pub fn is_joined_loaded(&self) -> bool {
+        self.joined_loaded
+    }
+
+    pub fn set_joined_loaded(&mut self) {
+        self.joined_loaded = true;
+    }
+
+    /// Joined channels whose kind 40 we don't have
+    pub fn missing_joined(&self) -> Vec<String> {
+        self.joined
+            .iter()
+            .filter(|id| !self.channels.contains_key(*id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_joined(&mut self, id: &str, joined: bool) {
+        if joined {
+            self.joined.insert(id.to_string());
+        } else {
+            self.joined.remove(id);
+        }
+        self.joined_at = now();
+    }
+
+    pub fn hide(&mut self, message_id: &str) {
+        self.hidden.insert(message_id.to_string());
+    }
+
+    pub fn mute(&mut self, public_key: XOnlyPublicKey) {
+        self.muted.insert(public_key);
+    }
+
+    /// Messages of `channel_id`, without the ones we hid or whose author we muted
+    pub fn messages(&self, channel_id: &str) -> Vec<&ChannelEntry> {
+        self.messages
+            .get(channel_id)
+            .map(|messages| {
+                messages
+                    .values()
+                    .filter(|m| !self.hidden.contains(&m.id) && !self.muted.contains(&m.author))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn message(&self, channel_id: &str, id: &str) -> Option<&ChannelEntry> {
+        self.messages
+            .get(channel_id)
+            .and_then(|messages| messages.values().find(|m| m.id == id))
+    }
+
+    /// Hide and mute events are only honored when we authored them
+    pub fn handle_event(&mut self, my_public_key: &XOnlyPublicKey, raw: &RawEvent) {
+        let author = match raw.pubkey() {
+            Ok(author) => author,
+            Err(_) => return,
+        };
+
+        match raw.kind {
+            nip28::CHANNEL_CREATION_KIND => {
+                let channel = self.channels.entry(raw.id.clone()).or_insert(Channel {
+                    creator: author,
+                    metadata: ChannelMetadata::default(),
+                    metadata_at: 0,
+                });
+                channel.creator = author;
+                if channel.metadata_at == 0 {
+                    channel.metadata = ChannelMetadata::from_json(&raw.content);
+                }
+                for pending in self
+                    .pending_metadata
+                    .remove(&raw.id)
+                    .unwrap_or_default()
+                    .iter()
+                {
+                    self.handle_metadata(&raw.id, pending);
+                }
+            }
+            nip28::CHANNEL_METADATA_KIND => {
+                if let Some(channel_id) = nip28::channel_id(raw) {
+                    if self.channels.contains_key(&channel_id) {
+                        self.handle_metadata(&channel_id, raw);
+                    } else {
+                        self.pending_metadata
+                            .entry(channel_id)
+                            .or_default()
+                            .push(raw.clone());
+                    }
+                }
+            }
+            nip28::CHANNEL_MESSAGE_KIND => {
+                if let Some(channel_id) = nip28::channel_id(raw) {
+                    self.messages.entry(channel_id).or_default().insert(
+                        (raw.created_at, raw.id.clone()),
+                        ChannelEntry {
+                            id: raw.id.clone(),
+                            author,
+                            content: raw.content.clone(),
+                            reply_to: nip28::marked_tag(raw, "reply"),
+                        },
+                    );
+                }
+            }
+            nip28::CHANNEL_HIDE_MESSAGE_KIND if &author == my_public_key => {
+                self.hidden.extend(raw.tag_values("e").map(String::from));
+            }
+            nip28::CHANNEL_MUTE_USER_KIND if &author == my_public_key => {
+                self.muted
+                    .extend(raw.tag_values("p").filter_map(|pk| pk.parse().ok()));
+            }
+            nip28::PUBLIC_CHATS_KIND
+                if &author == my_public_key && raw.created_at > self.joined_at =>
+            {
+                self.joined = raw.tag_values("e").map(String::from).collect();
+                self.joined_at = raw.created_at;
+            }
+            _ => (),
+        }
+    }
+
+    /// Only the creator of a channel can update its metadata
+    fn handle_metadata(&mut self, channel_id: &str, raw: &RawEvent) {
+        if let (Some(channel), Ok(author)) = (self.channels.get_mut(channel_id), raw.pubkey()) {
+            if channel.creator == author && raw.created_at > channel.metadata_at {
+                channel.metadata = ChannelMetadata::from_json(&raw.content);
+                channel.metadata_at = raw.created_at;
+            }
+        }
+    }
+}
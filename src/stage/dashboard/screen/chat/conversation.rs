@@ -0,0 +1,159 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::{BTreeMap, HashMap};
+
+use nostr_sdk::nostr::secp256k1::{SecretKey, XOnlyPublicKey};
+use nostr_sdk::nostr::util::nip04;
+use nostr_sdk::nostr::Event;
+
+use crate::nips::raw::RawEvent;
+use crate::nips::{nip17, nip59};
+
+pub const ENCRYPTED_DIRECT_MESSAGE_KIND: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Legacy kind 4 messages: content is encrypted, metadata isn't
+    Nip04,
+    /// Kind 14 rumors, sealed and gift wrapped
+    Nip17,
+}
+
+impl Protocol {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Nip04 => "NIP-04",
+            Self::Nip17 => "NIP-17",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Nip04 => "Legacy DM: sender, receiver and time are public",
+            Self::Nip17 => "Private DM: metadata is hidden",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatEntry {
+    pub author: XOnlyPublicKey,
+    pub content: String,
+    pub created_at: u64,
+    pub protocol: Protocol,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    /// Keyed by `(created_at, id)` so messages stay sorted and deduplicated
+    messages: BTreeMap<(u64, String), ChatEntry>,
+}
+
+impl Conversation {
+    pub fn messages(&self) -> impl Iterator<Item = &ChatEntry> {
+        self.messages.values()
+    }
+
+    pub fn last_activity(&self) -> u64 {
+        self.messages
+            .keys()
+            .next_back()
+            .map(|(t, _)| *t)
+            .unwrap_or_default()
+    }
+
+    /// Once a peer has used NIP-17 with us, we stop downgrading to NIP-04
+    pub fn protocol(&self) -> Protocol {
+        if self
+            .messages
+            .values()
+            .any(|m| m.protocol == Protocol::Nip17)
+        {
+            Protocol::Nip17
+        } else {
+            Protocol::Nip04
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Conversations {
+    inner: HashMap<XOnlyPublicKey, Conversation>,
+}
+
+impl Conversations {
+    pub fn get(&self, peer: &XOnlyPublicKey) -> Option<&Conversation> {
+        self.inner.get(peer)
+    }
+
+    pub fn open(&mut self, peer: XOnlyPublicKey) {
+        self.inner.entry(peer).or_default();
+    }
+
+    /// Most recent conversations first
+    pub fn sorted(&self) -> Vec<(&XOnlyPublicKey, &Conversation)> {
+        let mut list: Vec<(&XOnlyPublicKey, &Conversation)> = self.inner.iter().collect();
+        list.sort_by_key(|(_, c)| std::cmp::Reverse(c.last_activity()));
+        list
+    }
+
+    /// Decrypt a kind 4 or kind 1059 event and file it under its conversation.
+    /// Returns the peer if the event was a message for us.
+    pub fn handle_event(
+        &mut self,
+        secret_key: &SecretKey,
+        my_public_key: &XOnlyPublicKey,
+        event: &Event,
+    ) -> Option<XOnlyPublicKey> {
+        let raw = RawEvent::from_event(event).ok()?;
+        let (id, rumor, protocol) = match raw.kind {
+            ENCRYPTED_DIRECT_MESSAGE_KIND => (raw.id.clone(), raw, Protocol::Nip04),
+            nip59::GIFT_WRAP_KIND => match nip59::unwrap(secret_key, event) {
+                Ok(rumor) if rumor.kind == nip17::PRIVATE_DIRECT_MESSAGE_KIND => {
+                    (rumor.id.clone(), rumor, Protocol::Nip17)
+                }
+                Ok(_) => return None,
+                Err(e) => {
+                    log::warn!("Impossible to unwrap {}: {}", raw.id, e);
+                    return None;
+                }
+            },
+            _ => return None,
+        };
+
+        let author = rumor.pubkey().ok()?;
+        let peer = if &author == my_public_key {
+            rumor
+                .tag_values("p")
+                .filter_map(|pk| pk.parse::<XOnlyPublicKey>().ok())
+                .find(|pk| pk != my_public_key)
+                .unwrap_or(author)
+        } else {
+            author
+        };
+
+        let content = match protocol {
+            Protocol::Nip04 => match nip04::decrypt(secret_key, &peer, &rumor.content) {
+                Ok(content) => content,
+                Err(e) => {
+                    log::warn!("Impossible to decrypt {}: {}", id, e);
+                    return None;
+                }
+            },
+            Protocol::Nip17 => rumor.content.clone(),
+        };
+
+        self.inner.entry(peer).or_default().messages.insert(
+            (rumor.created_at, id),
+            ChatEntry {
+                author,
+                content,
+                created_at: rumor.created_at,
+                protocol,
+            },
+        );
+
+        Some(peer)
+    }
+}
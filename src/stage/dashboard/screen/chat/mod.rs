@@ -0,0 +1,768 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use iced::widget::{Button, Column, Row, Rule, Space, Text, TextInput};
+use iced::{theme, time, Alignment, Command, Element, Length, Subscription};
+use nostr_sdk::nostr::key::{FromPkStr, Keys};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::util::nip04;
+use nostr_sdk::nostr::{Event, EventId, Kind, SubscriptionFilter};
+use nostr_sdk::{Client, Result};
+
+mod channel;
+mod conversation;
+
+use self::channel::Channels;
+use self::conversation::{Conversations, Protocol, ENCRYPTED_DIRECT_MESSAGE_KIND};
+use crate::message::{DashboardMessage, Message};
+use crate::nips::raw::{now, RawEvent};
+use crate::nips::{nip17, nip28, nip59};
+use crate::stage::dashboard::component::Dashboard;
+use crate::stage::dashboard::{Context, State};
+
+const CHANNEL_MESSAGES_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTab {
+    Direct,
+    Channels,
+}
+
+impl Default for ChatTab {
+    fn default() -> Self {
+        Self::Direct
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelsMessage {
+    Select(String),
+    Join(String),
+    Leave(String),
+    ComposerChanged(String),
+    ReplyTo(Option<String>),
+    Send,
+    Hide(String),
+    Mute(XOnlyPublicKey),
+}
+
+#[derive(Debug, Clone)]
+pub enum ChatMessage {
+    Received(Event),
+    Refresh,
+    /// Events of the first load, with our joined channels list
+    Loaded(Vec<Event>),
+    SetHistory(Vec<Event>),
+    SetTab(ChatTab),
+    Select(XOnlyPublicKey),
+    NewPeerChanged(String),
+    OpenConversation,
+    ComposerChanged(String),
+    Send,
+    /// Result of sending the direct message drafted in the composer, with the kind 4 event
+    /// left to publish to our write relays
+    DirectSent(Result<Option<Event>, String>),
+    Channels(ChannelsMessage),
+}
+
+#[derive(Debug, Default)]
+pub struct ChatState {
+    loaded: bool,
+    tab: ChatTab,
+    conversations: Conversations,
+    selected: Option<XOnlyPublicKey>,
+    new_peer: String,
+    composer: String,
+    /// Direct message being sent, kept in the composer until it's sent
+    sending: Option<String>,
+    channels: Channels,
+    selected_channel: Option<String>,
+    channel_composer: String,
+    reply_to: Option<String>,
+    error: Option<String>,
+    /// Start of the live subscription, older events come from `load`
+    opened_at: u64,
+    /// Events already handled, so refreshes don't decrypt them again
+    handled: HashSet<String>,
+    /// Newest kind 4 message and gift wrap seen, where refreshes start from
+    newest_dm: u64,
+    newest_wrap: u64,
+    /// Joined channels already looked up by id
+    requested_channels: HashSet<String>,
+}
+
+impl ChatState {
+    pub fn new() -> Self {
+        Self {
+            opened_at: now(),
+            ..Self::default()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.composer = String::new();
+        self.sending = None;
+        self.channel_composer = String::new();
+        self.reply_to = None;
+        self.error = None;
+    }
+
+    fn handle_event(&mut self, ctx: &Context, event: &Event) {
+        if !self.handled.insert(event.id.to_hex()) {
+            return;
+        }
+        if event.kind == Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND) {
+            self.newest_dm = self.newest_dm.max(event.created_at);
+        } else if event.kind == Kind::from(nip59::GIFT_WRAP_KIND) {
+            self.newest_wrap = self.newest_wrap.max(event.created_at);
+        }
+
+        let keys = ctx.client.keys();
+        let my_public_key = keys.public_key();
+        match keys.secret_key() {
+            Ok(secret_key) => {
+                self.conversations
+                    .handle_event(&secret_key, &my_public_key, event);
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+        if let Ok(raw) = RawEvent::from_event(event) {
+            self.channels.handle_event(&my_public_key, &raw);
+        }
+    }
+
+    /// Direct messages newer than the ones we have. Gift wraps are backdated, so they're
+    /// asked from `MAX_TWEAK` before the newest one seen.
+    fn refresh(&self, ctx: &Context) -> Command<Message> {
+        let client = ctx.client.clone();
+        let public_key = client.keys().public_key();
+        let filters = vec![
+            SubscriptionFilter::new()
+                .author(public_key)
+                .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND))
+                .since(self.newest_dm),
+            SubscriptionFilter::new()
+                .pubkey(public_key)
+                .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND))
+                .since(self.newest_dm),
+            SubscriptionFilter::new()
+                .pubkey(public_key)
+                .kind(Kind::from(nip59::GIFT_WRAP_KIND))
+                .since(self.newest_wrap.saturating_sub(nip59::MAX_TWEAK)),
+        ];
+        Command::perform(
+            async move { get_events(&client, filters).await },
+            |events| ChatMessage::SetHistory(events).into(),
+        )
+    }
+
+    /// Joined channels aren't always among the newest ones, look up the others by id
+    fn fetch_missing_channels(&mut self, ctx: &Context) -> Command<Message> {
+        let missing: Vec<EventId> = self
+            .channels
+            .missing_joined()
+            .into_iter()
+            .filter(|id| self.requested_channels.insert(id.clone()))
+            .filter_map(|id| EventId::from_hex(&id).ok())
+            .collect();
+        if missing.is_empty() {
+            return Command::none();
+        }
+
+        let mut filters = vec![SubscriptionFilter::new()
+            .ids(missing.clone())
+            .kind(Kind::from(nip28::CHANNEL_CREATION_KIND))];
+        filters.extend(missing.into_iter().map(|id| {
+            SubscriptionFilter::new()
+                .event(id)
+                .kind(Kind::from(nip28::CHANNEL_METADATA_KIND))
+        }));
+        let client = ctx.client.clone();
+        Command::perform(
+            async move { get_events(&client, filters).await },
+            |events| ChatMessage::SetHistory(events).into(),
+        )
+    }
+
+    fn load_channel(&self, ctx: &Context, channel_id: &str) -> Command<Message> {
+        let client = ctx.client.clone();
+        let channel_id = match EventId::from_hex(channel_id) {
+            Ok(id) => id,
+            Err(e) => {
+                log::error!("Invalid channel id {}: {}", channel_id, e);
+                return Command::none();
+            }
+        };
+        Command::perform(
+            async move {
+                let filter = SubscriptionFilter::new()
+                    .event(channel_id)
+                    .kind(Kind::from(nip28::CHANNEL_MESSAGE_KIND))
+                    .limit(CHANNEL_MESSAGES_LIMIT);
+                get_events(&client, vec![filter]).await
+            },
+            |events| ChatMessage::SetHistory(events).into(),
+        )
+    }
+
+    /// Sign `raw` and queue it for the write relays
+    fn publish(&mut self, ctx: &Context, raw: RawEvent) -> Command<Message> {
+        let keys = ctx.client.keys();
+        match keys.secret_key().map(|secret_key| raw.sign(&secret_key)) {
+            Ok(Ok(event)) => Command::perform(async move { event }, Message::Publish),
+            Ok(Err(e)) => {
+                self.error = Some(e.to_string());
+                Command::none()
+            }
+            Err(e) => {
+                self.error = Some(e.to_string());
+                Command::none()
+            }
+        }
+    }
+
+    fn update_channels(&mut self, ctx: &Context, msg: ChannelsMessage) -> Command<Message> {
+        let my_public_key = ctx.client.keys().public_key();
+        match msg {
+            ChannelsMessage::Select(id) => {
+                self.selected_channel = Some(id.clone());
+                self.clear();
+                self.load_channel(ctx, &id)
+            }
+            ChannelsMessage::Join(_) | ChannelsMessage::Leave(_)
+                if !self.channels.is_joined_loaded() =>
+            {
+                Command::none()
+            }
+            ChannelsMessage::Join(id) => {
+                self.channels.set_joined(&id, true);
+                self.publish(
+                    ctx,
+                    nip28::public_chats(my_public_key, self.channels.joined()),
+                )
+            }
+            ChannelsMessage::Leave(id) => {
+                self.channels.set_joined(&id, false);
+                self.publish(
+                    ctx,
+                    nip28::public_chats(my_public_key, self.channels.joined()),
+                )
+            }
+            ChannelsMessage::ComposerChanged(value) => {
+                self.channel_composer = value;
+                Command::none()
+            }
+            ChannelsMessage::ReplyTo(id) => {
+                self.reply_to = id;
+                Command::none()
+            }
+            ChannelsMessage::Send => match &self.selected_channel {
+                Some(channel_id) if !self.channel_composer.is_empty() => {
+                    let reply_to = self
+                        .reply_to
+                        .as_ref()
+                        .and_then(|id| self.channels.message(channel_id, id))
+                        .map(|m| (m.id.as_str(), m.author));
+                    let raw = nip28::message(
+                        my_public_key,
+                        channel_id,
+                        reply_to,
+                        self.channel_composer.clone(),
+                    );
+                    self.channels.handle_event(&my_public_key, &raw);
+                    self.channel_composer.clear();
+                    self.reply_to = None;
+                    self.publish(ctx, raw)
+                }
+                _ => Command::none(),
+            },
+            ChannelsMessage::Hide(id) => {
+                self.channels.hide(&id);
+                self.publish(ctx, nip28::hide_message(my_public_key, &id))
+            }
+            ChannelsMessage::Mute(public_key) => {
+                self.channels.mute(public_key);
+                self.publish(ctx, nip28::mute_user(my_public_key, public_key))
+            }
+        }
+    }
+
+    fn view_direct(&self, ctx: &Context) -> Row<Message> {
+        let new_peer_input = TextInput::new("npub or hex public key", &self.new_peer)
+            .on_input(|s| ChatMessage::NewPeerChanged(s).into())
+            .on_submit(ChatMessage::OpenConversation.into())
+            .padding(10);
+
+        let mut list = Column::new()
+            .push(new_peer_input)
+            .spacing(10)
+            .width(Length::Fixed(260.0));
+
+        for (peer, conversation) in self.conversations.sorted().into_iter() {
+            let label = Row::new()
+                .push(Text::new(ctx.display_name(peer)).width(Length::Fill))
+                .push(Text::new(conversation.protocol().label()).size(14))
+                .spacing(10)
+                .align_items(Alignment::Center);
+            list = list.push(
+                Button::new(label)
+                    .width(Length::Fill)
+                    .padding(10)
+                    .style(list_style(self.selected.as_ref() == Some(peer)))
+                    .on_press(ChatMessage::Select(*peer).into()),
+            );
+        }
+
+        let mut messages = Column::new().spacing(10).width(Length::Fill);
+
+        if let Some(peer) = &self.selected {
+            let protocol = self
+                .conversations
+                .get(peer)
+                .map(|c| c.protocol())
+                .unwrap_or(Protocol::Nip04);
+
+            messages = messages
+                .push(Text::new(ctx.display_name(peer)).size(24))
+                .push(Text::new(protocol.description()).size(14))
+                .push(Rule::horizontal(1));
+
+            if let Some(conversation) = self.conversations.get(peer) {
+                let my_public_key = ctx.client.keys().public_key();
+                for entry in conversation.messages() {
+                    let author = if entry.author == my_public_key {
+                        String::from("You")
+                    } else {
+                        ctx.display_name(&entry.author)
+                    };
+                    messages = messages.push(
+                        Column::new()
+                            .push(
+                                Row::new()
+                                    .push(Text::new(author).size(14))
+                                    .push(Text::new(entry.protocol.label()).size(12))
+                                    .spacing(10),
+                            )
+                            .push(Text::new(entry.content.clone()))
+                            .spacing(5),
+                    );
+                }
+            }
+
+            let composer = TextInput::new("Message", &self.composer)
+                .on_input(|s| ChatMessage::ComposerChanged(s).into())
+                .on_submit(ChatMessage::Send.into())
+                .padding(10);
+            let button = Button::new("Send")
+                .padding(10)
+                .on_press(ChatMessage::Send.into());
+
+            messages = messages
+                .push(Space::with_height(Length::Fixed(10.0)))
+                .push(Row::new().push(composer).push(button).spacing(10));
+        }
+
+        Row::new()
+            .push(list)
+            .push(Rule::vertical(1))
+            .push(messages)
+            .spacing(20)
+    }
+
+    fn view_channels(&self, ctx: &Context) -> Row<Message> {
+        let mut list = Column::new().spacing(10).width(Length::Fixed(260.0));
+
+        for (id, channel) in self.channels.sorted().into_iter() {
+            let mut label = Row::new()
+                .push(Text::new(channel.name(id)).width(Length::Fill))
+                .spacing(10)
+                .align_items(Alignment::Center);
+            if self.channels.is_joined(id) {
+                label = label.push(Text::new("Joined").size(14));
+            }
+            list = list.push(
+                Button::new(label)
+                    .width(Length::Fill)
+                    .padding(10)
+                    .style(list_style(self.selected_channel.as_ref() == Some(id)))
+                    .on_press(ChannelsMessage::Select(id.clone()).into()),
+            );
+        }
+
+        let mut messages = Column::new().spacing(10).width(Length::Fill);
+
+        if let Some(channel_id) = &self.selected_channel {
+            // Disabled until the joined list is loaded
+            let join_button = if self.channels.is_joined(channel_id) {
+                Button::new("Leave")
+                    .style(theme::Button::Secondary)
+                    .on_press_maybe(
+                        self.channels
+                            .is_joined_loaded()
+                            .then(|| ChannelsMessage::Leave(channel_id.clone()).into()),
+                    )
+            } else {
+                Button::new("Join").on_press_maybe(
+                    self.channels
+                        .is_joined_loaded()
+                        .then(|| ChannelsMessage::Join(channel_id.clone()).into()),
+                )
+            };
+
+            messages = messages.push(
+                Row::new()
+                    .push(
+                        Text::new(self.channels.name(channel_id))
+                            .size(24)
+                            .width(Length::Fill),
+                    )
+                    .push(join_button.padding(10))
+                    .align_items(Alignment::Center),
+            );
+            if let Some(about) = self
+                .channels
+                .get(channel_id)
+                .and_then(|c| c.metadata.about.clone())
+            {
+                messages = messages.push(Text::new(about).size(14));
+            }
+            messages = messages.push(Rule::horizontal(1));
+
+            for entry in self.channels.messages(channel_id).into_iter() {
+                let mut header = Row::new()
+                    .push(Text::new(ctx.display_name(&entry.author)).size(14))
+                    .spacing(10)
+                    .align_items(Alignment::Center);
+                if let Some(parent) = entry
+                    .reply_to
+                    .as_ref()
+                    .and_then(|id| self.channels.message(channel_id, id))
+                {
+                    header = header.push(
+                        Text::new(format!("↪ {}", ctx.display_name(&parent.author))).size(12),
+                    );
+                }
+                header = header
+                    .push(Space::with_width(Length::Fill))
+                    .push(
+                        Button::new(Text::new("Reply").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(ChannelsMessage::ReplyTo(Some(entry.id.clone())).into()),
+                    )
+                    .push(
+                        Button::new(Text::new("Hide").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(ChannelsMessage::Hide(entry.id.clone()).into()),
+                    )
+                    .push(
+                        Button::new(Text::new("Mute").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(ChannelsMessage::Mute(entry.author).into()),
+                    );
+                messages = messages.push(
+                    Column::new()
+                        .push(header)
+                        .push(Text::new(entry.content.clone()))
+                        .spacing(5),
+                );
+            }
+
+            if let Some(parent) = self
+                .reply_to
+                .as_ref()
+                .and_then(|id| self.channels.message(channel_id, id))
+            {
+                messages = messages.push(
+                    Row::new()
+                        .push(
+                            Text::new(format!("Replying to {}", ctx.display_name(&parent.author)))
+                                .size(14)
+                                .width(Length::Fill),
+                        )
+                        .push(
+                            Button::new(Text::new("Cancel").size(14))
+                                .style(theme::Button::Text)
+                                .on_press(ChannelsMessage::ReplyTo(None).into()),
+                        )
+                        .align_items(Alignment::Center),
+                );
+            }
+
+            let composer = TextInput::new("Message", &self.channel_composer)
+                .on_input(|s| ChannelsMessage::ComposerChanged(s).into())
+                .on_submit(ChannelsMessage::Send.into())
+                .padding(10);
+            let button = Button::new("Send")
+                .padding(10)
+                .on_press(ChannelsMessage::Send.into());
+
+            messages = messages
+                .push(Space::with_height(Length::Fixed(10.0)))
+                .push(Row::new().push(composer).push(button).spacing(10));
+        }
+
+        Row::new()
+            .push(list)
+            .push(Rule::vertical(1))
+            .push(messages)
+            .spacing(20)
+    }
+}
+
+impl State for ChatState {
+    fn title(&self) -> String {
+        String::from("Nostr - Chat")
+    }
+
+    fn filters(&self, ctx: &Context) -> Vec<SubscriptionFilter> {
+        let public_key = ctx.client.keys().public_key();
+        let mut filters = vec![
+            SubscriptionFilter::new()
+                .author(public_key)
+                .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND))
+                .since(self.opened_at),
+            SubscriptionFilter::new()
+                .pubkey(public_key)
+                .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND))
+                .since(self.opened_at),
+            SubscriptionFilter::new()
+                .pubkey(public_key)
+                .kind(Kind::from(nip59::GIFT_WRAP_KIND))
+                .since(self.opened_at.saturating_sub(nip59::MAX_TWEAK)),
+        ];
+        if let Some(channel_id) = self
+            .selected_channel
+            .as_ref()
+            .and_then(|id| EventId::from_hex(id).ok())
+        {
+            filters.push(
+                SubscriptionFilter::new()
+                    .event(channel_id)
+                    .kind(Kind::from(nip28::CHANNEL_MESSAGE_KIND))
+                    .since(self.opened_at),
+            );
+        }
+        filters
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        time::every(Duration::from_secs(30)).map(|_| ChatMessage::Refresh.into())
+    }
+
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
+        self.loaded = true;
+        let client = ctx.client.clone();
+        let history = Command::perform(
+            async move {
+                let public_key = client.keys().public_key();
+                let filters = vec![
+                    SubscriptionFilter::new()
+                        .author(public_key)
+                        .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND)),
+                    SubscriptionFilter::new()
+                        .pubkey(public_key)
+                        .kind(Kind::from(ENCRYPTED_DIRECT_MESSAGE_KIND)),
+                    SubscriptionFilter::new()
+                        .pubkey(public_key)
+                        .kind(Kind::from(nip59::GIFT_WRAP_KIND)),
+                    SubscriptionFilter::new()
+                        .kinds(vec![
+                            Kind::from(nip28::CHANNEL_CREATION_KIND),
+                            Kind::from(nip28::CHANNEL_METADATA_KIND),
+                        ])
+                        .limit(CHANNEL_MESSAGES_LIMIT),
+                    SubscriptionFilter::new().author(public_key).kinds(vec![
+                        Kind::from(nip28::CHANNEL_HIDE_MESSAGE_KIND),
+                        Kind::from(nip28::CHANNEL_MUTE_USER_KIND),
+                        Kind::from(nip28::PUBLIC_CHATS_KIND),
+                    ]),
+                ];
+                get_events(&client, filters).await
+            },
+            |events| ChatMessage::Loaded(events).into(),
+        );
+
+        match &self.selected_channel {
+            Some(channel_id) => Command::batch(vec![history, self.load_channel(ctx, channel_id)]),
+            None => history,
+        }
+    }
+
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
+        if !self.loaded {
+            return self.load(ctx);
+        }
+
+        if let Message::Dashboard(DashboardMessage::Chat(msg)) = message {
+            match msg {
+                ChatMessage::Received(event) => self.handle_event(ctx, &event),
+                ChatMessage::Refresh => return self.refresh(ctx),
+                ChatMessage::Loaded(events) => {
+                    for event in events.iter() {
+                        self.handle_event(ctx, event);
+                    }
+                    self.channels.set_joined_loaded();
+                    return self.fetch_missing_channels(ctx);
+                }
+                ChatMessage::SetHistory(events) => {
+                    for event in events.iter() {
+                        self.handle_event(ctx, event);
+                    }
+                }
+                ChatMessage::SetTab(tab) => {
+                    self.tab = tab;
+                    self.clear();
+                }
+                ChatMessage::Select(peer) => {
+                    self.selected = Some(peer);
+                    self.clear();
+                }
+                ChatMessage::NewPeerChanged(value) => self.new_peer = value,
+                ChatMessage::OpenConversation => match Keys::from_pk_str(&self.new_peer) {
+                    Ok(keys) => {
+                        let peer = keys.public_key();
+                        self.conversations.open(peer);
+                        self.selected = Some(peer);
+                        self.new_peer.clear();
+                        self.clear();
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                },
+                ChatMessage::ComposerChanged(value) => self.composer = value,
+                ChatMessage::Send => {
+                    if let Some(peer) = self.selected {
+                        if self.composer.is_empty() || self.sending.is_some() {
+                            return Command::none();
+                        }
+                        let content = self.composer.clone();
+                        self.sending = Some(content.clone());
+                        let prefer_nip17 = self
+                            .conversations
+                            .get(&peer)
+                            .map(|c| c.protocol() == Protocol::Nip17)
+                            .unwrap_or(false);
+                        let client = ctx.client.clone();
+                        return Command::perform(
+                            async move { send_message(&client, peer, content, prefer_nip17).await },
+                            |res| ChatMessage::DirectSent(res.map_err(|e| e.to_string())).into(),
+                        );
+                    }
+                }
+                ChatMessage::DirectSent(result) => {
+                    if let Some(content) = self.sending.take() {
+                        if result.is_ok() && self.composer == content {
+                            self.composer.clear();
+                        }
+                    }
+                    match result {
+                        Ok(event) => {
+                            self.error = None;
+                            if let Some(event) = event {
+                                return Command::perform(async move { event }, Message::Publish);
+                            }
+                        }
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+                ChatMessage::Channels(msg) => return self.update_channels(ctx, msg),
+            }
+        }
+
+        Command::none()
+    }
+
+    fn view(&self, ctx: &Context) -> Element<Message> {
+        let tabs = Row::new()
+            .push(
+                Button::new("Direct messages")
+                    .padding(10)
+                    .style(list_style(self.tab == ChatTab::Direct))
+                    .on_press(ChatMessage::SetTab(ChatTab::Direct).into()),
+            )
+            .push(
+                Button::new("Channels")
+                    .padding(10)
+                    .style(list_style(self.tab == ChatTab::Channels))
+                    .on_press(ChatMessage::SetTab(ChatTab::Channels).into()),
+            )
+            .spacing(10);
+
+        let content = Column::new()
+            .push(tabs)
+            .push(if let Some(error) = &self.error {
+                Row::new().push(Text::new(error))
+            } else {
+                Row::new()
+            })
+            .push(match self.tab {
+                ChatTab::Direct => self.view_direct(ctx),
+                ChatTab::Channels => self.view_channels(ctx),
+            });
+
+        Dashboard::new().view(ctx, content.spacing(20).padding(20))
+    }
+}
+
+impl From<ChatState> for Box<dyn State> {
+    fn from(s: ChatState) -> Box<dyn State> {
+        Box::new(s)
+    }
+}
+
+impl From<ChatMessage> for Message {
+    fn from(msg: ChatMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Chat(msg))
+    }
+}
+
+impl From<ChannelsMessage> for Message {
+    fn from(msg: ChannelsMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Chat(ChatMessage::Channels(msg)))
+    }
+}
+
+fn list_style(selected: bool) -> theme::Button {
+    if selected {
+        theme::Button::Primary
+    } else {
+        theme::Button::Secondary
+    }
+}
+
+async fn get_events(client: &Client, filters: Vec<SubscriptionFilter>) -> Vec<Event> {
+    match client.get_events_of(filters).await {
+        Ok(events) => events,
+        Err(e) => {
+            log::error!("Impossible to get chat events: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Use NIP-17 when the conversation already does or when the peer published a DM relay
+/// list, otherwise fall back to legacy kind 4 messages. The kind 4 message is returned
+/// signed, to be queued for our write relays.
+async fn send_message(
+    client: &Client,
+    peer: XOnlyPublicKey,
+    content: String,
+    prefer_nip17: bool,
+) -> Result<Option<Event>> {
+    if prefer_nip17 || !nip17::get_dm_relays(client, peer).await.is_empty() {
+        nip17::send_private_message(client, peer, content).await?;
+        Ok(None)
+    } else {
+        let keys = client.keys();
+        let raw = RawEvent::new(
+            keys.public_key(),
+            now(),
+            ENCRYPTED_DIRECT_MESSAGE_KIND,
+            vec![vec![String::from("p"), peer.to_string()]],
+            nip04::encrypt(&keys.secret_key()?, &peer, content)?,
+        );
+        Ok(Some(raw.sign(&keys.secret_key()?)?))
+    }
+}
@@ -2,12 +2,14 @@
 // Distributed under the MIT software license
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use iced::widget::{image, Column, Row, Text};
 use iced::{Command, Element};
 use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
 
 use crate::message::{DashboardMessage, Message};
+use crate::net;
 use crate::stage::dashboard::component::{Contact, Dashboard};
 use crate::stage::dashboard::{Context, State};
 
@@ -64,7 +66,7 @@ impl State for ContactsState {
         }
 
         for (pk, contact) in self.contacts.iter() {
-            if contact.image.is_none() {
+            if ctx.config.media.autoload_images && contact.image.is_none() {
                 if let Some(url) = contact.profile.picture.clone() {
                     let pk = *pk;
                     commands.push(Command::perform(async {}, move |_| {
@@ -110,8 +112,17 @@ impl From<ContactsMessage> for Message {
     }
 }
 
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub async fn fetch_image(url: String) -> Option<image::Handle> {
-    match reqwest::get(url).await {
+    let client = match net::http_client(net::settings().proxy, IMAGE_TIMEOUT) {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Impossible to fetch image: {}", e);
+            return None;
+        }
+    };
+    match client.get(url).send().await {
         Ok(res) => match res.bytes().await {
             Ok(bytes) => return Some(image::Handle::from_memory(bytes.as_ref().to_vec())),
             Err(e) => log::error!("Impossible to fetch image bytes: {}", e.to_string()),
@@ -0,0 +1,568 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use iced::widget::{text_input, Button, Checkbox, Column, Row, Rule, Text, TextInput};
+use iced::{theme, time, Command, Element, Subscription};
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{Event, EventId, Kind, SubscriptionFilter};
+use nostr_sdk::Client;
+use once_cell::sync::Lazy;
+
+use crate::db::Database;
+use crate::message::{DashboardMessage, Message};
+use crate::nips::raw::{now, RawEvent};
+use crate::stage::dashboard::component::post::Post;
+use crate::stage::dashboard::component::Dashboard;
+use crate::stage::dashboard::{Context, State};
+
+mod search;
+
+use self::search::SearchResults;
+
+const FEED_LIMIT: usize = 50;
+const TRENDING_LIMIT: usize = 20;
+const TRENDING_HOURS: [u64; 3] = [1, 6, 24];
+
+const TEXT_NOTE_KIND: u64 = 1;
+
+/// Focused by the search shortcut
+pub static SEARCH_INPUT_ID: Lazy<text_input::Id> = Lazy::new(text_input::Id::unique);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExploreTab {
+    Global,
+    Trending,
+    Hashtag(String),
+    Search(String),
+}
+
+impl Default for ExploreTab {
+    fn default() -> Self {
+        Self::Global
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExploreMessage {
+    SetTab(ExploreTab),
+    HashtagChanged(String),
+    OpenHashtag,
+    Pin(String),
+    Unpin(String),
+    ToggleRelay(Url, bool),
+    SetRelays(Vec<Url>),
+    SetTrendingHours(u64),
+    Refresh,
+    SetEvents(ExploreTab, Vec<Event>),
+    QueryChanged(String),
+    Search,
+    SetSearchResults(String, SearchResults),
+    Received(Event),
+}
+
+#[derive(Debug, Default)]
+pub struct ExploreState {
+    loaded: bool,
+    tab: ExploreTab,
+    hashtag: String,
+    available_relays: Vec<Url>,
+    trending_hours: u64,
+    events: Vec<Event>,
+    query: String,
+    searching: bool,
+    search_results: SearchResults,
+    opened_at: u64,
+}
+
+impl ExploreState {
+    pub fn new() -> Self {
+        Self {
+            loaded: false,
+            tab: ExploreTab::default(),
+            hashtag: String::new(),
+            available_relays: Vec::new(),
+            trending_hours: TRENDING_HOURS[1],
+            events: Vec::new(),
+            query: String::new(),
+            searching: false,
+            search_results: SearchResults::default(),
+            opened_at: now(),
+        }
+    }
+
+    fn fetch(&self, ctx: &Context) -> Command<Message> {
+        let client = ctx.client.clone();
+        let tab = self.tab.clone();
+        match &self.tab {
+            ExploreTab::Global => {
+                let relays = ctx.config.explore.relays.clone();
+                Command::perform(
+                    async move {
+                        let filter = SubscriptionFilter::new()
+                            .kind(Kind::from(TEXT_NOTE_KIND))
+                            .limit(FEED_LIMIT);
+                        get_events_from(&client, &relays, vec![filter]).await
+                    },
+                    move |events| ExploreMessage::SetEvents(tab, events).into(),
+                )
+            }
+            ExploreTab::Trending => {
+                let db = ctx.db.clone();
+                let since = now().saturating_sub(self.trending_hours * 3600);
+                Command::perform(
+                    async move { trending(&client, &db, since).await },
+                    move |events| ExploreMessage::SetEvents(tab, events).into(),
+                )
+            }
+            ExploreTab::Hashtag(hashtag) => {
+                let filter = SubscriptionFilter::new()
+                    .kind(Kind::from(TEXT_NOTE_KIND))
+                    .hashtag(hashtag.clone())
+                    .limit(FEED_LIMIT);
+                Command::perform(
+                    async move { get_events(&client, vec![filter]).await },
+                    move |events| ExploreMessage::SetEvents(tab, events).into(),
+                )
+            }
+            ExploreTab::Search(query) => {
+                let query = query.clone();
+                let db = ctx.db.clone();
+                let relay_info = ctx.relay_info.clone();
+                Command::perform(
+                    async move {
+                        let results =
+                            search::search(&client, &db, &relay_info, query.clone()).await;
+                        (query, results)
+                    },
+                    |(query, results)| ExploreMessage::SetSearchResults(query, results).into(),
+                )
+            }
+        }
+    }
+
+    fn set_tab(&mut self, ctx: &Context, tab: ExploreTab) -> Command<Message> {
+        if self.tab != tab {
+            self.tab = tab;
+            self.events.clear();
+            self.search_results = SearchResults::default();
+        }
+        self.searching = matches!(self.tab, ExploreTab::Search(_));
+        self.fetch(ctx)
+    }
+
+    fn view_search_results<'a>(
+        &self,
+        ctx: &Context,
+        mut content: Column<'a, Message>,
+    ) -> Column<'a, Message> {
+        if self.searching {
+            return content.push(Text::new("Searching..."));
+        }
+        if self.search_results.is_empty() {
+            return content.push(Text::new("No results"));
+        }
+
+        if !self.search_results.hashtags.is_empty() {
+            let mut hashtags = Row::new().spacing(10);
+            for hashtag in self.search_results.hashtags.iter() {
+                hashtags = hashtags.push(
+                    Button::new(Text::new(format!("#{}", hashtag)))
+                        .style(theme::Button::Secondary)
+                        .on_press(
+                            ExploreMessage::SetTab(ExploreTab::Hashtag(hashtag.clone())).into(),
+                        ),
+                );
+            }
+            content = content.push(Text::new("Hashtags").size(20)).push(hashtags);
+        }
+
+        if !self.search_results.profiles.is_empty() {
+            content = content.push(Text::new("Profiles").size(20));
+            for profile in self.search_results.profiles.iter() {
+                let mut info = Column::new().push(Text::new(
+                    profile
+                        .display_name
+                        .clone()
+                        .unwrap_or_else(|| ctx.display_name(&profile.public_key)),
+                ));
+                if let Some(name) = &profile.name {
+                    info = info.push(Text::new(format!("@{}", name)).size(16));
+                }
+                if let Some(about) = &profile.about {
+                    info = info.push(Text::new(about.clone()).size(14));
+                }
+                content = content.push(info.spacing(5));
+            }
+        }
+
+        if !self.search_results.notes.is_empty() {
+            content = content.push(Text::new("Notes").size(20));
+            for event in self.search_results.notes.iter() {
+                content = content.push(Post::new(event.clone()).view(ctx));
+            }
+        }
+
+        content
+    }
+
+    fn tab_button<'a>(&self, label: String, tab: ExploreTab) -> Button<'a, Message> {
+        let style = if self.tab == tab {
+            theme::Button::Primary
+        } else {
+            theme::Button::Secondary
+        };
+        Button::new(Text::new(label))
+            .style(style)
+            .on_press(ExploreMessage::SetTab(tab).into())
+    }
+}
+
+impl State for ExploreState {
+    fn title(&self) -> String {
+        String::from("Nostr - Explore")
+    }
+
+    fn filters(&self, _ctx: &Context) -> Vec<SubscriptionFilter> {
+        match &self.tab {
+            ExploreTab::Hashtag(hashtag) => vec![SubscriptionFilter::new()
+                .kind(Kind::from(TEXT_NOTE_KIND))
+                .hashtag(hashtag.clone())
+                .since(self.opened_at)],
+            _ => Vec::new(),
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        time::every(Duration::from_secs(60)).map(|_| ExploreMessage::Refresh.into())
+    }
+
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
+        self.loaded = true;
+        let client = ctx.client.clone();
+        Command::batch(vec![
+            Command::perform(
+                async move { client.relays().await.into_keys().collect() },
+                |relays| ExploreMessage::SetRelays(relays).into(),
+            ),
+            self.fetch(ctx),
+        ])
+    }
+
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
+        if !self.loaded {
+            return self.load(ctx);
+        }
+
+        if let Message::Dashboard(DashboardMessage::Explore(msg)) = message {
+            match msg {
+                ExploreMessage::SetTab(tab) => return self.set_tab(ctx, tab),
+                ExploreMessage::HashtagChanged(hashtag) => self.hashtag = hashtag,
+                ExploreMessage::OpenHashtag => {
+                    let hashtag = normalize_hashtag(&self.hashtag);
+                    if !hashtag.is_empty() {
+                        self.hashtag.clear();
+                        return self.set_tab(ctx, ExploreTab::Hashtag(hashtag));
+                    }
+                }
+                ExploreMessage::Pin(hashtag) => {
+                    if !ctx.config.explore.pinned.contains(&hashtag) {
+                        ctx.config.explore.pinned.push(hashtag);
+                        ctx.config.save();
+                    }
+                }
+                ExploreMessage::Unpin(hashtag) => {
+                    ctx.config.explore.pinned.retain(|h| h != &hashtag);
+                    ctx.config.save();
+                }
+                ExploreMessage::ToggleRelay(url, selected) => {
+                    if selected {
+                        ctx.config.explore.relays.insert(url.to_string());
+                    } else {
+                        ctx.config.explore.relays.remove(&url.to_string());
+                    }
+                    ctx.config.save();
+                    return self.fetch(ctx);
+                }
+                ExploreMessage::SetRelays(relays) => self.available_relays = relays,
+                ExploreMessage::SetTrendingHours(hours) => {
+                    self.trending_hours = hours;
+                    return self.fetch(ctx);
+                }
+                // Searching is expensive, it's run again only on request
+                ExploreMessage::Refresh => {
+                    if !matches!(self.tab, ExploreTab::Search(_)) {
+                        return self.fetch(ctx);
+                    }
+                }
+                ExploreMessage::SetEvents(tab, events) => {
+                    if tab == self.tab {
+                        self.events = events;
+                    }
+                }
+                ExploreMessage::Received(event) => {
+                    if let ExploreTab::Hashtag(hashtag) = &self.tab {
+                        let tagged = RawEvent::from_event(&event)
+                            .map(|raw| raw.tag_values("t").any(|t| t.eq_ignore_ascii_case(hashtag)))
+                            .unwrap_or(false);
+                        if tagged && !self.events.iter().any(|e| e.id == event.id) {
+                            self.events.insert(0, event);
+                        }
+                    }
+                }
+                ExploreMessage::QueryChanged(query) => self.query = query,
+                ExploreMessage::Search => {
+                    let query = self.query.trim().to_string();
+                    if !query.is_empty() {
+                        return self.set_tab(ctx, ExploreTab::Search(query));
+                    }
+                }
+                ExploreMessage::SetSearchResults(query, results) => {
+                    if self.tab == ExploreTab::Search(query) {
+                        self.searching = false;
+                        self.search_results = results;
+                    }
+                }
+            }
+        }
+
+        Command::none()
+    }
+
+    fn posts(&self, _ctx: &Context) -> Vec<Event> {
+        match self.tab {
+            ExploreTab::Search(_) => self.search_results.notes.clone(),
+            _ => self.events.clone(),
+        }
+    }
+
+    fn view(&self, ctx: &Context) -> Element<Message> {
+        let mut tabs = Row::new()
+            .push(self.tab_button(String::from("Global"), ExploreTab::Global))
+            .push(self.tab_button(String::from("Trending"), ExploreTab::Trending))
+            .spacing(10);
+        for hashtag in ctx.config.explore.pinned.iter() {
+            tabs = tabs.push(self.tab_button(
+                format!("#{}", hashtag),
+                ExploreTab::Hashtag(hashtag.clone()),
+            ));
+        }
+
+        let search_input = TextInput::new("Search notes, profiles and hashtags", &self.query)
+            .id(SEARCH_INPUT_ID.clone())
+            .on_input(|s| ExploreMessage::QueryChanged(s).into())
+            .on_submit(ExploreMessage::Search.into())
+            .padding(10);
+        let search_button = Button::new("Search")
+            .padding(10)
+            .on_press(ExploreMessage::Search.into());
+
+        let hashtag_input = TextInput::new("Browse hashtag", &self.hashtag)
+            .on_input(|s| ExploreMessage::HashtagChanged(s).into())
+            .on_submit(ExploreMessage::OpenHashtag.into())
+            .padding(10);
+        let hashtag_button = Button::new("Open")
+            .padding(10)
+            .on_press(ExploreMessage::OpenHashtag.into());
+
+        let mut content = Column::new()
+            .push(
+                Row::new()
+                    .push(search_input)
+                    .push(search_button)
+                    .spacing(10),
+            )
+            .push(tabs)
+            .push(
+                Row::new()
+                    .push(hashtag_input)
+                    .push(hashtag_button)
+                    .spacing(10),
+            );
+
+        match &self.tab {
+            ExploreTab::Global => {
+                let mut relays = Row::new().spacing(15);
+                for url in self.available_relays.iter() {
+                    let url_clone = url.clone();
+                    relays = relays.push(
+                        Checkbox::new(
+                            url.to_string(),
+                            ctx.config.explore.relays.contains(&url.to_string()),
+                        )
+                        .on_toggle(move |selected| {
+                            ExploreMessage::ToggleRelay(url_clone.clone(), selected).into()
+                        }),
+                    );
+                }
+                content = content.push(relays);
+            }
+            ExploreTab::Trending => {
+                let mut hours = Row::new()
+                    .push(Text::new("Reactions and reposts of the last"))
+                    .spacing(10);
+                for h in TRENDING_HOURS.into_iter() {
+                    let style = if self.trending_hours == h {
+                        theme::Button::Primary
+                    } else {
+                        theme::Button::Secondary
+                    };
+                    hours = hours.push(
+                        Button::new(Text::new(format!("{}h", h)))
+                            .style(style)
+                            .on_press(ExploreMessage::SetTrendingHours(h).into()),
+                    );
+                }
+                content = content.push(hours);
+            }
+            ExploreTab::Hashtag(hashtag) => {
+                let pin = if ctx.config.explore.pinned.contains(hashtag) {
+                    Button::new("Unpin").on_press(ExploreMessage::Unpin(hashtag.clone()).into())
+                } else {
+                    Button::new("Pin").on_press(ExploreMessage::Pin(hashtag.clone()).into())
+                };
+                content = content.push(
+                    Row::new()
+                        .push(Text::new(format!("#{}", hashtag)).size(24))
+                        .push(pin)
+                        .spacing(10),
+                );
+            }
+            ExploreTab::Search(query) => {
+                content = content
+                    .push(Text::new(format!("Results for \"{}\"", query)).size(24))
+                    .push(Rule::horizontal(1));
+                return Dashboard::new().view(
+                    ctx,
+                    self.view_search_results(ctx, content)
+                        .spacing(20)
+                        .padding(20),
+                );
+            }
+        }
+
+        content = content.push(Rule::horizontal(1));
+
+        if self.events.is_empty() {
+            content = content.push(Text::new("No notes yet"));
+        }
+        for event in self.events.iter() {
+            content = content.push(Post::new(event.clone()).view(ctx));
+        }
+
+        Dashboard::new().view(ctx, content.spacing(20).padding(20))
+    }
+}
+
+impl From<ExploreState> for Box<dyn State> {
+    fn from(s: ExploreState) -> Box<dyn State> {
+        Box::new(s)
+    }
+}
+
+impl From<ExploreMessage> for Message {
+    fn from(msg: ExploreMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Explore(msg))
+    }
+}
+
+/// Lowercase, without the leading `#`
+fn normalize_hashtag(hashtag: &str) -> String {
+    hashtag.trim().trim_start_matches('#').to_lowercase()
+}
+
+async fn get_events(client: &Client, filters: Vec<SubscriptionFilter>) -> Vec<Event> {
+    match client.get_events_of(filters).await {
+        Ok(mut events) => {
+            events.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+            events
+        }
+        Err(e) => {
+            log::error!("Impossible to get events: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Query only `relays`, through a short-lived client sharing our keys
+async fn get_events_from(
+    client: &Client,
+    relays: &BTreeSet<String>,
+    filters: Vec<SubscriptionFilter>,
+) -> Vec<Event> {
+    let connected = client.relays().await;
+    if relays.is_empty()
+        || connected
+            .keys()
+            .all(|url| relays.contains(&url.to_string()))
+    {
+        return get_events(client, filters).await;
+    }
+
+    let temp = Client::new(&client.keys());
+    for (url, relay) in connected
+        .iter()
+        .filter(|(url, _)| relays.contains(&url.to_string()))
+    {
+        if let Err(e) = temp.add_relay(url.clone(), relay.proxy()).await {
+            log::error!("Impossible to add {}: {}", url, e);
+        }
+    }
+    temp.connect().await;
+    let events = get_events(&temp, filters).await;
+    if let Err(e) = temp.shutdown().await {
+        log::error!("Impossible to shutdown client: {}", e);
+    }
+    events
+}
+
+/// Notes with the most reactions and reposts since `since`, counted in the event index
+/// of what the sync received. The notes missing from the index are fetched.
+async fn trending(client: &Client, db: &Database, since: u64) -> Vec<Event> {
+    let db = db.clone();
+    let ranked = tokio::task::spawn_blocking(move || {
+        let mut ranked = Vec::new();
+        for (id, _) in db.trending(since, TRENDING_LIMIT)?.into_iter() {
+            let note = db
+                .get(&id)?
+                .filter(|raw| raw.kind == TEXT_NOTE_KIND)
+                .and_then(|raw| Event::from_json(raw.as_json()).ok());
+            ranked.push((id, note));
+        }
+        Ok::<_, rusqlite::Error>(ranked)
+    })
+    .await;
+    let ranked = match ranked {
+        Ok(Ok(ranked)) => ranked,
+        Ok(Err(e)) => {
+            log::error!("Impossible to count trending notes: {}", e);
+            return Vec::new();
+        }
+        Err(e) => {
+            log::error!("Impossible to count trending notes: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let missing: Vec<EventId> = ranked
+        .iter()
+        .filter(|(_, note)| note.is_none())
+        .filter_map(|(id, _)| EventId::from_hex(id).ok())
+        .collect();
+    let mut fetched: HashMap<String, Event> = HashMap::new();
+    if !missing.is_empty() {
+        let filter = SubscriptionFilter::new()
+            .ids(missing)
+            .kind(Kind::from(TEXT_NOTE_KIND));
+        for event in get_events(client, vec![filter]).await.into_iter() {
+            fetched.insert(event.id.to_hex(), event);
+        }
+    }
+
+    ranked
+        .into_iter()
+        .filter_map(|(id, note)| note.or_else(|| fetched.remove(&id)))
+        .collect()
+}
@@ -0,0 +1,218 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! NIP-50 search, on the relays supporting it and on the local event index
+
+use std::collections::{HashMap, HashSet};
+
+use iced::futures::future;
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::{Event, Kind, SubscriptionFilter};
+use nostr_sdk::Client;
+use serde::Deserialize;
+
+use super::{get_events_from, TEXT_NOTE_KIND};
+use crate::db::Database;
+use crate::nips::raw::RawEvent;
+use crate::stage::dashboard::relay_info::RelayInfoCache;
+
+const SEARCH_NIP: u16 = 50;
+const METADATA_KIND: u64 = 0;
+const NOTES_LIMIT: usize = 50;
+const PROFILES_LIMIT: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct ProfileResult {
+    pub public_key: XOnlyPublicKey,
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub about: Option<String>,
+}
+
+impl ProfileResult {
+    fn matches(&self, query: &str) -> bool {
+        [&self.name, &self.display_name, &self.about]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(query))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Metadata {
+    name: Option<String>,
+    display_name: Option<String>,
+    about: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    pub notes: Vec<Event>,
+    pub profiles: Vec<ProfileResult>,
+    pub hashtags: Vec<String>,
+}
+
+impl SearchResults {
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.profiles.is_empty() && self.hashtags.is_empty()
+    }
+}
+
+/// Connected relays advertising NIP-50 in their information document
+async fn search_relays(client: &Client, relay_info: &RelayInfoCache) -> HashSet<String> {
+    let relays: Vec<_> = client
+        .relays()
+        .await
+        .into_iter()
+        .map(|(url, relay)| (url, relay.proxy()))
+        .collect();
+    let documents = future::join_all(
+        relays
+            .iter()
+            .map(|(url, proxy)| relay_info.fetch(url, *proxy)),
+    )
+    .await;
+    relays
+        .into_iter()
+        .map(|(url, _)| url)
+        .zip(documents)
+        .filter_map(|(url, document)| match document {
+            Some(document) if document.supports(SEARCH_NIP) => Some(url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Notes and profiles of the event index matching `query`
+async fn search_index(db: &Database, query: String) -> (Vec<Event>, Vec<Event>) {
+    let db = db.clone();
+    let found = tokio::task::spawn_blocking(move || {
+        Ok::<_, rusqlite::Error>((
+            db.search_notes(&query, NOTES_LIMIT)?,
+            db.search_profiles(&query, PROFILES_LIMIT)?,
+        ))
+    })
+    .await;
+    let to_events = |events: Vec<RawEvent>| -> Vec<Event> {
+        events
+            .into_iter()
+            .filter_map(|raw| Event::from_json(raw.as_json()).ok())
+            .collect()
+    };
+    match found {
+        Ok(Ok((notes, profiles))) => (to_events(notes), to_events(profiles)),
+        Ok(Err(e)) => {
+            log::error!("Impossible to search the event index: {}", e);
+            (Vec::new(), Vec::new())
+        }
+        Err(e) => {
+            log::error!("Impossible to search the event index: {}", e);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+pub async fn search(
+    client: &Client,
+    db: &Database,
+    relay_info: &RelayInfoCache,
+    query: String,
+) -> SearchResults {
+    let needle = query.to_lowercase();
+    let mut notes: HashMap<String, Event> = HashMap::new();
+    let mut profiles: HashMap<XOnlyPublicKey, ProfileResult> = HashMap::new();
+
+    // Local events first, so known profiles win over the remote ones
+    let (local_notes, local_profiles) = search_index(db, query.clone()).await;
+    for event in local_notes.into_iter() {
+        notes.insert(event.id.to_hex(), event);
+    }
+    for event in local_profiles.into_iter() {
+        let profile = profile_result(&event);
+        if profile.matches(&needle) {
+            profiles.insert(profile.public_key, profile);
+        }
+    }
+    if let Ok(store) = client.store() {
+        for profile in store.get_contacts().unwrap_or_default().into_iter() {
+            let profile = ProfileResult {
+                public_key: profile.pubkey,
+                name: profile.name,
+                display_name: profile.display_name,
+                about: profile.about,
+            };
+            if profile.matches(&needle) {
+                profiles.entry(profile.public_key).or_insert(profile);
+            }
+        }
+    }
+
+    let relays = search_relays(client, relay_info).await;
+    if !relays.is_empty() {
+        let filters = vec![
+            SubscriptionFilter::new()
+                .kind(Kind::from(TEXT_NOTE_KIND))
+                .search(query.clone())
+                .limit(NOTES_LIMIT),
+            SubscriptionFilter::new()
+                .kind(Kind::from(METADATA_KIND))
+                .search(query.clone())
+                .limit(PROFILES_LIMIT),
+        ];
+        for event in get_events_from(client, &relays, filters).await.into_iter() {
+            if is_metadata(&event) {
+                profiles
+                    .entry(event.pubkey)
+                    .or_insert_with(|| profile_result(&event));
+            } else {
+                notes.entry(event.id.to_hex()).or_insert(event);
+            }
+        }
+    } else {
+        log::debug!("No connected relay supports NIP-50, searching only the local events");
+    }
+
+    let mut notes: Vec<Event> = notes.into_values().collect();
+    notes.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+
+    let mut profiles: Vec<ProfileResult> = profiles.into_values().collect();
+    profiles.sort_by_key(|p| p.display_name.clone().or_else(|| p.name.clone()));
+
+    SearchResults {
+        hashtags: hashtags(&notes, &needle),
+        notes,
+        profiles,
+    }
+}
+
+fn profile_result(event: &Event) -> ProfileResult {
+    let metadata: Metadata = serde_json::from_str(&event.content).unwrap_or_default();
+    ProfileResult {
+        public_key: event.pubkey,
+        name: metadata.name,
+        display_name: metadata.display_name,
+        about: metadata.about,
+    }
+}
+
+fn is_metadata(event: &Event) -> bool {
+    RawEvent::from_event(event)
+        .map(|raw| raw.kind == METADATA_KIND)
+        .unwrap_or(false)
+}
+
+/// Hashtags of the matching notes that contain the query, most used first
+fn hashtags(notes: &[Event], needle: &str) -> Vec<String> {
+    let needle = needle.trim_start_matches('#');
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for raw in notes.iter().filter_map(|e| RawEvent::from_event(e).ok()) {
+        let tags: HashSet<String> = raw.tag_values("t").map(|t| t.to_lowercase()).collect();
+        for tag in tags.into_iter().filter(|t| t.contains(needle)) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut hashtags: Vec<(String, usize)> = counts.into_iter().collect();
+    hashtags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hashtags.into_iter().map(|(tag, _)| tag).collect()
+}
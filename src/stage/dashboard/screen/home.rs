@@ -6,16 +6,17 @@ use iced::{Command, Element};
 use nostr_sdk::nostr::Event;
 
 use crate::message::{DashboardMessage, Message};
+use crate::nips::raw::RawEvent;
 use crate::stage::dashboard::component::post::Post;
 use crate::stage::dashboard::component::Dashboard;
 use crate::stage::dashboard::{Context, State};
 
-const FEED_LIMIT: usize = 40;
-
 #[derive(Debug, Clone)]
 pub enum HomeMessage {
     PushTextNote(Event),
     Like(Event),
+    Reply(Event),
+    Repost(Event),
 }
 
 #[derive(Clone, Default)]
@@ -59,12 +60,8 @@ impl State for HomeState {
             }
             Message::Dashboard(DashboardMessage::Home(msg)) => match msg {
                 HomeMessage::PushTextNote(_) => {}
-                HomeMessage::Like(event) => {
-                    let client = ctx.client.clone();
-                    return Command::perform(async move { client.like(&event).await }, |_| {
-                        Message::Tick
-                    });
-                }
+                // Handled by the dashboard
+                HomeMessage::Like(_) | HomeMessage::Reply(_) | HomeMessage::Repost(_) => {}
             },
             _ => (),
         }
@@ -72,23 +69,35 @@ impl State for HomeState {
         Command::none()
     }
 
+    fn posts(&self, ctx: &Context) -> Vec<Event> {
+        match ctx.client.store() {
+            Ok(store) => store
+                .get_feed(ctx.config.feed.page_size, self.page)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|event| !(ctx.config.feed.hide_replies && is_reply(event)))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
     fn view(&self, ctx: &Context) -> Element<Message> {
         let mut content: Column<Message> = Column::new();
 
-        if let Ok(store) = ctx.client.store() {
-            for event in store
-                .get_feed(FEED_LIMIT, self.page)
-                .unwrap_or_default()
-                .into_iter()
-            {
-                content = content.push(Post::new(event).view(ctx));
-            }
+        for event in self.posts(ctx).into_iter() {
+            content = content.push(Post::new(event).view(ctx));
         }
 
         Dashboard::new().view(ctx, content.spacing(10).padding(20))
     }
 }
 
+fn is_reply(event: &Event) -> bool {
+    RawEvent::from_event(event)
+        .map(|raw| raw.tag_values("e").next().is_some())
+        .unwrap_or(false)
+}
+
 impl From<HomeState> for Box<dyn State> {
     fn from(s: HomeState) -> Box<dyn State> {
         Box::new(s)
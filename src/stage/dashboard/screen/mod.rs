@@ -8,11 +8,13 @@ mod home;
 mod notifications;
 mod profile;
 mod setting;
+mod thread;
 
 pub use self::chat::{ChatMessage, ChatState};
 pub use self::contacts::{ContactsMessage, ContactsState};
-pub use self::explore::{ExploreMessage, ExploreState};
+pub use self::explore::{ExploreMessage, ExploreState, SEARCH_INPUT_ID};
 pub use self::home::{HomeMessage, HomeState};
 pub use self::notifications::{NotificationsMessage, NotificationsState};
 pub use self::profile::{ProfileMessage, ProfileState};
-pub use self::setting::{RelaysState, SettingMessage, SettingState};
+pub use self::setting::{RelaysMessage, RelaysState, SettingMessage, SettingState};
+pub use self::thread::{ThreadMessage, ThreadState, COMPOSER_ID};
@@ -1,22 +1,88 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::widget::Column;
-use iced::{Command, Element};
+use iced::widget::{Button, Column, Row, Rule, Space, Text};
+use iced::{theme, Alignment, Command, Element, Length};
+use nostr_sdk::nostr::{Event, EventId, Kind, SubscriptionFilter};
+use nostr_sdk::Client;
 
+use crate::component::{Circle, Icon};
 use crate::message::{DashboardMessage, Message};
+use crate::nips::nip10;
+use crate::nips::raw::{now, RawEvent};
 use crate::stage::dashboard::component::Dashboard;
+use crate::stage::dashboard::notification::{
+    NotificationKind, REACTION_KIND, REPOST_KIND, TEXT_NOTE_KIND, ZAP_RECEIPT_KIND,
+};
 use crate::stage::dashboard::{Context, State};
+use crate::theme::color::TRANSPARENT;
+use crate::theme::icon::{CHAT, HEART, LIGHTNING, PERSON, REPEAT};
+
+const NOTIFICATIONS_LIMIT: usize = 500;
 
 #[derive(Debug, Clone)]
-pub enum NotificationsMessage {}
+pub enum NotificationsMessage {
+    Refresh,
+    SetEvents(Vec<Event>),
+    MarkRead(NotificationKind, String),
+    MarkAllRead,
+}
 
 #[derive(Debug, Default)]
-pub struct NotificationsState {}
+pub struct NotificationsState {
+    loaded: bool,
+    opened_at: u64,
+}
 
 impl NotificationsState {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            loaded: false,
+            opened_at: now(),
+        }
+    }
+
+    /// Fetch the events tagging our public key, and the notes of ours they reply to so
+    /// replies can be told from mentions
+    pub fn fetch(client: Client) -> Command<Message> {
+        Command::perform(
+            async move {
+                let public_key = client.keys().public_key();
+                let filter = SubscriptionFilter::new()
+                    .pubkey(public_key)
+                    .kinds(vec![
+                        Kind::from(TEXT_NOTE_KIND),
+                        Kind::from(REPOST_KIND),
+                        Kind::from(REACTION_KIND),
+                        Kind::from(ZAP_RECEIPT_KIND),
+                    ])
+                    .limit(NOTIFICATIONS_LIMIT);
+                let mut events = match client.get_events_of(vec![filter]).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::error!("Impossible to get notifications: {}", e);
+                        return Vec::new();
+                    }
+                };
+
+                let parents: Vec<EventId> = events
+                    .iter()
+                    .filter(|e| e.kind == Kind::from(TEXT_NOTE_KIND))
+                    .filter_map(|e| RawEvent::from_event(e).ok())
+                    .filter_map(|raw| nip10::replied_to(&raw))
+                    .filter_map(|id| EventId::from_hex(id).ok())
+                    .collect();
+                if !parents.is_empty() {
+                    let filter = SubscriptionFilter::new().ids(parents).author(public_key);
+                    match client.get_events_of(vec![filter]).await {
+                        Ok(own) => events.extend(own),
+                        Err(e) => log::error!("Impossible to get replied notes: {}", e),
+                    }
+                }
+                events
+            },
+            |events| NotificationsMessage::SetEvents(events).into(),
+        )
     }
 }
 
@@ -25,16 +91,102 @@ impl State for NotificationsState {
         String::from("Nostr - Notifications")
     }
 
-    fn update(&mut self, _ctx: &mut Context, message: Message) -> Command<Message> {
-        if let Message::Dashboard(DashboardMessage::Notifications(_msg)) = message {
-            Command::none()
-        } else {
-            Command::none()
+    fn filters(&self, ctx: &Context) -> Vec<SubscriptionFilter> {
+        vec![SubscriptionFilter::new()
+            .pubkey(ctx.client.keys().public_key())
+            .kinds(vec![
+                Kind::from(TEXT_NOTE_KIND),
+                Kind::from(REPOST_KIND),
+                Kind::from(REACTION_KIND),
+                Kind::from(ZAP_RECEIPT_KIND),
+            ])
+            .since(self.opened_at)]
+    }
+
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
+        self.loaded = true;
+        Self::fetch(ctx.client.clone())
+    }
+
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
+        if !self.loaded {
+            return self.load(ctx);
+        }
+
+        if let Message::Dashboard(DashboardMessage::Notifications(msg)) = message {
+            match msg {
+                NotificationsMessage::MarkRead(kind, target) => {
+                    ctx.notifications.mark_read(kind, &target)
+                }
+                NotificationsMessage::MarkAllRead => ctx.notifications.mark_all_read(),
+                // Handled by `App`, since the unread count is needed on every screen
+                NotificationsMessage::Refresh | NotificationsMessage::SetEvents(_) => (),
+            }
         }
+
+        Command::none()
     }
 
     fn view(&self, ctx: &Context) -> Element<Message> {
-        let content = Column::new();
+        let heading = Row::new()
+            .push(Text::new("Notifications").size(30).width(Length::Fill))
+            .push(
+                Button::new("Mark all as read")
+                    .padding(10)
+                    .on_press(NotificationsMessage::MarkAllRead.into()),
+            )
+            .align_items(Alignment::Center);
+
+        let mut content = Column::new().push(heading).spacing(10);
+
+        for group in ctx.notifications.groups().into_iter() {
+            let icon = match group.kind {
+                NotificationKind::Mention => PERSON,
+                NotificationKind::Reply => CHAT,
+                NotificationKind::Reaction => HEART,
+                NotificationKind::Repost => REPEAT,
+                NotificationKind::Zap => LIGHTNING,
+            };
+            let dot = Circle::new(4.0).color(if group.is_unread() {
+                ctx.palette().primary
+            } else {
+                TRANSPARENT
+            });
+
+            let mut info = Column::new()
+                .push(Text::new(group.summary(|pk| ctx.display_name(pk))))
+                .spacing(5)
+                .width(Length::Fill);
+            if let Some(preview) = &group.preview {
+                info = info.push(Text::new(preview.clone()).size(14));
+            }
+
+            let row = Row::new()
+                .push(dot)
+                .push(Icon::view(&icon))
+                .push(info)
+                .spacing(15)
+                .align_items(Alignment::Center);
+
+            content = content
+                .push(
+                    Button::new(row)
+                        .width(Length::Fill)
+                        .padding(10)
+                        .style(theme::Button::Text)
+                        .on_press(
+                            NotificationsMessage::MarkRead(group.kind, group.target.clone()).into(),
+                        ),
+                )
+                .push(Rule::horizontal(1));
+        }
+
+        if ctx.notifications.groups().is_empty() {
+            content = content
+                .push(Space::with_height(Length::Fixed(20.0)))
+                .push(Text::new("No notifications yet"));
+        }
+
         Dashboard::new().view(ctx, content.spacing(20).padding(20))
     }
 }
@@ -44,3 +196,9 @@ impl From<NotificationsState> for Box<dyn State> {
         Box::new(s)
     }
 }
+
+impl From<NotificationsMessage> for Message {
+    fn from(msg: NotificationsMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Notifications(msg))
+    }
+}
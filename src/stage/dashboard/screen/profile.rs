@@ -1,23 +1,68 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::widget::Column;
-use iced::{Command, Element};
+use iced::widget::{Button, Column, Row, Text};
+use iced::{theme, Alignment, Command, Element};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::{Event, Kind, SubscriptionFilter};
+use serde_json::Value;
 
 use crate::message::{DashboardMessage, Message};
+use crate::nips::nip19;
+use crate::nips::raw::now;
+use crate::stage::dashboard::component::post::Post;
 use crate::stage::dashboard::component::Dashboard;
 use crate::stage::dashboard::context::Context;
-use crate::stage::dashboard::State;
+use crate::stage::dashboard::{lookup, State};
+
+const NOTES_LIMIT: usize = 40;
+
+const METADATA_KIND: u64 = 0;
+const TEXT_NOTE_KIND: u64 = 1;
 
 #[derive(Debug, Clone)]
-pub enum ProfileMessage {}
+pub enum ProfileMessage {
+    SetEvents(Vec<Event>),
+    /// Event received by the sync
+    Received(Event),
+}
 
 #[derive(Debug, Default)]
-pub struct ProfileState {}
+pub struct ProfileState {
+    loaded: bool,
+    /// `None` for our own profile
+    public_key: Option<XOnlyPublicKey>,
+    /// Relay hints of the `nprofile` it was opened from
+    relays: Vec<String>,
+    /// Content of the latest kind 0
+    metadata: Option<Value>,
+    notes: Vec<Event>,
+    fetched: bool,
+    opened_at: u64,
+}
 
 impl ProfileState {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(public_key: Option<XOnlyPublicKey>, relays: Vec<String>) -> Self {
+        Self {
+            public_key,
+            relays,
+            opened_at: now(),
+            ..Self::default()
+        }
+    }
+
+    fn public_key(&self, ctx: &Context) -> XOnlyPublicKey {
+        self.public_key
+            .unwrap_or_else(|| ctx.client.keys().public_key())
+    }
+
+    fn metadata(&self, field: &str) -> Option<String> {
+        self.metadata
+            .as_ref()?
+            .get(field)?
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(String::from)
     }
 }
 
@@ -26,16 +71,116 @@ impl State for ProfileState {
         String::from("Nostr - Profile")
     }
 
-    fn update(&mut self, _ctx: &mut Context, message: Message) -> Command<Message> {
-        if let Message::Dashboard(DashboardMessage::Profile(_msg)) = message {
-            Command::none()
-        } else {
-            Command::none()
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
+        self.loaded = true;
+        let client = ctx.client.clone();
+        let public_key = self.public_key(ctx);
+        let relays = self.relays.clone();
+        Command::perform(
+            async move {
+                let filters = vec![
+                    SubscriptionFilter::new()
+                        .authors(vec![public_key])
+                        .kind(Kind::from(METADATA_KIND))
+                        .limit(1),
+                    SubscriptionFilter::new()
+                        .authors(vec![public_key])
+                        .kind(Kind::from(TEXT_NOTE_KIND))
+                        .limit(NOTES_LIMIT),
+                ];
+                lookup::get_events(&client, &relays, filters).await
+            },
+            |events| ProfileMessage::SetEvents(events).into(),
+        )
+    }
+
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
+        if !self.loaded {
+            return self.load(ctx);
         }
+
+        if let Message::Dashboard(DashboardMessage::Profile(msg)) = message {
+            match msg {
+                ProfileMessage::SetEvents(events) => {
+                    self.fetched = true;
+                    let (metadata, notes): (Vec<Event>, Vec<Event>) = events
+                        .into_iter()
+                        .partition(|e| e.kind == Kind::from(METADATA_KIND));
+                    self.metadata = metadata
+                        .first()
+                        .and_then(|e| serde_json::from_str(&e.content).ok());
+                    self.notes = notes;
+                }
+                ProfileMessage::Received(event) => {
+                    if event.pubkey != self.public_key(ctx) {
+                        return Command::none();
+                    }
+                    if event.kind == Kind::from(METADATA_KIND) {
+                        if let Ok(metadata) = serde_json::from_str(&event.content) {
+                            self.metadata = Some(metadata);
+                        }
+                    } else if event.kind == Kind::from(TEXT_NOTE_KIND)
+                        && !self.notes.iter().any(|e| e.id == event.id)
+                    {
+                        self.notes.insert(0, event);
+                    }
+                }
+            }
+        }
+
+        Command::none()
+    }
+
+    /// Metadata and notes published while the profile is open
+    fn filters(&self, ctx: &Context) -> Vec<SubscriptionFilter> {
+        vec![SubscriptionFilter::new()
+            .authors(vec![self.public_key(ctx)])
+            .kinds(vec![Kind::from(METADATA_KIND), Kind::from(TEXT_NOTE_KIND)])
+            .since(self.opened_at)]
+    }
+
+    fn posts(&self, _ctx: &Context) -> Vec<Event> {
+        self.notes.clone()
     }
 
     fn view(&self, ctx: &Context) -> Element<Message> {
-        let content = Column::new();
+        let public_key = self.public_key(ctx);
+        let npub = nip19::npub(&public_key);
+
+        let name = self
+            .metadata("display_name")
+            .or_else(|| self.metadata("name"))
+            .unwrap_or_else(|| ctx.display_name(&public_key));
+
+        let mut content = Column::new()
+            .push(Text::new(name).size(30))
+            .push(
+                Row::new()
+                    .push(Text::new(npub.clone()).size(14))
+                    .push(
+                        Button::new(Text::new("Copy").size(14))
+                            .padding(5)
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::Clipboard(npub)),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+            )
+            .spacing(10);
+
+        if let Some(about) = self.metadata("about") {
+            content = content.push(Text::new(about));
+        }
+
+        if !self.fetched {
+            content = content.push(Text::new("Loading..."));
+        } else if self.notes.is_empty() {
+            content = content.push(Text::new("No notes found"));
+        }
+        for event in self.notes.iter() {
+            content = content.push(Post::new(event.clone()).view(ctx));
+        }
+
         Dashboard::new().view(ctx, content.spacing(20).padding(20))
     }
 }
@@ -45,3 +190,9 @@ impl From<ProfileState> for Box<dyn State> {
         Box::new(s)
     }
 }
+
+impl From<ProfileMessage> for Message {
+    fn from(msg: ProfileMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Profile(msg))
+    }
+}
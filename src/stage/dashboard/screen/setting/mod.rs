@@ -1,21 +1,34 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use iced::widget::{Button, Column};
-use iced::{Command, Element};
+use iced::widget::{Button, Checkbox, Column, PickList, Row, Slider, Text};
+use iced::{Alignment, Command, Element};
 
+use crate::config::{MAX_FONT_SCALE, MIN_FONT_SCALE};
 use crate::message::{DashboardMessage, Message};
+use crate::notifier::NotifierSettings;
 use crate::stage::dashboard::component::Dashboard;
 use crate::stage::dashboard::{Context, Setting, Stage, State};
+use crate::theme::Theme;
 
 pub mod relays;
 
 pub use self::relays::{RelaysMessage, RelaysState};
 
+const PAGE_SIZES: [usize; 4] = [20, 40, 80, 160];
+
 #[derive(Debug, Clone)]
 pub enum SettingMessage {
     GoToRelays,
     Relays(RelaysMessage),
+    SetNotifier(NotifierSettings),
+    SetTheme(Theme),
+    /// Applied while the slider moves, saved on release
+    SetFontScale(f32),
+    SaveConfig,
+    SetAutoloadImages(bool),
+    SetPageSize(usize),
+    SetHideReplies(bool),
 }
 
 #[derive(Debug, Default)]
@@ -32,13 +45,36 @@ impl State for SettingState {
         String::from("Nostr - Setting")
     }
 
-    fn update(&mut self, _ctx: &mut Context, message: Message) -> Command<Message> {
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
         if let Message::Dashboard(DashboardMessage::Setting(msg)) = message {
             match msg {
                 SettingMessage::GoToRelays => Command::perform(async move {}, |_| {
                     Message::SetDashboardStage(Stage::Setting(Setting::Relays))
                 }),
-                _ => Command::none(),
+                SettingMessage::Relays(_) => Command::none(),
+                SettingMessage::SetFontScale(scale) => {
+                    ctx.config.appearance.font_scale = scale;
+                    Command::none()
+                }
+                msg => {
+                    match msg {
+                        SettingMessage::SetNotifier(settings) => {
+                            ctx.config.notifications = settings;
+                            ctx.notifier.set_settings(settings);
+                        }
+                        SettingMessage::SetTheme(theme) => ctx.config.appearance.theme = theme,
+                        SettingMessage::SetAutoloadImages(value) => {
+                            ctx.config.media.autoload_images = value
+                        }
+                        SettingMessage::SetPageSize(size) => ctx.config.feed.page_size = size,
+                        SettingMessage::SetHideReplies(value) => {
+                            ctx.config.feed.hide_replies = value
+                        }
+                        _ => (),
+                    }
+                    ctx.config.save();
+                    Command::none()
+                }
             }
         } else {
             Command::none()
@@ -51,13 +87,137 @@ impl State for SettingState {
             .on_press(Message::Dashboard(DashboardMessage::Setting(
                 SettingMessage::GoToRelays,
             )));
-        let content = Column::new().push(button);
+
+        let config = &ctx.config;
+        let appearance = Column::new()
+            .push(Text::new("Appearance").size(20))
+            .push(
+                Row::new()
+                    .push(Text::new("Theme"))
+                    .push(
+                        PickList::new(
+                            Theme::available(),
+                            Some(config.appearance.theme.clone()),
+                            |theme| SettingMessage::SetTheme(theme).into(),
+                        )
+                        .padding(10),
+                    )
+                    .spacing(20)
+                    .align_items(Alignment::Center),
+            )
+            .push(
+                Row::new()
+                    .push(Text::new("Font scale"))
+                    .push(
+                        Slider::new(
+                            MIN_FONT_SCALE..=MAX_FONT_SCALE,
+                            config.appearance.font_scale,
+                            |scale| SettingMessage::SetFontScale(scale).into(),
+                        )
+                        .step(0.05)
+                        .on_release(SettingMessage::SaveConfig.into())
+                        .width(200),
+                    )
+                    .push(Text::new(format!(
+                        "{:.0}%",
+                        config.appearance.font_scale * 100.0
+                    )))
+                    .spacing(20)
+                    .align_items(Alignment::Center),
+            )
+            .spacing(10);
+
+        let media = Column::new()
+            .push(Text::new("Media").size(20))
+            .push(
+                Checkbox::new(
+                    "Load profile pictures automatically",
+                    config.media.autoload_images,
+                )
+                .on_toggle(|value| SettingMessage::SetAutoloadImages(value).into()),
+            )
+            .spacing(10);
+
+        let feed = Column::new()
+            .push(Text::new("Feed").size(20))
+            .push(
+                Row::new()
+                    .push(Text::new("Notes per page"))
+                    .push(
+                        PickList::new(&PAGE_SIZES[..], Some(config.feed.page_size), |size| {
+                            SettingMessage::SetPageSize(size).into()
+                        })
+                        .padding(10),
+                    )
+                    .spacing(20)
+                    .align_items(Alignment::Center),
+            )
+            .push(
+                Checkbox::new("Hide replies", config.feed.hide_replies)
+                    .on_toggle(|value| SettingMessage::SetHideReplies(value).into()),
+            )
+            .spacing(10);
+
+        let settings = config.notifications;
+        let notifications = Column::new()
+            .push(Text::new("Desktop notifications").size(20))
+            .push(notifier_checkbox(
+                "Direct messages",
+                settings,
+                settings.direct_messages,
+                |s, v| s.direct_messages = v,
+            ))
+            .push(notifier_checkbox(
+                "Mentions",
+                settings,
+                settings.mentions,
+                |s, v| s.mentions = v,
+            ))
+            .push(notifier_checkbox(
+                "Replies",
+                settings,
+                settings.replies,
+                |s, v| s.replies = v,
+            ))
+            .push(notifier_checkbox(
+                "Do not disturb",
+                settings,
+                settings.do_not_disturb,
+                |s, v| s.do_not_disturb = v,
+            ))
+            .spacing(10);
+
+        let content = Column::new()
+            .push(button)
+            .push(appearance)
+            .push(media)
+            .push(feed)
+            .push(notifications);
         Dashboard::new().view(ctx, content.spacing(20).padding(20))
     }
 }
 
+fn notifier_checkbox<'a>(
+    label: &str,
+    settings: NotifierSettings,
+    value: bool,
+    set: fn(&mut NotifierSettings, bool),
+) -> Checkbox<'a, Message> {
+    Checkbox::new(label, value).on_toggle(move |value| {
+        let mut settings = settings;
+        set(&mut settings, value);
+        SettingMessage::SetNotifier(settings).into()
+    })
+}
+
 impl From<SettingState> for Box<dyn State> {
     fn from(s: SettingState) -> Box<dyn State> {
         Box::new(s)
     }
 }
+
+impl From<SettingMessage> for Message {
+    fn from(msg: SettingMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Setting(msg))
+    }
+}
@@ -1,10 +1,12 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::time::Duration;
 
-use iced::widget::{text, Button, Checkbox, Column, Row, Rule, Text, TextInput};
+use chrono::{DateTime, Utc};
+use iced::widget::{text, Button, Checkbox, Column, PickList, Row, Rule, Text, TextInput};
 use iced::{time, Alignment, Command, Element, Length, Subscription};
 use nostr_sdk::nostr::url::Url;
 use nostr_sdk::RelayStatus;
@@ -12,10 +14,15 @@ use nostr_sdk::RelayStatus;
 use super::SettingMessage;
 use crate::component::{Circle, Icon};
 use crate::message::{DashboardMessage, Message};
+use crate::net::{self, ProxyOverride};
+use crate::nips::nip11::{self, RelayInformationDocument};
+use crate::nips::nip65::{self, RelayPolicy};
 use crate::stage::dashboard::component::Dashboard;
+use crate::stage::dashboard::relay_auth::{self, AuthPolicy, AuthState};
+use crate::stage::dashboard::relay_health::RelayStats;
 use crate::stage::dashboard::{Context, State};
-use crate::theme::color::{GREEN, GREY, RED, YELLOW};
 use crate::theme::icon::TRASH;
+use crate::theme::Palette;
 use crate::RUNTIME;
 
 #[derive(Debug, Clone)]
@@ -25,10 +32,35 @@ pub enum RelaysMessage {
     ProxyToggled(bool),
     AddRelay,
     AddRelayFromStore(Url, Option<SocketAddr>),
+    /// Relay added from the saved ones, with the proxy to keep as its override
+    RelayAdded(Url, Option<SocketAddr>),
     RemoveRelay(String),
     DisconnectRelay(String),
     UpdateRelays,
     SetRelays(Vec<(RelayStatus, Url, Option<SocketAddr>)>),
+    SetRelayInfo(Url, Option<RelayInformationDocument>),
+    ToggleRelayInfo(Url),
+    SetPolicy(Url, RelayPolicy),
+    PublishRelayList,
+    /// Saved relays restored at startup: whether proxies were adopted as overrides, the
+    /// relays reconnected to follow the proxy settings, and the relay list imported if
+    /// it was looked up
+    RelaysRestored(bool, Vec<Url>, Option<Option<Vec<(Url, RelayPolicy)>>>),
+    /// Relays reconnected after the proxy settings changed
+    Reconnected(Vec<Url>),
+    ProbeRelays,
+    AuthChallenge(String, Vec<Url>),
+    AuthSent(Vec<(Url, Result<String, String>)>),
+    Authenticate(Url),
+    DismissAuth(Url),
+    SetAuthPolicy(Url, AuthPolicy),
+    GlobalProxyChanged(String),
+    TorOnlyToggled(bool),
+    SaveNetwork,
+    EditProxy(Url),
+    EditedProxyChanged(String),
+    SaveRelayProxy,
+    CancelEditProxy,
 }
 
 #[derive(Debug, Default)]
@@ -38,6 +70,16 @@ pub struct RelaysState {
     proxy: String,
     use_proxy: bool,
     relays: Vec<(RelayStatus, Url, Option<SocketAddr>)>,
+    /// Relays whose NIP-11 document was already requested in this session
+    info_requested: HashSet<Url>,
+    expanded: Option<Url>,
+    /// Id of the relay list queued for publishing
+    relay_list_event: Option<String>,
+    relay_list_status: Option<String>,
+    global_proxy: String,
+    tor_only: bool,
+    /// Relay whose proxy is being edited, and the input
+    editing_proxy: Option<(Url, String)>,
     error: Option<String>,
 }
 
@@ -52,12 +94,160 @@ impl RelaysState {
         self.proxy = String::new();
         self.use_proxy = false;
         self.relays = Vec::new();
+        self.info_requested = HashSet::new();
+        self.expanded = None;
+        self.relay_list_event = None;
+        self.relay_list_status = None;
+        self.global_proxy = String::new();
+        self.tor_only = false;
+        self.editing_proxy = None;
         self.error = None;
     }
 
-    async fn add_relay(&mut self, ctx: &Context, proxy: Option<SocketAddr>) {
-        match ctx.client.add_relay(&self.relay_url, proxy).await {
+    /// Save `settings` and reconnect the relays whose proxy changed
+    fn apply_network(
+        &mut self,
+        ctx: &mut Context,
+        settings: net::ProxySettings,
+    ) -> Command<Message> {
+        net::set_settings(settings);
+        ctx.save_network();
+        self.error = None;
+        let client = ctx.client.clone();
+        Command::perform(async move { net::apply(&client).await }, |reconnected| {
+            RelaysMessage::Reconnected(reconnected).into()
+        })
+    }
+
+    fn fetch_relay_info(&mut self, ctx: &Context) -> Command<Message> {
+        let mut commands = Vec::new();
+        for (_, url, proxy) in self.relays.iter() {
+            if ctx.relay_info.is_stale(url) && self.info_requested.insert(url.clone()) {
+                let url = url.clone();
+                let proxy = *proxy;
+                commands.push(Command::perform(
+                    async move {
+                        let document = match nip11::fetch(&url, proxy).await {
+                            Ok(document) => Some(document),
+                            Err(e) => {
+                                log::warn!("Impossible to fetch NIP-11 document of {}: {}", url, e);
+                                None
+                            }
+                        };
+                        (url, document)
+                    },
+                    |(url, document)| RelaysMessage::SetRelayInfo(url, document).into(),
+                ));
+            }
+        }
+        Command::batch(commands)
+    }
+
+    fn view_relay_info<'a>(&self, document: &RelayInformationDocument) -> Column<'a, Message> {
+        let mut info = Column::new().spacing(5).padding([0, 0, 0, 34]);
+        if let Some(description) = &document.description {
+            info = info.push(Text::new(description.clone()).size(14));
+        }
+        if let Some(pubkey) = &document.pubkey {
+            info = info.push(Text::new(format!("Operator: {}", pubkey)).size(14));
+        }
+        if let Some(contact) = &document.contact {
+            info = info.push(Text::new(format!("Contact: {}", contact)).size(14));
+        }
+        if let Some(software) = &document.software {
+            let software = match &document.version {
+                Some(version) => format!("{} {}", software, version),
+                None => software.clone(),
+            };
+            info = info.push(Text::new(format!("Software: {}", software)).size(14));
+        }
+        if !document.supported_nips.is_empty() {
+            let nips: Vec<String> = document
+                .supported_nips
+                .iter()
+                .map(|nip| nip.to_string())
+                .collect();
+            info = info.push(Text::new(format!("Supported NIPs: {}", nips.join(", "))).size(14));
+        }
+
+        let limitation = &document.limitation;
+        let limits: Vec<String> = [
+            ("max message length", limitation.max_message_length),
+            ("max subscriptions", limitation.max_subscriptions),
+            ("max filters", limitation.max_filters),
+            ("max limit", limitation.max_limit),
+            ("max event tags", limitation.max_event_tags),
+            ("max content length", limitation.max_content_length),
+            ("min PoW difficulty", limitation.min_pow_difficulty),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| format!("{}: {}", name, v)))
+        .collect();
+        if !limits.is_empty() {
+            info = info.push(Text::new(format!("Limitations: {}", limits.join(", "))).size(14));
+        }
+        info
+    }
+
+    fn view_relay_health<'a>(&self, stats: &RelayStats, palette: &Palette) -> Column<'a, Message> {
+        let mut health = Column::new()
+            .push(
+                Text::new(format!(
+                    "Connections: {} ({} failed), events: {} ({} duplicates), {}",
+                    stats.attempts,
+                    stats.failures,
+                    stats.events,
+                    stats.duplicates,
+                    format_bytes(stats.bytes),
+                ))
+                .size(14),
+            )
+            .push(Text::new(format!("Latency: {}", stats.sparkline())).size(14))
+            .spacing(5)
+            .padding([0, 0, 0, 34]);
+        if let Some((at, error)) = &stats.last_error {
+            let dt: DateTime<Utc> =
+                DateTime::from_timestamp(*at as i64, 0).unwrap_or(DateTime::<Utc>::MIN_UTC);
+            health = health.push(
+                Text::new(format!(
+                    "Last error ({}): {}",
+                    dt.format("%Y-%m-%d %H:%M:%S"),
+                    error
+                ))
+                .size(14)
+                .style(palette.red),
+            );
+        }
+        health
+    }
+
+    /// Relay being added, and its proxy: the one entered, or the one the settings give
+    fn relay_proxy(&self) -> Result<(Url, Option<SocketAddr>), String> {
+        let url = Url::parse(&self.relay_url).map_err(|e| e.to_string())?;
+        let proxy = if self.use_proxy {
+            let proxy: SocketAddr = self
+                .proxy
+                .parse()
+                .map_err(|e| format!("Invalid proxy: {}", e))?;
+            Some(proxy)
+        } else {
+            net::relay_proxy(&url).map_err(|e| e.to_string())?
+        };
+        Ok((url, proxy))
+    }
+
+    /// Add the relay, keeping the proxy entered as its override once it's added
+    async fn add_relay(&mut self, ctx: &mut Context, url: Url, proxy: Option<SocketAddr>) {
+        match ctx.client.add_relay(url.clone(), proxy).await {
             Ok(_) => {
+                if let (true, Some(proxy)) = (self.use_proxy, proxy) {
+                    let mut settings = net::settings();
+                    settings
+                        .overrides
+                        .insert(url.to_string(), ProxyOverride::Socks5(proxy));
+                    net::set_settings(settings);
+                    ctx.save_network();
+                }
                 ctx.client.connect().await;
                 self.relay_url.clear();
                 self.error = None;
@@ -78,9 +268,20 @@ impl State for RelaysState {
         ])
     }
 
-    fn load(&mut self, _ctx: &Context) -> Command<Message> {
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
         self.loaded = true;
-        Command::perform(async {}, |_| RelaysMessage::UpdateRelays.into())
+        let settings = net::settings();
+        self.global_proxy = settings.proxy.map(|p| p.to_string()).unwrap_or_default();
+        self.tor_only = settings.tor_only;
+        let mut commands = vec![Command::perform(async {}, |_| {
+            RelaysMessage::UpdateRelays.into()
+        })];
+        if ctx.relay_health.is_empty() {
+            commands.push(Command::perform(async {}, |_| {
+                RelaysMessage::ProbeRelays.into()
+            }));
+        }
+        Command::batch(commands)
     }
 
     fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
@@ -92,16 +293,12 @@ impl State for RelaysState {
                 RelaysMessage::ProxyChanged(proxy) => self.proxy = proxy,
                 RelaysMessage::ProxyToggled(value) => self.use_proxy = value,
                 RelaysMessage::AddRelay => {
-                    if self.use_proxy {
-                        match self.proxy.parse() {
-                            Ok(proxy) => {
-                                RUNTIME.block_on(async { self.add_relay(ctx, Some(proxy)).await })
-                            }
-                            Err(e) => self.error = Some(e.to_string()),
+                    match self.relay_proxy() {
+                        Ok((url, proxy)) => {
+                            RUNTIME.block_on(async { self.add_relay(ctx, url, proxy).await })
                         }
-                    } else {
-                        RUNTIME.block_on(async { self.add_relay(ctx, None).await });
-                    };
+                        Err(e) => self.error = Some(e),
+                    }
                     return self.load(ctx);
                 }
                 RelaysMessage::RemoveRelay(url) => {
@@ -115,15 +312,52 @@ impl State for RelaysState {
                     )
                 }
                 RelaysMessage::AddRelayFromStore(url, proxy) => {
+                    // The proxy it was saved with becomes its override once it's added
+                    let settings = net::settings();
+                    let adopted = proxy.filter(|proxy| {
+                        settings.proxy != Some(*proxy)
+                            && !settings.overrides.contains_key(url.as_str())
+                    });
+                    let proxy = match adopted {
+                        Some(proxy) => Some(proxy),
+                        None => match net::relay_proxy(&url) {
+                            Ok(proxy) => proxy,
+                            Err(e) => {
+                                self.error = Some(e.to_string());
+                                return Command::none();
+                            }
+                        },
+                    };
+                    let added = url.clone();
                     return Command::perform(
                         async move {
-                            if let Err(e) = client.add_relay(url.clone(), proxy).await {
+                            let result = client.add_relay(url.clone(), proxy).await;
+                            if let Err(e) = &result {
                                 log::error!("Impossible to add {}: {}", url, e.to_string());
                             }
                             client.connect().await;
+                            result.is_ok()
                         },
-                        |_| RelaysMessage::UpdateRelays.into(),
-                    )
+                        move |ok| {
+                            if ok {
+                                RelaysMessage::RelayAdded(added, adopted).into()
+                            } else {
+                                RelaysMessage::UpdateRelays.into()
+                            }
+                        },
+                    );
+                }
+                RelaysMessage::RelayAdded(url, adopted) => {
+                    if let Some(proxy) = adopted {
+                        let mut settings = net::settings();
+                        settings
+                            .overrides
+                            .entry(url.to_string())
+                            .or_insert(ProxyOverride::Socks5(proxy));
+                        net::set_settings(settings);
+                        ctx.save_network();
+                    }
+                    return Command::perform(async {}, |_| RelaysMessage::UpdateRelays.into());
                 }
                 RelaysMessage::DisconnectRelay(url) => {
                     return Command::perform(
@@ -135,7 +369,130 @@ impl State for RelaysState {
                         |_| RelaysMessage::UpdateRelays.into(),
                     )
                 }
-                RelaysMessage::SetRelays(relays) => self.relays = relays,
+                RelaysMessage::SetRelays(relays) => {
+                    self.relays = relays;
+                    return self.fetch_relay_info(ctx);
+                }
+                RelaysMessage::SetRelayInfo(url, document) => {
+                    if let Some(document) = document {
+                        ctx.relay_info.insert(&url, document);
+                    }
+                }
+                RelaysMessage::PublishRelayList => {
+                    let relays: Vec<(Url, RelayPolicy)> = self
+                        .relays
+                        .iter()
+                        .map(|(_, url, _)| (url.clone(), ctx.relay_policies.get(url)))
+                        .collect();
+                    let keys = client.keys();
+                    let raw = nip65::relay_list_event(keys.public_key(), &relays);
+                    match keys.secret_key().map(|secret_key| raw.sign(&secret_key)) {
+                        Ok(Ok(event)) => {
+                            self.relay_list_event = Some(event.id.to_hex());
+                            self.relay_list_status = None;
+                            return Command::perform(async move { event }, Message::Publish);
+                        }
+                        Ok(Err(e)) => {
+                            self.relay_list_status =
+                                Some(format!("Impossible to sign relay list: {}", e))
+                        }
+                        Err(e) => {
+                            self.relay_list_status =
+                                Some(format!("Impossible to sign relay list: {}", e))
+                        }
+                    }
+                }
+                RelaysMessage::Authenticate(url) => {
+                    if let Some(AuthState::Challenged(challenge)) =
+                        ctx.relay_auth.state(&url).cloned()
+                    {
+                        return Command::perform(
+                            async move { relay_auth::authenticate(&client, vec![url], challenge).await },
+                            |results| RelaysMessage::AuthSent(results).into(),
+                        );
+                    }
+                }
+                RelaysMessage::DismissAuth(url) => {
+                    ctx.relay_auth.set_state(&url, AuthState::Refused)
+                }
+                RelaysMessage::SetAuthPolicy(url, policy) => {
+                    ctx.config.relays.set_auth_policy(&url, policy);
+                    ctx.config.save();
+                }
+                RelaysMessage::GlobalProxyChanged(proxy) => self.global_proxy = proxy,
+                RelaysMessage::TorOnlyToggled(value) => self.tor_only = value,
+                RelaysMessage::SaveNetwork => {
+                    let mut settings = net::settings();
+                    settings.proxy = if self.global_proxy.trim().is_empty() {
+                        None
+                    } else {
+                        match self.global_proxy.trim().parse() {
+                            Ok(proxy) => Some(proxy),
+                            Err(e) => {
+                                self.error = Some(format!("Invalid proxy: {}", e));
+                                return Command::none();
+                            }
+                        }
+                    };
+                    settings.tor_only = self.tor_only;
+                    return self.apply_network(ctx, settings);
+                }
+                RelaysMessage::EditProxy(url) => {
+                    let current = match net::settings().overrides.get(url.as_str()) {
+                        Some(ProxyOverride::Direct) => String::from("direct"),
+                        Some(ProxyOverride::Socks5(proxy)) => proxy.to_string(),
+                        None => String::new(),
+                    };
+                    self.editing_proxy = Some((url, current));
+                }
+                RelaysMessage::EditedProxyChanged(proxy) => {
+                    if let Some((_, input)) = self.editing_proxy.as_mut() {
+                        *input = proxy;
+                    }
+                }
+                RelaysMessage::CancelEditProxy => self.editing_proxy = None,
+                RelaysMessage::SaveRelayProxy => {
+                    if let Some((url, input)) = self.editing_proxy.take() {
+                        let mut settings = net::settings();
+                        match input.trim() {
+                            "" => {
+                                settings.overrides.remove(url.as_str());
+                            }
+                            "direct" | "none" => {
+                                settings
+                                    .overrides
+                                    .insert(url.to_string(), ProxyOverride::Direct);
+                            }
+                            proxy => match proxy.parse() {
+                                Ok(proxy) => {
+                                    settings
+                                        .overrides
+                                        .insert(url.to_string(), ProxyOverride::Socks5(proxy));
+                                }
+                                Err(e) => {
+                                    self.error = Some(format!("Invalid proxy: {}", e));
+                                    self.editing_proxy = Some((url, input));
+                                    return Command::none();
+                                }
+                            },
+                        }
+                        return self.apply_network(ctx, settings);
+                    }
+                }
+                // Handled by the dashboard, which keeps them across screens
+                RelaysMessage::RelaysRestored(..)
+                | RelaysMessage::Reconnected(_)
+                | RelaysMessage::SetPolicy(..)
+                | RelaysMessage::ProbeRelays
+                | RelaysMessage::AuthChallenge(..)
+                | RelaysMessage::AuthSent(_) => (),
+                RelaysMessage::ToggleRelayInfo(url) => {
+                    if self.expanded.as_ref() == Some(&url) {
+                        self.expanded = None;
+                    } else {
+                        self.expanded = Some(url);
+                    }
+                }
                 RelaysMessage::UpdateRelays => {
                     return Command::perform(
                         async move {
@@ -159,6 +516,7 @@ impl State for RelaysState {
     }
 
     fn view(&self, ctx: &Context) -> Element<Message> {
+        let palette = ctx.palette();
         let heading = Text::new("Relays").size(30);
 
         let on_submit = Message::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(
@@ -199,12 +557,26 @@ impl State for RelaysState {
             relays = relays.push(Text::new("Relays:"));
         }
 
+        if let Some((at, candidates)) = ctx.relay_auth.ignored() {
+            let dt: DateTime<Utc> =
+                DateTime::from_timestamp(at as i64, 0).unwrap_or(DateTime::<Utc>::MIN_UTC);
+            relays = relays.push(
+                Text::new(format!(
+                    "Auth challenge ignored ({}): {} connected relays could have sent it",
+                    dt.format("%Y-%m-%d %H:%M:%S"),
+                    candidates
+                ))
+                .size(14)
+                .style(palette.yellow),
+            );
+        }
+
         for (status, url, proxy) in self.relays.iter() {
             let status = match status {
-                RelayStatus::Initialized => Circle::new(7.0).color(GREY),
-                RelayStatus::Connecting => Circle::new(7.0).color(YELLOW),
-                RelayStatus::Connected => Circle::new(7.0).color(GREEN),
-                RelayStatus::Disconnected => Circle::new(7.0).color(RED),
+                RelayStatus::Initialized => Circle::new(7.0).color(palette.grey),
+                RelayStatus::Connecting => Circle::new(7.0).color(palette.yellow),
+                RelayStatus::Connected => Circle::new(7.0).color(palette.green),
+                RelayStatus::Disconnected => Circle::new(7.0).color(palette.red),
                 RelayStatus::Terminated => continue,
             };
 
@@ -222,22 +594,182 @@ impl State for RelaysState {
                     SettingMessage::Relays(RelaysMessage::RemoveRelay(url.to_string())),
                 )));
 
-            let info = Row::new()
-                .push(status)
+            let document = ctx.relay_info.get(url);
+
+            let mut info = Row::new().push(status);
+            if let Some(name) = document.as_ref().and_then(|d| d.name.clone()) {
+                info = info.push(Text::new(name));
+            }
+            info = info
                 .push(Text::new(url.to_string()))
-                .push(Text::new(format!("Proxy: {}", proxy.is_some())))
+                .push(Text::new(format!("Proxy: {}", format_proxy(proxy))));
+            if let Some(stats) = ctx.relay_health.get(url) {
+                info = info.push(match stats.latency_ms() {
+                    Some(ms) => Text::new(format!("{} ms", ms)),
+                    None => Text::new("Unreachable").style(palette.red),
+                });
+            }
+            if let Some(document) = &document {
+                if document.limitation.payment_required {
+                    info = info.push(Text::new("Payment required").style(palette.yellow));
+                }
+                if document.limitation.auth_required {
+                    info = info.push(Text::new("Auth required").style(palette.yellow));
+                }
+            }
+            match ctx.relay_auth.state(url) {
+                Some(AuthState::Challenged(_)) => {
+                    info = info.push(Text::new("Requests authentication").style(palette.yellow))
+                }
+                Some(AuthState::Pending(_)) => {
+                    info = info.push(Text::new("Authenticating...").style(palette.yellow))
+                }
+                Some(AuthState::Authenticated) => {
+                    info = info.push(Text::new("Authenticated").style(palette.green))
+                }
+                Some(AuthState::Failed(reason)) => {
+                    info =
+                        info.push(Text::new(format!("Auth failed: {}", reason)).style(palette.red))
+                }
+                Some(AuthState::Refused) => {
+                    info = info.push(Text::new("Auth refused").style(palette.grey))
+                }
+                None => (),
+            }
+            let info = info
                 .spacing(20)
                 .align_items(Alignment::Center)
                 .width(Length::Fill);
 
+            let policy = ctx.relay_policies.get(url);
+            let read_url = url.clone();
+            let read_checkbox = Checkbox::new("Read", policy.read).on_toggle(move |read| {
+                RelaysMessage::SetPolicy(read_url.clone(), RelayPolicy { read, ..policy }).into()
+            });
+            let write_url = url.clone();
+            let write_checkbox = Checkbox::new("Write", policy.write).on_toggle(move |write| {
+                RelaysMessage::SetPolicy(write_url.clone(), RelayPolicy { write, ..policy }).into()
+            });
+
+            let auth_url = url.clone();
+            let auth_policy = PickList::new(
+                &AuthPolicy::ALL[..],
+                Some(ctx.config.relays.auth_policy(url)),
+                move |policy| RelaysMessage::SetAuthPolicy(auth_url.clone(), policy).into(),
+            )
+            .padding(10);
+
+            let button_proxy = Button::new(Text::new("Edit proxy"))
+                .padding(10)
+                .style(iced::theme::Button::Secondary)
+                .on_press(RelaysMessage::EditProxy(url.clone()).into());
+
+            let button_info = Button::new(Text::new("Details"))
+                .padding(10)
+                .style(iced::theme::Button::Secondary)
+                .on_press(RelaysMessage::ToggleRelayInfo(url.clone()).into());
+
             relays = relays.push(
                 Row::new()
                     .push(info)
+                    .push(read_checkbox)
+                    .push(write_checkbox)
+                    .push(auth_policy)
+                    .push(button_proxy)
+                    .push(button_info)
                     .push(button)
                     .push(button_remove)
                     .spacing(20)
                     .align_items(Alignment::Center),
             );
+
+            if let Some((_, input)) = self.editing_proxy.as_ref().filter(|(u, _)| u == url) {
+                relays = relays.push(
+                    Row::new()
+                        .push(
+                            TextInput::new(
+                                "Socks5 proxy, \"direct\", or empty for the global proxy",
+                                input,
+                            )
+                            .on_input(|s| RelaysMessage::EditedProxyChanged(s).into())
+                            .on_submit(RelaysMessage::SaveRelayProxy.into())
+                            .padding(10),
+                        )
+                        .push(
+                            Button::new(Text::new("Save"))
+                                .padding(10)
+                                .on_press(RelaysMessage::SaveRelayProxy.into()),
+                        )
+                        .push(
+                            Button::new(Text::new("Cancel"))
+                                .padding(10)
+                                .style(iced::theme::Button::Secondary)
+                                .on_press(RelaysMessage::CancelEditProxy.into()),
+                        )
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .padding([0, 0, 0, 34]),
+                );
+            }
+
+            if let Some(AuthState::Challenged(_)) = ctx.relay_auth.state(url) {
+                relays = relays.push(
+                    Row::new()
+                        .push(
+                            Button::new(Text::new("Authenticate"))
+                                .padding(10)
+                                .on_press(RelaysMessage::Authenticate(url.clone()).into()),
+                        )
+                        .push(
+                            Button::new(Text::new("Dismiss"))
+                                .padding(10)
+                                .style(iced::theme::Button::Secondary)
+                                .on_press(RelaysMessage::DismissAuth(url.clone()).into()),
+                        )
+                        .spacing(10)
+                        .padding([0, 0, 0, 34]),
+                );
+            }
+
+            if self.expanded.as_ref() == Some(url) {
+                relays = relays.push(match &document {
+                    Some(document) => self.view_relay_info(document),
+                    None => Column::new()
+                        .push(Text::new("No information document available").size(14))
+                        .padding([0, 0, 0, 34]),
+                });
+                if let Some(stats) = ctx.relay_health.get(url) {
+                    relays = relays.push(self.view_relay_health(&stats, &palette));
+                }
+            }
+        }
+
+        if !self.relays.is_empty() {
+            let mut publish = Row::new()
+                .push(
+                    Button::new(Text::new("Publish relay list"))
+                        .padding(10)
+                        .on_press(RelaysMessage::PublishRelayList.into()),
+                )
+                .spacing(20)
+                .align_items(Alignment::Center);
+            if let Some(status) = &self.relay_list_status {
+                publish = publish.push(Text::new(status.clone()));
+            } else if let Some(status) = self
+                .relay_list_event
+                .as_ref()
+                .and_then(|id| ctx.publisher.status(id))
+            {
+                publish = publish.push(Text::new(if status.accepted + status.rejected == 0 {
+                    String::from("Relay list pending")
+                } else {
+                    format!(
+                        "Relay list sent to {} of {} relays",
+                        status.accepted, status.total
+                    )
+                }));
+            }
+            relays = relays.push(publish);
         }
 
         let mut saved_relays = Column::new().spacing(10);
@@ -269,7 +801,7 @@ impl State for RelaysState {
 
                 let info = Row::new()
                     .push(Text::new(url.to_string()))
-                    .push(Text::new(format!("Proxy: {}", proxy.is_some())))
+                    .push(Text::new(format!("Proxy: {}", format_proxy(&proxy))))
                     .spacing(20)
                     .align_items(Alignment::Center)
                     .width(Length::Fill);
@@ -285,8 +817,45 @@ impl State for RelaysState {
             }
         }
 
+        let network = Column::new()
+            .push(Text::new("Network"))
+            .push(
+                Row::new()
+                    .push(
+                        TextInput::new(
+                            "Global socks5 proxy (ex. 127.0.0.1:9050 for Tor)",
+                            &self.global_proxy,
+                        )
+                        .on_input(|s| RelaysMessage::GlobalProxyChanged(s).into())
+                        .on_submit(RelaysMessage::SaveNetwork.into())
+                        .padding(10)
+                        .size(20),
+                    )
+                    .push(
+                        Checkbox::new("Tor only", self.tor_only)
+                            .on_toggle(|value| RelaysMessage::TorOnlyToggled(value).into()),
+                    )
+                    .push(
+                        Button::new(Text::new("Save"))
+                            .padding(10)
+                            .on_press(RelaysMessage::SaveNetwork.into()),
+                    )
+                    .spacing(20)
+                    .align_items(Alignment::Center),
+            )
+            .push(
+                Text::new(
+                    "Relays, images and relay information documents go through the global proxy, \
+                     unless a relay has its own. In Tor-only mode the connections without a proxy are refused.",
+                )
+                .size(14),
+            )
+            .spacing(10);
+
         let content = Column::new()
             .push(Row::new().push(heading))
+            .push(network)
+            .push(Rule::horizontal(1))
             .push(Row::new().push(relay_url_input).push(button).spacing(10))
             .push(if self.use_proxy {
                 Column::new()
@@ -319,3 +888,18 @@ impl From<RelaysMessage> for Message {
         Self::Dashboard(DashboardMessage::Setting(SettingMessage::Relays(msg)))
     }
 }
+
+fn format_proxy(proxy: &Option<SocketAddr>) -> String {
+    match proxy {
+        Some(proxy) => proxy.to_string(),
+        None => String::from("direct"),
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MiB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KiB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use iced::widget::{text_input, Button, Column, Row, Text, TextInput};
+use iced::{Command, Element};
+use nostr_sdk::nostr::{Event, EventId, Kind, SubscriptionFilter};
+use nostr_sdk::Client;
+use once_cell::sync::Lazy;
+
+use crate::message::{DashboardMessage, Message};
+use crate::nips::nip19::Entity;
+use crate::nips::raw::{now, RawEvent};
+use crate::stage::dashboard::component::post::Post;
+use crate::stage::dashboard::component::Dashboard;
+use crate::stage::dashboard::context::Context;
+use crate::stage::dashboard::{lookup, State};
+
+const REPLIES_LIMIT: usize = 200;
+
+const TEXT_NOTE_KIND: u64 = 1;
+
+/// Focused when the thread is opened to reply
+pub static COMPOSER_ID: Lazy<text_input::Id> = Lazy::new(text_input::Id::unique);
+
+#[derive(Debug, Clone)]
+pub enum ThreadMessage {
+    /// Root event, if found, and its replies
+    SetThread(Option<Event>, Vec<Event>),
+    /// Note received by the sync
+    Received(Event),
+    ComposerChanged(String),
+    SendReply,
+}
+
+#[derive(Debug, Default)]
+pub struct ThreadState {
+    loaded: bool,
+    /// `nevent`, `note` or `naddr` it was opened from
+    target: Option<Entity>,
+    root: Option<Event>,
+    replies: Vec<Event>,
+    fetched: bool,
+    composer: String,
+    error: Option<String>,
+    opened_at: u64,
+}
+
+impl ThreadState {
+    pub fn new(target: Option<Entity>) -> Self {
+        Self {
+            target,
+            opened_at: now(),
+            ..Self::default()
+        }
+    }
+}
+
+impl State for ThreadState {
+    fn title(&self) -> String {
+        String::from("Nostr - Thread")
+    }
+
+    fn load(&mut self, ctx: &Context) -> Command<Message> {
+        self.loaded = true;
+        let target = match self.target.clone() {
+            Some(target) => target,
+            None => {
+                self.fetched = true;
+                return Command::none();
+            }
+        };
+        let client = ctx.client.clone();
+        Command::perform(
+            async move { get_thread(&client, target).await },
+            |(root, replies)| ThreadMessage::SetThread(root, replies).into(),
+        )
+    }
+
+    fn update(&mut self, ctx: &mut Context, message: Message) -> Command<Message> {
+        if !self.loaded {
+            return self.load(ctx);
+        }
+
+        if let Message::Dashboard(DashboardMessage::Thread(msg)) = message {
+            match msg {
+                ThreadMessage::SetThread(root, mut replies) => {
+                    replies.sort_by_key(|e| e.created_at);
+                    self.root = root;
+                    self.replies = replies;
+                    self.fetched = true;
+                }
+                ThreadMessage::Received(event) => {
+                    let replies_to_root = match &self.root {
+                        Some(root) => RawEvent::from_event(&event)
+                            .map(|raw| raw.tag_values("e").any(|id| id == root.id.to_hex()))
+                            .unwrap_or(false),
+                        None => false,
+                    };
+                    if replies_to_root && !self.replies.iter().any(|e| e.id == event.id) {
+                        self.replies.push(event);
+                        self.replies.sort_by_key(|e| e.created_at);
+                    }
+                }
+                ThreadMessage::ComposerChanged(value) => self.composer = value,
+                ThreadMessage::SendReply => {
+                    let root = match &self.root {
+                        Some(root) if !self.composer.trim().is_empty() => root,
+                        _ => return Command::none(),
+                    };
+                    let keys = ctx.client.keys();
+                    let raw = RawEvent::new(
+                        keys.public_key(),
+                        now(),
+                        TEXT_NOTE_KIND,
+                        reply_tags(root),
+                        self.composer.trim().to_string(),
+                    );
+                    match keys.secret_key().map(|secret_key| raw.sign(&secret_key)) {
+                        Ok(Ok(event)) => {
+                            self.composer.clear();
+                            self.error = None;
+                            self.replies.push(event.clone());
+                            return Command::perform(async move { event }, Message::Publish);
+                        }
+                        Ok(Err(e)) => self.error = Some(format!("Impossible to sign reply: {}", e)),
+                        Err(e) => self.error = Some(format!("Impossible to sign reply: {}", e)),
+                    }
+                }
+            }
+        }
+
+        Command::none()
+    }
+
+    /// Replies to the root posted while the thread is open
+    fn filters(&self, _ctx: &Context) -> Vec<SubscriptionFilter> {
+        match &self.root {
+            Some(root) => vec![SubscriptionFilter::new()
+                .event(root.id)
+                .kind(Kind::from(TEXT_NOTE_KIND))
+                .since(self.opened_at)],
+            None => Vec::new(),
+        }
+    }
+
+    fn posts(&self, _ctx: &Context) -> Vec<Event> {
+        self.root
+            .iter()
+            .chain(self.replies.iter())
+            .cloned()
+            .collect()
+    }
+
+    fn view(&self, ctx: &Context) -> Element<Message> {
+        let mut content = Column::new().push(Text::new("Thread").size(30));
+
+        match &self.root {
+            Some(root) => {
+                content = content.push(Post::new(root.clone()).view(ctx));
+                let composer = TextInput::new("Reply", &self.composer)
+                    .id(COMPOSER_ID.clone())
+                    .on_input(|s| ThreadMessage::ComposerChanged(s).into())
+                    .on_submit(ThreadMessage::SendReply.into())
+                    .padding(10);
+                content = content.push(
+                    Row::new()
+                        .push(composer)
+                        .push(
+                            Button::new("Send")
+                                .padding(10)
+                                .on_press(ThreadMessage::SendReply.into()),
+                        )
+                        .spacing(10),
+                );
+                if let Some(error) = &self.error {
+                    content = content.push(Text::new(error.clone()));
+                }
+                for event in self.replies.iter() {
+                    content =
+                        content.push(Post::new(event.clone()).view(ctx).padding([15, 15, 15, 40]));
+                }
+            }
+            None if self.fetched => content = content.push(Text::new("Event not found")),
+            None => content = content.push(Text::new("Loading...")),
+        }
+
+        Dashboard::new().view(ctx, content.spacing(10).padding(20))
+    }
+}
+
+/// Event `target` points to, and the notes replying to it
+async fn get_thread(client: &Client, target: Entity) -> (Option<Event>, Vec<Event>) {
+    let root = match target {
+        Entity::Event { id, relays, .. } => match EventId::from_hex(&id) {
+            Ok(id) => {
+                let filter = SubscriptionFilter::new().ids(vec![id]);
+                lookup::get_events(client, &relays, vec![filter])
+                    .await
+                    .into_iter()
+                    .next()
+            }
+            Err(e) => {
+                log::error!("Invalid event id {}: {}", id, e);
+                None
+            }
+        },
+        Entity::Address {
+            identifier,
+            author,
+            kind,
+            relays,
+        } => {
+            let filter = SubscriptionFilter::new()
+                .authors(vec![author])
+                .kind(Kind::from(kind as u64));
+            // Newest first, so the latest version of the replaceable event wins
+            lookup::get_events(client, &relays, vec![filter])
+                .await
+                .into_iter()
+                .find(|event| {
+                    RawEvent::from_event(event)
+                        .map(|raw| raw.tag_values("d").next() == Some(identifier.as_str()))
+                        .unwrap_or(false)
+                })
+        }
+        Entity::Profile { .. } => None,
+    };
+
+    let replies = match &root {
+        Some(root) => {
+            let filter = SubscriptionFilter::new()
+                .kind(Kind::from(TEXT_NOTE_KIND))
+                .event(root.id)
+                .limit(REPLIES_LIMIT);
+            lookup::get_events(client, &[], vec![filter]).await
+        }
+        None => Vec::new(),
+    };
+    (root, replies)
+}
+
+/// NIP-10 tags of a reply to `event`: the root of its thread, `event` itself and the
+/// people involved
+fn reply_tags(event: &Event) -> Vec<Vec<String>> {
+    let id = event.id.to_hex();
+    let raw = RawEvent::from_event(event).ok();
+    let root = raw.as_ref().and_then(|raw| {
+        raw.tags
+            .iter()
+            .find(|tag| tag.len() >= 4 && tag[0] == "e" && tag[3] == "root")
+            .map(|tag| tag[1].clone())
+    });
+
+    let mut tags = match root {
+        Some(root) => vec![
+            vec![String::from("e"), root, String::new(), String::from("root")],
+            vec![String::from("e"), id, String::new(), String::from("reply")],
+        ],
+        None => vec![vec![
+            String::from("e"),
+            id,
+            String::new(),
+            String::from("root"),
+        ]],
+    };
+    let mut mentioned = vec![event.pubkey.to_string()];
+    if let Some(raw) = &raw {
+        for pk in raw.tag_values("p") {
+            if !mentioned.iter().any(|m| m == pk) {
+                mentioned.push(pk.to_string());
+            }
+        }
+    }
+    tags.extend(mentioned.into_iter().map(|pk| vec![String::from("p"), pk]));
+    tags
+}
+
+impl From<ThreadState> for Box<dyn State> {
+    fn from(s: ThreadState) -> Box<dyn State> {
+        Box::new(s)
+    }
+}
+
+impl From<ThreadMessage> for Message {
+    fn from(msg: ThreadMessage) -> Self {
+        Self::Dashboard(DashboardMessage::Thread(msg))
+    }
+}
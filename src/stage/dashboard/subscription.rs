@@ -0,0 +1,174 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! REQ subscription of the active screen, on top of the global sync
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, SubscriptionFilter};
+use nostr_sdk::Client;
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+
+use super::relay_policy::RelayPolicies;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+/// Held while a subscription is replaced, so the CLOSE and REQ of successive screens
+/// can't interleave
+static REPLACING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// Id sent to each relay -> subscription, relay. Each relay gets an id of its own, so the
+/// sync can tell which relay sent an event.
+static RELAY_IDS: Lazy<std::sync::Mutex<HashMap<String, (String, Url)>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone)]
+pub struct ScreenSubscription {
+    id: String,
+    filters: Vec<SubscriptionFilter>,
+    /// Serialized filters, to tell when they change
+    key: String,
+}
+
+impl ScreenSubscription {
+    pub fn new(stage: &str, filters: &[SubscriptionFilter]) -> Self {
+        Self {
+            id: format!(
+                "screen-{}-{}",
+                stage,
+                NEXT_ID.fetch_add(1, Ordering::Relaxed)
+            ),
+            filters: filters.to_vec(),
+            key: key(filters),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn filters(&self) -> &[SubscriptionFilter] {
+        &self.filters
+    }
+
+    pub fn has_filters(&self, filters: &[SubscriptionFilter]) -> bool {
+        self.key == key(filters)
+    }
+}
+
+fn key(filters: &[SubscriptionFilter]) -> String {
+    serde_json::to_string(filters).unwrap_or_default()
+}
+
+/// Relay a screen subscription id was sent to
+pub fn relay_of(relay_id: &str) -> Option<Url> {
+    let ids = RELAY_IDS.lock().ok()?;
+    ids.get(relay_id).map(|(_, url)| url.clone())
+}
+
+/// Id of the subscription `id` on `url`
+fn relay_id(id: &str, url: &Url) -> String {
+    let mut ids = match RELAY_IDS.lock() {
+        Ok(ids) => ids,
+        Err(_) => return id.to_string(),
+    };
+    let existing = ids
+        .iter()
+        .find(|(_, (i, u))| i == id && u == url)
+        .map(|(relay_id, _)| relay_id.clone());
+    existing.unwrap_or_else(|| {
+        let relay_id = format!("{}-{}", id, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        ids.insert(relay_id.clone(), (id.to_string(), url.clone()));
+        relay_id
+    })
+}
+
+/// Forget the ids of the subscription `id`, on `url` only if given, and return them
+fn remove_relay_ids(id: &str, url: Option<&Url>) -> Vec<(String, Url)> {
+    let mut ids = match RELAY_IDS.lock() {
+        Ok(ids) => ids,
+        Err(_) => return Vec::new(),
+    };
+    let removed: Vec<(String, Url)> = ids
+        .iter()
+        .filter(|(_, (i, u))| i == id && url.map(|url| u == url).unwrap_or(true))
+        .map(|(relay_id, (_, u))| (relay_id.clone(), u.clone()))
+        .collect();
+    for (relay_id, _) in removed.iter() {
+        ids.remove(relay_id);
+    }
+    removed
+}
+
+/// Close the `previous` subscription, then open the `next` one
+pub async fn replace(
+    client: &Client,
+    policies: &RelayPolicies,
+    previous: Option<String>,
+    next: Option<(String, Vec<SubscriptionFilter>)>,
+) {
+    let _guard = REPLACING.lock().await;
+    if let Some(id) = previous {
+        close(client, id).await;
+    }
+    if let Some((id, filters)) = next {
+        open(client, policies, id, filters).await;
+    }
+}
+
+/// Open (`read`) or close the subscription `id` on `url` only, when its read policy
+/// changes
+pub async fn set_read(
+    client: &Client,
+    url: &Url,
+    id: String,
+    filters: Vec<SubscriptionFilter>,
+    read: bool,
+) {
+    let _guard = REPLACING.lock().await;
+    let msg = if read {
+        ClientMessage::new_req(relay_id(&id, url), filters)
+    } else {
+        match remove_relay_ids(&id, Some(url)).pop() {
+            Some((relay_id, _)) => ClientMessage::new_close(relay_id),
+            None => return,
+        }
+    };
+    if let Some(relay) = client.relays().await.get(url) {
+        if let Err(e) = relay.send_msg(msg).await {
+            log::error!("Impossible to update subscription on {}: {}", url, e);
+        }
+    }
+}
+
+/// Open the subscription on the relays we read from
+async fn open(
+    client: &Client,
+    policies: &RelayPolicies,
+    id: String,
+    filters: Vec<SubscriptionFilter>,
+) {
+    for (url, relay) in client.relays().await.into_iter() {
+        if !policies.get(&url).read {
+            continue;
+        }
+        if let Err(e) = relay
+            .send_msg(ClientMessage::new_req(relay_id(&id, &url), filters.clone()))
+            .await
+        {
+            log::error!("Impossible to subscribe to {}: {}", url, e);
+        }
+    }
+}
+
+async fn close(client: &Client, id: String) {
+    let relays = client.relays().await;
+    for (relay_id, url) in remove_relay_ids(&id, None).into_iter() {
+        if let Some(relay) = relays.get(&url) {
+            if let Err(e) = relay.send_msg(ClientMessage::new_close(relay_id)).await {
+                log::error!("Impossible to close subscription on {}: {}", url, e);
+            }
+        }
+    }
+}
@@ -1,24 +1,94 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::net::SocketAddr;
+use std::time::Duration;
+
 use async_stream::stream;
 use iced::advanced::subscription::{EventStream, Recipe};
 use iced::advanced::Hasher;
 use iced::Subscription;
 use iced_futures::BoxStream;
-use nostr_sdk::nostr::Event;
-use nostr_sdk::{Client, RelayPoolNotifications};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::url::Url;
+use nostr_sdk::nostr::{ClientMessage, Event, EventId, Kind, RelayMessage, SubscriptionFilter};
+use nostr_sdk::{Client, Relay, RelayPoolNotifications, RelayStatus};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
 
-use crate::RUNTIME;
+use crate::checkpoint::Checkpoints;
+use crate::db::Database;
+use crate::nips::nip77::Item;
+use crate::nips::raw::{now, to_hex};
+use crate::notifier::Notifier;
+use crate::outbox::Outbox;
+use crate::relay::negentropy;
+use crate::seen_on::SeenOn;
+use crate::stage::dashboard::relay_health::RelayHealth;
+use crate::stage::dashboard::relay_info::RelayInfoCache;
+use crate::stage::dashboard::relay_policy::RelayPolicies;
+use crate::stage::dashboard::subscription;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+const METADATA_KIND: u64 = 0;
+const TEXT_NOTE_KIND: u64 = 1;
+const CONTACT_LIST_KIND: u64 = 3;
+const REPOST_KIND: u64 = 6;
+const REACTION_KIND: u64 = 7;
+
+const OWN_FILTER: &str = "own";
+const MENTIONS_FILTER: &str = "mentions";
+const CONTACTS_FILTER: &str = "contacts";
+const HOME_FILTER: &str = "home";
+const MISSING_FILTER: &str = "missing";
+
+const NEGENTROPY_NIP: u16 = 77;
+/// How far back the home feed is reconciled with negentropy. Older notes missing
+/// locally aren't downloaded from NIP-77 relays: the REQ that follows only asks for the
+/// new ones, and the store keeps at most `HOME_SCAN_LIMIT` notes to compare anyway.
+const HOME_WINDOW: u64 = 7 * 24 * 60 * 60;
+const HOME_SCAN_LIMIT: usize = 5000;
+const IDS_CHUNK: usize = 500;
+
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    Started,
+    /// Sync couldn't start, next attempt in `retry_in`
+    Failed {
+        error: String,
+        retry_in: Duration,
+    },
+    Event(Event),
+    /// NIP-42 challenge. The SDK doesn't tell which relay sent it, so `relays` are the
+    /// connected relays it may come from.
+    AuthChallenge {
+        challenge: String,
+        relays: Vec<Url>,
+    },
+    /// `OK` of a relay for a published event
+    Ok {
+        event_id: String,
+        accepted: bool,
+        message: String,
+    },
+}
 
 pub struct NostrSync {
     client: Client,
+    db: Database,
+    relay_info: RelayInfoCache,
+    policies: RelayPolicies,
+    relay_health: RelayHealth,
+    notifier: Notifier,
+    seen_on: SeenOn,
+    checkpoints: Checkpoints,
     join: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl Recipe for NostrSync {
-    type Output = Event;
+    type Output = SyncEvent;
 
     fn hash(&self, state: &mut Hasher) {
         use std::hash::Hash;
@@ -29,25 +99,186 @@ impl Recipe for NostrSync {
         let (sender, mut receiver) = mpsc::unbounded_channel();
 
         let client = self.client.clone();
-        RUNTIME.block_on(async move {
-            if let Err(e) = client.sync().await {
-                panic!("Impossible to start sync thread: {}", e);
+        let db = self.db.clone();
+        let relay_info = self.relay_info.clone();
+        let policies = self.policies.clone();
+        let relay_health = self.relay_health.clone();
+        let notifier = self.notifier.clone();
+        let seen_on = self.seen_on.clone();
+        let checkpoints = self.checkpoints.clone();
+        let outbox = Outbox::new(&client);
+        let join = tokio::task::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                match subscribe(&client, &relay_info, &policies, &checkpoints, None).await {
+                    Ok(_) => break,
+                    Err(e) => {
+                        log::error!("Impossible to start sync: {}", e);
+                        let failed = SyncEvent::Failed {
+                            error: e.to_string(),
+                            retry_in: backoff,
+                        };
+                        if sender.send(failed).is_err() {
+                            return;
+                        }
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+            if sender.send(SyncEvent::Started).is_err() {
+                return;
             }
-        });
 
-        let client = self.client.clone();
-        let join = tokio::task::spawn(async move {
+            {
+                let client = client.clone();
+                let policies = policies.clone();
+                let checkpoints = checkpoints.clone();
+                let outbox = outbox.clone();
+                tokio::task::spawn(async move {
+                    if let Err(e) = outbox.update(&client, &policies, &checkpoints).await {
+                        log::error!("Impossible to start outbox: {}", e);
+                    }
+                });
+            }
+
+            let public_key = client.keys().public_key();
+            let mut contacts_at = 0;
             let mut notifications = client.notifications();
-            while let Ok(notification) = notifications.recv().await {
+            let mut outbox_notifications = outbox.client().notifications();
+            loop {
+                let received = tokio::select! {
+                    received = notifications.recv() => received,
+                    received = outbox_notifications.recv() => received,
+                };
+                let notification = match received {
+                    Ok(notification) => notification,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Sync lagged behind, {} notifications skipped", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
                 match notification {
                     RelayPoolNotifications::ReceivedEvent(event) => {
-                        // TODO: Send desktop notification
-                        sender.send(event).ok();
+                        // The screens read the feed, the profiles and the contacts from
+                        // the SDK store. Saved before going on, so a new contact list is
+                        // there for the subscriptions that follow it.
+                        let store = client.clone();
+                        let stored = event.clone();
+                        let saved =
+                            tokio::task::spawn_blocking(move || -> nostr_sdk::Result<()> {
+                                store.store()?.handle_event(&stored)?;
+                                Ok(())
+                            })
+                            .await;
+                        if let Ok(Err(e)) = saved {
+                            log::error!("Impossible to store event: {}", e);
+                        }
+                        // Follow the new contacts as soon as our contact list changes
+                        if event.pubkey == public_key
+                            && event.kind == Kind::from(CONTACT_LIST_KIND)
+                            && event.created_at > contacts_at
+                        {
+                            contacts_at = event.created_at;
+                            let client = client.clone();
+                            let relay_info = relay_info.clone();
+                            let policies = policies.clone();
+                            let checkpoints = checkpoints.clone();
+                            let outbox = outbox.clone();
+                            tokio::task::spawn(async move {
+                                if let Err(e) = subscribe(
+                                    &client,
+                                    &relay_info,
+                                    &policies,
+                                    &checkpoints,
+                                    Some(&[CONTACTS_FILTER, HOME_FILTER]),
+                                )
+                                .await
+                                {
+                                    log::error!("Impossible to subscribe to contacts: {}", e);
+                                }
+                                if let Err(e) =
+                                    outbox.update(&client, &policies, &checkpoints).await
+                                {
+                                    log::error!("Impossible to update outbox: {}", e);
+                                }
+                            });
+                        }
+                        let index = db.clone();
+                        let indexed = event.clone();
+                        tokio::task::spawn_blocking(move || {
+                            if let Err(e) = index.insert(&indexed) {
+                                log::error!("Impossible to index event: {}", e);
+                            }
+                        });
+                        notifier.handle_event(&client, &event);
+                        sender.send(SyncEvent::Event(event)).ok();
+                    }
+                    RelayPoolNotifications::ReceivedMessage(msg) => {
+                        // The SDK hands over the parsed message, so its size is the one of
+                        // the message serialized again
+                        let bytes = msg.as_json().len();
+                        match msg {
+                            RelayMessage::Event {
+                                subscription_id,
+                                event,
+                            } => {
+                                let subscription_id = subscription_id.to_string();
+                                let url = checkpoints
+                                    .relay_of(&subscription_id)
+                                    .or_else(|| subscription::relay_of(&subscription_id));
+                                if let Some(url) = url {
+                                    let duplicate = seen_on.record(&event.id.to_hex(), &url);
+                                    relay_health.count_event(&url, bytes, duplicate);
+                                }
+                                checkpoints.update(&subscription_id, event.created_at);
+                            }
+                            RelayMessage::EndOfStoredEvents(subscription_id) => {
+                                let subscription_id = subscription_id.to_string();
+                                if let Some(url) = checkpoints.relay_of(&subscription_id) {
+                                    relay_health.count_eose(&url, bytes);
+                                    checkpoints.end_of_stored(&subscription_id);
+                                } else if let Some(url) = subscription::relay_of(&subscription_id) {
+                                    relay_health.count_eose(&url, bytes);
+                                } else if let Some(url) = relay_health.end_probe(&subscription_id) {
+                                    close(&client, &url, subscription_id).await;
+                                }
+                                seen_on.save();
+                            }
+                            RelayMessage::Auth { challenge } => {
+                                let mut relays = Vec::new();
+                                for (url, relay) in client.relays().await.into_iter() {
+                                    if let RelayStatus::Connected = relay.status().await {
+                                        relays.push(url);
+                                    }
+                                }
+                                sender
+                                    .send(SyncEvent::AuthChallenge { challenge, relays })
+                                    .ok();
+                            }
+                            RelayMessage::Ok {
+                                event_id,
+                                status,
+                                message,
+                            } => {
+                                sender
+                                    .send(SyncEvent::Ok {
+                                        event_id: event_id.to_hex(),
+                                        accepted: status,
+                                        message,
+                                    })
+                                    .ok();
+                            }
+                            _ => (),
+                        }
                     }
                     RelayPoolNotifications::Shutdown => break,
-                    _ => (),
                 }
             }
+            outbox.shutdown().await;
+            checkpoints.save();
+            seen_on.save();
             log::debug!("Exited from notification thread");
         });
         self.join = Some(join);
@@ -60,8 +291,273 @@ impl Recipe for NostrSync {
     }
 }
 
+/// Public keys of our contact list
+fn contacts(client: &Client) -> nostr_sdk::Result<Vec<XOnlyPublicKey>> {
+    Ok(client
+        .store()?
+        .get_contacts()?
+        .into_iter()
+        .map(|profile| profile.pubkey)
+        .collect())
+}
+
+/// Filters of the global sync, by name
+fn sync_filters(
+    public_key: XOnlyPublicKey,
+    contacts: Vec<XOnlyPublicKey>,
+) -> Vec<(&'static str, SubscriptionFilter)> {
+    let mut filters = vec![
+        (OWN_FILTER, SubscriptionFilter::new().author(public_key)),
+        (
+            MENTIONS_FILTER,
+            SubscriptionFilter::new().pubkey(public_key),
+        ),
+    ];
+    if !contacts.is_empty() {
+        filters.push((
+            CONTACTS_FILTER,
+            SubscriptionFilter::new()
+                .authors(contacts.clone())
+                .kinds(vec![
+                    Kind::from(METADATA_KIND),
+                    Kind::from(CONTACT_LIST_KIND),
+                    Kind::from(REPOST_KIND),
+                    Kind::from(REACTION_KIND),
+                ]),
+        ));
+        filters.push((
+            HOME_FILTER,
+            SubscriptionFilter::new()
+                .authors(contacts)
+                .kind(Kind::from(TEXT_NOTE_KIND)),
+        ));
+    }
+    filters
+}
+
+/// Open the sync subscriptions on every relay we read from, from the checkpoints of the
+/// previous session. `only` restricts them to some of the filters.
+async fn subscribe(
+    client: &Client,
+    relay_info: &RelayInfoCache,
+    policies: &RelayPolicies,
+    checkpoints: &Checkpoints,
+    only: Option<&[&str]>,
+) -> nostr_sdk::Result<()> {
+    let contacts = contacts(client)?;
+    for (url, relay) in client.relays().await.iter() {
+        if !policies.get(url).read {
+            continue;
+        }
+        subscribe_relay(client, relay_info, checkpoints, url, relay, &contacts, only).await?;
+    }
+    Ok(())
+}
+
+/// Open the sync subscriptions on `url` only, when we start reading from it again
+pub async fn resubscribe(
+    client: &Client,
+    relay_info: &RelayInfoCache,
+    checkpoints: &Checkpoints,
+    url: &Url,
+) -> nostr_sdk::Result<()> {
+    let contacts = contacts(client)?;
+    if let Some(relay) = client.relays().await.get(url) {
+        subscribe_relay(client, relay_info, checkpoints, url, relay, &contacts, None).await?;
+    }
+    Ok(())
+}
+
+/// Close the sync subscriptions on `url`, when we stop reading from it
+pub async fn unsubscribe(client: &Client, checkpoints: &Checkpoints, url: &Url) {
+    if let Some(relay) = client.relays().await.get(url) {
+        for id in checkpoints.subscriptions_on(url).into_iter() {
+            if let Err(e) = relay.send_msg(ClientMessage::new_close(id)).await {
+                log::error!("Impossible to close subscription on {}: {}", url, e);
+            }
+        }
+    }
+}
+
+async fn subscribe_relay(
+    client: &Client,
+    relay_info: &RelayInfoCache,
+    checkpoints: &Checkpoints,
+    url: &Url,
+    relay: &Relay,
+    contacts: &[XOnlyPublicKey],
+    only: Option<&[&str]>,
+) -> nostr_sdk::Result<()> {
+    for (name, filter) in sync_filters(client.keys().public_key(), contacts.to_vec()).into_iter() {
+        if only.map(|only| !only.contains(&name)).unwrap_or(false) {
+            continue;
+        }
+        if name == CONTACTS_FILTER || name == HOME_FILTER {
+            checkpoints.set_authors(url, name, contacts);
+        }
+        if name == HOME_FILTER {
+            let client = client.clone();
+            let relay_info = relay_info.clone();
+            let checkpoints = checkpoints.clone();
+            let url = url.clone();
+            let proxy = relay.proxy();
+            tokio::task::spawn(async move {
+                if let Err(e) =
+                    sync_home(&client, &relay_info, &checkpoints, &url, proxy, filter).await
+                {
+                    log::error!("Impossible to sync home feed from {}: {}", url, e);
+                }
+            });
+            continue;
+        }
+        let filter = match checkpoints.since(url, name) {
+            Some(since) => filter.since(since),
+            None => filter,
+        };
+        relay
+            .send_msg(ClientMessage::new_req(
+                checkpoints.subscription_id(url, name),
+                vec![filter],
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Close the subscription `id` on `url`
+async fn close(client: &Client, url: &Url, id: String) {
+    if let Some(relay) = client.relays().await.get(url) {
+        if let Err(e) = relay.send_msg(ClientMessage::new_close(id)).await {
+            log::error!("Impossible to close subscription on {}: {}", url, e);
+        }
+    }
+}
+
+/// Home feed from `url`. Relays supporting NIP-77 are reconciled with negentropy, so
+/// only the notes of the last `HOME_WINDOW` missing locally are downloaded, then followed
+/// from now on. The others get a REQ since the checkpoint.
+async fn sync_home(
+    client: &Client,
+    relay_info: &RelayInfoCache,
+    checkpoints: &Checkpoints,
+    url: &Url,
+    proxy: Option<SocketAddr>,
+    filter: SubscriptionFilter,
+) -> nostr_sdk::Result<()> {
+    let (since, missing) = match reconcile_home(client, relay_info, url, proxy, &filter).await {
+        Some(missing) => (Some(now()), missing),
+        None => (checkpoints.since(url, HOME_FILTER), Vec::new()),
+    };
+
+    let relays = client.relays().await;
+    let relay = match relays.get(url) {
+        Some(relay) => relay,
+        None => return Ok(()),
+    };
+
+    if !missing.is_empty() {
+        let filters: Vec<SubscriptionFilter> = missing
+            .chunks(IDS_CHUNK)
+            .map(|chunk| SubscriptionFilter::new().ids(chunk.to_vec()))
+            .collect();
+        relay
+            .send_msg(ClientMessage::new_req(
+                checkpoints.subscription_id(url, MISSING_FILTER),
+                filters,
+            ))
+            .await?;
+    }
+
+    let filter = match since {
+        Some(since) => filter.since(since),
+        None => filter,
+    };
+    relay
+        .send_msg(ClientMessage::new_req(
+            checkpoints.subscription_id(url, HOME_FILTER),
+            vec![filter],
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Ids of the recent home feed notes `url` has and the store hasn't, or `None` if the
+/// relay can't reconcile
+async fn reconcile_home(
+    client: &Client,
+    relay_info: &RelayInfoCache,
+    url: &Url,
+    proxy: Option<SocketAddr>,
+    filter: &SubscriptionFilter,
+) -> Option<Vec<EventId>> {
+    match relay_info.fetch(url, proxy).await {
+        Some(document) if document.supports(NEGENTROPY_NIP) => (),
+        _ => return None,
+    }
+
+    let since = now().saturating_sub(HOME_WINDOW);
+    let items: Vec<Item> = client
+        .store()
+        .ok()?
+        .get_feed(HOME_SCAN_LIMIT, 1)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|event| event.created_at >= since && event.kind == Kind::from(TEXT_NOTE_KIND))
+        .filter_map(|event| Item::new(event.created_at, &event.id.to_hex()))
+        .collect();
+    let local = items.len();
+    let filter = serde_json::to_value(filter.clone().since(since)).ok()?;
+
+    match negentropy::reconcile(url, proxy, filter, items).await {
+        Ok(result) => {
+            log::debug!(
+                "Reconciled {} local notes with {} in {} rounds ({} bytes): {} missing",
+                local,
+                url,
+                result.rounds,
+                result.bytes,
+                result.need.len()
+            );
+            Some(
+                result
+                    .need
+                    .iter()
+                    .filter_map(|id| EventId::from_hex(to_hex(id)).ok())
+                    .collect(),
+            )
+        }
+        Err(e) => {
+            log::debug!(
+                "Negentropy unavailable on {}, falling back to REQ: {}",
+                url,
+                e
+            );
+            None
+        }
+    }
+}
+
 impl NostrSync {
-    pub fn subscription(client: Client) -> Subscription<Event> {
-        Subscription::from_recipe(Self { client, join: None })
+    pub fn subscription(
+        client: Client,
+        db: Database,
+        relay_info: RelayInfoCache,
+        policies: RelayPolicies,
+        relay_health: RelayHealth,
+        notifier: Notifier,
+        seen_on: SeenOn,
+        checkpoints: Checkpoints,
+    ) -> Subscription<SyncEvent> {
+        Subscription::from_recipe(Self {
+            client,
+            db,
+            relay_info,
+            policies,
+            relay_health,
+            notifier,
+            seen_on,
+            checkpoints,
+            join: None,
+        })
     }
 }
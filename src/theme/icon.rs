@@ -2,6 +2,8 @@
 // Distributed under the MIT software license
 
 // pub const BITCOIN: char = '\u{F635}';
+pub const ARROW_LEFT: char = '\u{F12F}';
+pub const ARROW_RIGHT: char = '\u{F138}';
 pub const HOME: char = '\u{F425}';
 pub const EXPLORE: char = '\u{F3EE}';
 pub const CHAT: char = '\u{F268}';
@@ -11,6 +13,6 @@ pub const PERSON: char = '\u{F4DA}';
 pub const SETTING: char = '\u{F3E5}';
 pub const TRASH: char = '\u{F78B}';
 pub const HEART: char = '\u{F417}';
-// pub const LIGHTNING: char = '\u{F46D}';
+pub const LIGHTNING: char = '\u{F46D}';
 pub const LOCK: char = '\u{F47B}';
 pub const REPEAT: char = '\u{F813}';
@@ -1,12 +1,116 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
 pub mod color;
 pub mod font;
 pub mod icon;
+pub mod palette;
+
+pub use self::palette::Palette;
 
-/* #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Theme {
+/// Whether the desktop prefers a dark theme, as last detected
+static SYSTEM_DARK: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(detect_dark()));
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Theme {
     Light,
+    #[default]
     Dark,
-} */
+    /// Light or dark, following the desktop
+    System,
+    /// Palette of the themes file
+    Custom(String),
+}
+
+impl Theme {
+    /// Built-in themes, then the palettes of the themes file
+    pub fn available() -> Vec<Self> {
+        let mut themes = vec![Self::Light, Self::Dark, Self::System];
+        themes.extend(palette::names().into_iter().map(Self::Custom));
+        themes
+    }
+
+    /// Theme to draw with. Custom themes missing from the themes file fall back to dark.
+    pub fn resolve(&self) -> iced::Theme {
+        match self {
+            Self::Light => iced::Theme::Light,
+            Self::Dark => iced::Theme::Dark,
+            Self::System => {
+                if SYSTEM_DARK.load(Ordering::SeqCst) {
+                    iced::Theme::Dark
+                } else {
+                    iced::Theme::Light
+                }
+            }
+            Self::Custom(name) => match palette::get(name) {
+                Some(palette) => iced::Theme::custom(name.clone(), palette.iced()),
+                None => iced::Theme::Dark,
+            },
+        }
+    }
+
+    /// Colors of the theme, with the same fallbacks as `resolve`
+    pub fn palette(&self) -> Palette {
+        match self {
+            Self::Light => Palette::light(),
+            Self::Dark => Palette::dark(),
+            Self::System => {
+                if SYSTEM_DARK.load(Ordering::SeqCst) {
+                    Palette::dark()
+                } else {
+                    Palette::light()
+                }
+            }
+            Self::Custom(name) => palette::get(name).unwrap_or_else(Palette::dark),
+        }
+    }
+}
+
+impl From<String> for Theme {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "light" => Self::Light,
+            "dark" => Self::Dark,
+            "system" => Self::System,
+            _ => Self::Custom(name),
+        }
+    }
+}
+
+impl From<Theme> for String {
+    fn from(theme: Theme) -> Self {
+        match theme {
+            Theme::Light => String::from("light"),
+            Theme::Dark => String::from("dark"),
+            Theme::System => String::from("system"),
+            Theme::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Light => write!(f, "Light"),
+            Self::Dark => write!(f, "Dark"),
+            Self::System => write!(f, "System"),
+            Self::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Detect the desktop preference again, e.g. when the window gets the focus back
+pub fn refresh_system() {
+    SYSTEM_DARK.store(detect_dark(), Ordering::SeqCst);
+}
+
+fn detect_dark() -> bool {
+    !matches!(dark_light::detect(), dark_light::Mode::Light)
+}
@@ -0,0 +1,192 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! User palettes, loaded from `themes.toml`:
+//!
+//! ```toml
+//! [solarized]
+//! background = "#002b36"
+//! text = "#839496"
+//! primary = "#268bd2"
+//! success = "#859900"
+//! danger = "#dc322f"
+//! # Optional, for the states shown in the views
+//! grey = "#586e75"
+//! red = "#dc322f"
+//! green = "#859900"
+//! yellow = "#b58900"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use iced::Color;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use super::color::{GREEN, GREY, RED, YELLOW};
+use crate::util::dir;
+
+static PALETTES: Lazy<BTreeMap<String, Palette>> = Lazy::new(load);
+
+/// Colors of a theme: the ones of iced, and the roles of the states shown in the views
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    pub text: Color,
+    pub primary: Color,
+    pub success: Color,
+    pub danger: Color,
+    /// Secondary text and inactive states
+    pub grey: Color,
+    /// Errors, failures and unread counts
+    pub red: Color,
+    /// Connected and accepted
+    pub green: Color,
+    /// Pending, or waiting for the user
+    pub yellow: Color,
+}
+
+impl Palette {
+    pub fn dark() -> Self {
+        Self::with_roles(iced::theme::Palette::DARK, GREY, RED, GREEN, YELLOW)
+    }
+
+    pub fn light() -> Self {
+        Self::with_roles(
+            iced::theme::Palette::LIGHT,
+            Color::from_rgb8(110, 110, 110),
+            Color::from_rgb8(200, 0, 0),
+            Color::from_rgb8(0, 140, 0),
+            Color::from_rgb8(180, 130, 0),
+        )
+    }
+
+    fn with_roles(
+        palette: iced::theme::Palette,
+        grey: Color,
+        red: Color,
+        green: Color,
+        yellow: Color,
+    ) -> Self {
+        Self {
+            background: palette.background,
+            text: palette.text,
+            primary: palette.primary,
+            success: palette.success,
+            danger: palette.danger,
+            grey,
+            red,
+            green,
+            yellow,
+        }
+    }
+
+    /// Palette iced draws the widgets with
+    pub fn iced(&self) -> iced::theme::Palette {
+        iced::theme::Palette {
+            background: self.background,
+            text: self.text,
+            primary: self.primary,
+            success: self.success,
+            danger: self.danger,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PaletteFile {
+    background: String,
+    text: String,
+    primary: String,
+    success: String,
+    danger: String,
+    grey: Option<String>,
+    red: Option<String>,
+    green: Option<String>,
+    yellow: Option<String>,
+}
+
+impl PaletteFile {
+    /// Missing roles are derived from the other colors
+    fn palette(&self) -> Option<Palette> {
+        let role = |hex: &Option<String>, default: Color| match hex {
+            Some(hex) => parse_hex(hex),
+            None => Some(default),
+        };
+        let text = parse_hex(&self.text)?;
+        let success = parse_hex(&self.success)?;
+        let danger = parse_hex(&self.danger)?;
+        Some(Palette {
+            background: parse_hex(&self.background)?,
+            text,
+            primary: parse_hex(&self.primary)?,
+            success,
+            danger,
+            grey: role(&self.grey, Color { a: 0.6, ..text })?,
+            red: role(&self.red, danger)?,
+            green: role(&self.green, success)?,
+            yellow: role(&self.yellow, YELLOW)?,
+        })
+    }
+}
+
+pub fn names() -> Vec<String> {
+    PALETTES.keys().cloned().collect()
+}
+
+pub fn get(name: &str) -> Option<Palette> {
+    PALETTES.get(name).copied()
+}
+
+fn load() -> BTreeMap<String, Palette> {
+    let path = match path() {
+        Some(path) => path,
+        None => return BTreeMap::new(),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return BTreeMap::new(),
+    };
+    let files: BTreeMap<String, PaletteFile> = match toml::from_str(&content) {
+        Ok(files) => files,
+        Err(e) => {
+            log::error!("Impossible to parse {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    files
+        .into_iter()
+        .filter_map(|(name, file)| match file.palette() {
+            Some(palette) => Some((name, palette)),
+            None => {
+                log::error!("Invalid colors in palette {}", name);
+                None
+            }
+        })
+        .collect()
+}
+
+/// `#rrggbb` or `#rrggbbaa`
+fn parse_hex(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = match hex.len() {
+        8 => channel(6)? as f32 / 255.0,
+        _ => 1.0,
+    };
+    Some(Color::from_rgba8(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        alpha,
+    ))
+}
+
+fn path() -> Option<PathBuf> {
+    dir::config_dir().ok().map(|dir| dir.join("themes.toml"))
+}
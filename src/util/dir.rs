@@ -1,20 +1,203 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use std::fs;
+//! Where files are kept
+//!
+//! In order of precedence: the `--data-dir` flag, portable mode (a `portable` file
+//! next to the binary, everything kept in `data/` beside it), then the XDG base
+//! directories. The files of an existing `~/.nostr-desktop` are moved to the XDG
+//! directories.
+
 use std::path::{Path, PathBuf};
+use std::{fs, io};
 
 use nostr_sdk::Result;
+use once_cell::sync::OnceCell;
+
+const APP_NAME: &str = "nostr-desktop";
+const LEGACY_DIR: &str = ".nostr-desktop";
+const PORTABLE_MARKER: &str = "portable";
+
+/// Files of the legacy directory that belong in the config directory: the settings, and
+/// the files they were kept in before `settings.toml`
+const CONFIG_FILES: [&str; 6] = [
+    "settings.toml",
+    "themes.toml",
+    "proxy.json",
+    "desktop-notifications.json",
+    "relay-auth.json",
+    "explore.json",
+];
+/// Relay policies of each account, `relays-<public key>.json`, also config files
+const ACCOUNT_CONFIG_PREFIX: &str = "relays-";
+/// Files of the legacy directory that belong in the cache directory
+const CACHE_FILES: [&str; 1] = ["relay-info.json"];
+
+static DIRS: OnceCell<Dirs> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+struct Dirs {
+    data: PathBuf,
+    config: PathBuf,
+    cache: PathBuf,
+}
+
+impl Dirs {
+    /// Everything in a single directory
+    fn single(path: PathBuf) -> Self {
+        Self {
+            data: path.clone(),
+            config: path.clone(),
+            cache: path,
+        }
+    }
+
+    /// Directory a file of the legacy directory belongs in
+    fn of(&self, name: &str) -> &Path {
+        if CONFIG_FILES.contains(&name)
+            || (name.starts_with(ACCOUNT_CONFIG_PREFIX) && name.ends_with(".json"))
+        {
+            &self.config
+        } else if CACHE_FILES.contains(&name) {
+            &self.cache
+        } else {
+            &self.data
+        }
+    }
+}
 
-pub fn home() -> PathBuf {
-    match dirs::home_dir() {
-        Some(path) => path,
-        None => Path::new("./").to_path_buf(),
+/// Resolve the directories, migrating the legacy one if needed. Must be called
+/// before anything reads or writes a file.
+pub fn init(data_dir: Option<PathBuf>) -> Result<()> {
+    let dirs = resolve(data_dir)?;
+    for path in [&dirs.data, &dirs.config, &dirs.cache] {
+        fs::create_dir_all(path)?;
     }
+    log::info!("Data directory: {}", dirs.data.display());
+    let _ = DIRS.set(dirs);
+    Ok(())
 }
 
+/// Data directory: the store, queues and per-account state
 pub fn default_dir() -> Result<PathBuf> {
-    let path: PathBuf = home().join(".nostr-desktop");
-    fs::create_dir_all(path.as_path())?;
-    Ok(path)
+    Ok(resolved()?.data.clone())
+}
+
+/// Config directory: settings edited by the user
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(resolved()?.config.clone())
+}
+
+/// Cache directory: files that can be fetched again
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(resolved()?.cache.clone())
+}
+
+fn resolved() -> Result<&'static Dirs> {
+    if DIRS.get().is_none() {
+        init(None)?;
+    }
+    match DIRS.get() {
+        Some(dirs) => Ok(dirs),
+        None => Err(not_found("data directory").into()),
+    }
+}
+
+fn resolve(data_dir: Option<PathBuf>) -> Result<Dirs> {
+    if let Some(path) = data_dir {
+        return Ok(Dirs::single(path));
+    }
+
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        if exe_dir.join(PORTABLE_MARKER).exists() {
+            return Ok(Dirs::single(exe_dir.join("data")));
+        }
+    }
+
+    let xdg = Dirs {
+        data: dirs::data_dir()
+            .ok_or_else(|| not_found("XDG data directory"))?
+            .join(APP_NAME),
+        config: dirs::config_dir()
+            .ok_or_else(|| not_found("XDG config directory"))?
+            .join(APP_NAME),
+        cache: dirs::cache_dir()
+            .ok_or_else(|| not_found("XDG cache directory"))?
+            .join(APP_NAME),
+    };
+    if let Some(home) = dirs::home_dir() {
+        let legacy = home.join(LEGACY_DIR);
+        if legacy.is_dir() {
+            migrate(&legacy, &xdg)?;
+        }
+    }
+    Ok(xdg)
+}
+
+/// Move the files of `~/.nostr-desktop` to the XDG directories one by one, so a migration
+/// cut short goes on at the next start. Files the XDG directories already have are left
+/// in the legacy directory.
+fn migrate(legacy: &Path, dirs: &Dirs) -> Result<()> {
+    log::info!("Moving {} to {}", legacy.display(), dirs.data.display());
+    let mut left = 0;
+    for entry in fs::read_dir(legacy)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let to = dirs.of(&name.to_string_lossy()).join(&name);
+        left += move_entry(&entry.path(), &to)?;
+    }
+    if left == 0 {
+        fs::remove_dir(legacy)?;
+    } else {
+        log::warn!(
+            "{} files of {} are already in the XDG directories, it can be removed",
+            left,
+            legacy.display()
+        );
+    }
+    Ok(())
+}
+
+/// Move a file or a directory, returning how many files were left because `to` already
+/// has them
+fn move_entry(from: &Path, to: &Path) -> io::Result<usize> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        let mut left = 0;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            left += move_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        if left == 0 {
+            fs::remove_dir(from)?;
+        }
+        return Ok(left);
+    }
+
+    if to.exists() {
+        return Ok(1);
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_err() {
+        // Different file systems: copy under another name first, so a copy cut short
+        // isn't taken for the file
+        let mut part = to.as_os_str().to_owned();
+        part.push(".part");
+        fs::copy(from, &part)?;
+        fs::rename(&part, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(0)
+}
+
+fn not_found(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("impossible to find the {}", what),
+    )
 }
@@ -0,0 +1,82 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! State kept in JSON files of the app directories
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Minimum delay between two writes of a [`Debounce`]d file
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Read `path`, `None` if it doesn't exist or can't be parsed
+pub fn load<T>(path: Option<PathBuf>) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    let path = path?;
+    let json = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&json) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::error!("Impossible to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Write `value` to `path`. `what` names the state in the logs.
+pub fn save<T>(path: Option<PathBuf>, value: &T, what: &str)
+where
+    T: Serialize,
+{
+    let path = match path {
+        Some(path) => path,
+        None => return,
+    };
+    match serde_json::to_string(value) {
+        Ok(json) => {
+            if let Err(e) = fs::write(path, json) {
+                log::error!("Impossible to save {}: {}", what, e);
+            }
+        }
+        Err(e) => log::error!("Impossible to serialize {}: {}", what, e),
+    }
+}
+
+/// Tracks the changes of state updated often, so it's written at most every
+/// [`SAVE_INTERVAL`]
+#[derive(Debug, Default)]
+pub struct Debounce {
+    dirty: bool,
+    saved_at: Option<Instant>,
+}
+
+impl Debounce {
+    pub fn touch(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether there are changes and the last write is old enough
+    pub fn is_due(&self) -> bool {
+        self.dirty
+            && self
+                .saved_at
+                .map(|t| t.elapsed() > SAVE_INTERVAL)
+                .unwrap_or(true)
+    }
+
+    /// Whether there are changes to write, marking them written
+    pub fn take(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.dirty = false;
+        self.saved_at = Some(Instant::now());
+        true
+    }
+}
@@ -2,3 +2,4 @@
 // Distributed under the MIT software license
 
 pub mod dir;
+pub mod json_store;